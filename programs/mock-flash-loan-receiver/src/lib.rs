@@ -0,0 +1,65 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+declare_id!("2eJX6dqxT3CbFmRzQL6TdDhdqS4Span7WjqiVHJUAsWT");
+
+/// Seed of the receiver authority, owner of the receiver token accounts
+pub const RECEIVER_AUTHORITY_SEED: &[u8] = b"receiver_authority";
+
+/// Flash loan receiver for local testing of the callback flash loan
+///
+/// Repays the loan plus fee out of its token account owned by the receiver authority, or as
+/// much of it as the account holds, so that it must be funded with the fee beforehand.
+#[program]
+pub mod mock_flash_loan_receiver {
+    use super::*;
+
+    pub fn receive_flash_loan(ctx: Context<ReceiveFlashLoan>, amount: u64, fee: u64) -> Result<()> {
+        msg!("Instruction: receive_flash_loan");
+        let repay_amount = amount
+            .checked_add(fee)
+            .ok_or(ErrorCode::MathOverflow)?
+            .min(ctx.accounts.destination_liquidity.amount);
+
+        let bump = [ctx.bumps.receiver_authority];
+        let signer_seeds: &[&[u8]] = &[RECEIVER_AUTHORITY_SEED, &bump];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.destination_liquidity.to_account_info(),
+                    to: ctx.accounts.reserve_liquidity_supply.to_account_info(),
+                    authority: ctx.accounts.receiver_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            repay_amount,
+        )
+    }
+}
+
+/// Receive flash loan context, following the flash loan receiver interface of the lending
+/// program: the loan, the reserve supply and the token program first, then the receiver's own
+/// accounts
+#[derive(Accounts)]
+pub struct ReceiveFlashLoan<'info> {
+    /// Receiver token account holding the loan
+    #[account(mut, token::authority = receiver_authority)]
+    pub destination_liquidity: Account<'info, TokenAccount>,
+
+    /// Reserve liquidity supply the loan and fee are repaid into
+    #[account(mut, constraint = reserve_liquidity_supply.mint == destination_liquidity.mint)]
+    pub reserve_liquidity_supply: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: PDA signing for the receiver token accounts
+    #[account(seeds = [RECEIVER_AUTHORITY_SEED], bump)]
+    pub receiver_authority: UncheckedAccount<'info>,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Math operation overflow")]
+    MathOverflow,
+}
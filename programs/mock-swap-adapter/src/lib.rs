@@ -0,0 +1,88 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+
+declare_id!("3dfRx2Tso6NJokGKqpg5rEnbefSSEB6VQCeRhYZph21X");
+
+/// Seed of the pool authority, owner of the pool token accounts
+pub const POOL_AUTHORITY_SEED: &[u8] = b"pool_authority";
+
+/// Swap adapter for local testing of the leveraged position instructions
+///
+/// Swaps at a fixed 1:1 rate of raw token amounts against pool token accounts owned by the pool
+/// authority, which must be funded with the output token beforehand.
+#[program]
+pub mod mock_swap_adapter {
+    use super::*;
+
+    pub fn swap(ctx: Context<Swap>, amount_in: u64, min_amount_out: u64) -> Result<()> {
+        msg!("Instruction: swap");
+        let amount_out = amount_in;
+        if amount_out < min_amount_out {
+            msg!("Swap would return {} below the minimum {}", amount_out, min_amount_out);
+            return Err(ErrorCode::SlippageExceeded.into());
+        }
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.source.to_account_info(),
+                    to: ctx.accounts.pool_source.to_account_info(),
+                    authority: ctx.accounts.authority.to_account_info(),
+                },
+            ),
+            amount_in,
+        )?;
+
+        let bump = [ctx.bumps.pool_authority];
+        let signer_seeds: &[&[u8]] = &[POOL_AUTHORITY_SEED, &bump];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.pool_destination.to_account_info(),
+                    to: ctx.accounts.destination.to_account_info(),
+                    authority: ctx.accounts.pool_authority.to_account_info(),
+                },
+                &[signer_seeds],
+            ),
+            amount_out,
+        )
+    }
+}
+
+/// Swap context, following the swap adapter interface of the lending program: source,
+/// destination and authority first, then the adapter's own accounts
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    /// User token account swapped from
+    #[account(mut)]
+    pub source: Account<'info, TokenAccount>,
+
+    /// User token account swapped into
+    #[account(mut, constraint = destination.mint == pool_destination.mint)]
+    pub destination: Account<'info, TokenAccount>,
+
+    /// Authority of the source token account
+    pub authority: Signer<'info>,
+
+    /// Pool token account of the source mint, receives the amount in
+    #[account(mut, constraint = pool_source.mint == source.mint)]
+    pub pool_source: Account<'info, TokenAccount>,
+
+    /// Pool token account of the destination mint, pays the amount out
+    #[account(mut, token::authority = pool_authority)]
+    pub pool_destination: Account<'info, TokenAccount>,
+
+    /// CHECK: PDA signing for the pool token accounts
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("Swap amount out is below the minimum amount out")]
+    SlippageExceeded,
+}
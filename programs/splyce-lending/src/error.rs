@@ -209,6 +209,30 @@ pub enum LendingError {
     /// Borrow Attribution Limit Not Exceeded
     #[error("Borrow Attribution Limit Not Exceeded")]
     BorrowAttributionLimitNotExceeded,
+    /// Reserve is not in the obligation's e-mode category
+    #[error("Reserve is not in the obligation's e-mode category")]
+    EModeCategoryViolation,
+    /// Obligation delegate limit exceeded
+    #[error("Obligation delegate limit exceeded")]
+    ObligationDelegateLimit,
+    /// Obligation order trigger is not met
+    #[error("Obligation order trigger is not met")]
+    ObligationOrderNotTriggered,
+    /// Obligation order would increase the obligation's loan to value
+    #[error("Obligation order would increase the obligation's loan to value")]
+    ObligationOrderIncreasesLtv,
+    /// Obligation deposits are not empty
+    #[error("Obligation deposits are not empty")]
+    ObligationDepositsNotEmpty,
+    /// Obligation borrows are not empty
+    #[error("Obligation borrows are not empty")]
+    ObligationBorrowsNotEmpty,
+    /// Swap adapter returned less than the minimum amount out
+    #[error("Swap adapter returned less than the minimum amount out")]
+    SwapAmountTooSmall,
+    /// Reserve is locked by an instruction in progress
+    #[error("Reserve is locked by an instruction in progress")]
+    ReserveLocked,
 }
 
 impl From<LendingError> for ProgramError {
@@ -0,0 +1,29 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Accept obligation transfer context
+#[derive(Accounts)]
+pub struct AcceptObligationTransfer<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub new_owner: Signer<'info>,
+}
+
+/// Take ownership of an obligation proposed by its owner. Deposits and borrows are left
+/// untouched, delegates granted by the previous owner are revoked.
+pub fn handle_accept_obligation_transfer(ctx: Context<AcceptObligationTransfer>) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let new_owner = &ctx.accounts.new_owner;
+
+    if obligation.pending_owner == Pubkey::default() || obligation.pending_owner != new_owner.key() {
+        msg!("New owner provided is not the pending owner of the obligation");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+
+    obligation.owner = new_owner.key();
+    obligation.pending_owner = Pubkey::default();
+    obligation.clear_delegates();
+
+    Ok(())
+}
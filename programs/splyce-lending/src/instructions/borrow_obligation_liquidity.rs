@@ -0,0 +1,295 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, spl_token, Mint, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::update_borrow_attribution_values_after,
+    math::{Decimal, SaturatingSub, TryAdd},
+    state::*,
+    utils::{create_temporary_wsol, is_native_mint, unwrap_lamports, TEMPORARY_WSOL_SEED},
+};
+
+/// Borrow obligation liquidity context
+///
+/// Exactly one of `destination_liquidity` and `temporary_wsol` is expected. The temporary wrapped
+/// SOL account borrows from the native-mint reserve straight to the owner's system wallet, it is
+/// created and closed within the instruction.
+///
+/// Remaining accounts: the obligation's deposit reserves other than the borrow reserve
+/// (writable), in order, to update their borrow attribution.
+#[derive(Accounts)]
+pub struct BorrowObligationLiquidity<'info> {
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub source_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// User liquidity token account
+    #[account(mut)]
+    pub destination_liquidity: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub borrow_reserve: AccountLoader<'info, Reserve>,
+
+    /// Reserve liquidity fee receiver
+    #[account(mut)]
+    pub borrow_reserve_liquidity_fee_receiver: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Obligation owner, or a delegate with the borrow permission. Pays for the temporary wrapped
+    /// SOL account.
+    #[account(mut)]
+    pub obligation_owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Host fee receiver, receives the host share of the borrow fee
+    #[account(mut)]
+    pub host_fee_receiver: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// CHECK: temporary wrapped SOL account of the owner, created and closed by the instruction
+    #[account(mut, seeds = [TEMPORARY_WSOL_SEED, obligation_owner.key().as_ref()], bump)]
+    pub temporary_wsol: Option<UncheckedAccount<'info>>,
+
+    #[account(address = spl_token::native_mint::ID)]
+    pub native_mint: Option<Box<Account<'info, Mint>>>,
+
+    pub system_program: Option<Program<'info, System>>,
+}
+
+/// Borrow `liquidity_amount` of liquidity, or as much as the obligation's health and the
+/// reserve and market limits allow with `u64::MAX`. The borrow fee is added on top of the
+/// amount received.
+pub fn handle_borrow_obligation_liquidity<'info>(
+    ctx: Context<'_, '_, 'info, 'info, BorrowObligationLiquidity<'info>>,
+    liquidity_amount: u64,
+) -> Result<()> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let source_liquidity = &ctx.accounts.source_liquidity;
+    let borrow_reserve_key = ctx.accounts.borrow_reserve.key();
+    let mut borrow_reserve = ctx.accounts.borrow_reserve.load_mut()?;
+    let fee_receiver = &ctx.accounts.borrow_reserve_liquidity_fee_receiver;
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &mut ctx.accounts.lending_market;
+    let obligation_owner = &ctx.accounts.obligation_owner;
+    let clock = Clock::get()?;
+
+    if borrow_reserve.lending_market != lending_market.key() {
+        msg!("Borrow reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if borrow_reserve.liquidity.supply_pubkey != source_liquidity.key() {
+        msg!("Borrow reserve liquidity supply must be used as the source liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    let destination_liquidity =
+        match (&ctx.accounts.destination_liquidity, &ctx.accounts.temporary_wsol) {
+            (Some(destination_liquidity), None) => {
+                if borrow_reserve.liquidity.supply_pubkey == destination_liquidity.key() {
+                    msg!("Borrow reserve liquidity supply cannot be used as the destination liquidity provided");
+                    return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+                }
+                destination_liquidity.to_account_info()
+            }
+            (None, Some(temporary_wsol)) => {
+                if !is_native_mint(&borrow_reserve.liquidity.mint_pubkey) {
+                    msg!("Native SOL can only be borrowed from the native mint reserve");
+                    return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+                }
+                temporary_wsol.to_account_info()
+            }
+            _ => {
+                msg!("Exactly one of destination liquidity and temporary wrapped SOL must be provided");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+        };
+    if borrow_reserve.config.fee_receiver != fee_receiver.key() {
+        msg!("Borrow reserve liquidity fee receiver does not match the borrow reserve liquidity fee receiver provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if borrow_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Borrow reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+    obligation.validate_authority(
+        &obligation_owner.key(),
+        DELEGATE_PERMISSION_BORROW,
+        clock.slot,
+    )?;
+    if obligation.deposits().is_empty() {
+        msg!("Obligation has no deposits to borrow against");
+        return Err(ProgramError::from(LendingError::ObligationDepositsEmpty).into());
+    }
+    if obligation.deposited_value == Decimal::zero() {
+        msg!("Obligation deposits have zero value");
+        return Err(ProgramError::from(LendingError::ObligationDepositsZero).into());
+    }
+
+    // an obligation borrowing an isolated asset holds that single borrow only
+    let existing_borrow_type = if obligation.borrowing_isolated_asset != 0 {
+        ReserveType::Isolated
+    } else {
+        ReserveType::Regular
+    };
+    let existing_borrows: Vec<(Pubkey, ReserveType)> = obligation
+        .borrows()
+        .iter()
+        .map(|liquidity| (liquidity.borrow_reserve, existing_borrow_type))
+        .collect();
+    let borrow_reserve_type = borrow_reserve.config.reserve_type()?;
+    borrow_reserve_type.validate_borrow(
+        &borrow_reserve_key,
+        &existing_borrows,
+        obligation.has_isolated_collateral != 0,
+    )?;
+    borrow_reserve.validate_emode_category(obligation.emode_category)?;
+
+    let remaining_borrow_value = obligation.remaining_borrow_value();
+    if remaining_borrow_value == Decimal::zero() {
+        msg!("Remaining borrow value is zero");
+        return Err(ProgramError::from(LendingError::BorrowTooLarge).into());
+    }
+
+    let remaining_reserve_borrow = Decimal::from(borrow_reserve.config.borrow_limit)
+        .saturating_sub(borrow_reserve.liquidity.borrowed_amount_wads);
+    if remaining_reserve_borrow == Decimal::zero() {
+        msg!("Borrow reserve has reached its borrow limit");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let (max_borrow_value, remaining_reserve_borrow) = if liquidity_amount == u64::MAX {
+        (
+            remaining_borrow_value.min(lending_market.rate_limiter.remaining_outflow(clock.slot)?),
+            remaining_reserve_borrow.min(borrow_reserve.rate_limiter.remaining_outflow(clock.slot)?),
+        )
+    } else {
+        (remaining_borrow_value, remaining_reserve_borrow)
+    };
+
+    let CalculateBorrowResult {
+        borrow_amount,
+        receive_amount,
+        borrow_fee,
+        host_fee,
+    } = borrow_reserve.calculate_borrow(liquidity_amount, max_borrow_value, remaining_reserve_borrow)?;
+
+    if receive_amount == 0 {
+        msg!("Borrow amount is too small to receive liquidity after fees");
+        return Err(ProgramError::from(LendingError::BorrowTooSmall).into());
+    }
+
+    let borrow_value = borrow_reserve.market_value(borrow_amount)?;
+    if let Err(err) = lending_market.rate_limiter.update(clock.slot, borrow_value) {
+        msg!("Market outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+    if let Err(err) = borrow_reserve.rate_limiter.update(clock.slot, borrow_amount) {
+        msg!("Reserve outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+
+    borrow_reserve.liquidity.borrow(borrow_amount)?;
+    borrow_reserve.last_update.mark_stale();
+
+    let cumulative_borrow_rate_wads = borrow_reserve.liquidity.cumulative_borrow_rate_wads;
+    let liquidity = obligation
+        .find_or_add_liquidity_to_borrows(borrow_reserve_key, cumulative_borrow_rate_wads)?;
+    liquidity.borrow(borrow_amount)?;
+    liquidity.market_value = liquidity.market_value.try_add(borrow_value)?;
+
+    obligation.unweighted_borrowed_value =
+        obligation.unweighted_borrowed_value.try_add(borrow_value)?;
+    obligation.borrowed_value = obligation
+        .borrowed_value
+        .try_add(borrow_reserve.borrow_weighted_market_value(borrow_amount)?)?;
+    if borrow_reserve_type == ReserveType::Isolated {
+        obligation.borrowing_isolated_asset = 1;
+    }
+
+    update_borrow_attribution_values_after(
+        &mut obligation,
+        &mut borrow_reserve,
+        &borrow_reserve_key,
+        ctx.remaining_accounts,
+    )?;
+    obligation.last_update.mark_stale();
+
+    let lending_market = &ctx.accounts.lending_market;
+    let source_liquidity = ctx.accounts.source_liquidity.to_account_info();
+    let token_program = ctx.accounts.token_program.to_account_info();
+    let signer_seeds = lending_market.signer_seeds();
+    let transfer = |to: AccountInfo<'info>, amount: u64| {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                Transfer {
+                    from: source_liquidity.clone(),
+                    to,
+                    authority: lending_market.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            amount,
+        )
+    };
+
+    let mut owner_fee = borrow_fee;
+    if let Some(host_fee_receiver) = &ctx.accounts.host_fee_receiver {
+        if host_fee > 0 {
+            owner_fee = owner_fee
+                .checked_sub(host_fee)
+                .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+            transfer(host_fee_receiver.to_account_info(), host_fee)?;
+        }
+    }
+    if owner_fee > 0 {
+        transfer(ctx.accounts.borrow_reserve_liquidity_fee_receiver.to_account_info(), owner_fee)?;
+    }
+
+    let obligation_owner = ctx.accounts.obligation_owner.to_account_info();
+    if ctx.accounts.temporary_wsol.is_some() {
+        let (Some(native_mint), Some(system_program), Some(bump)) = (
+            &ctx.accounts.native_mint,
+            &ctx.accounts.system_program,
+            ctx.bumps.temporary_wsol,
+        ) else {
+            msg!("Native mint and system program must be provided to unwrap native SOL");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        };
+        create_temporary_wsol(
+            obligation_owner.clone(),
+            destination_liquidity.clone(),
+            native_mint.to_account_info(),
+            system_program.to_account_info(),
+            token_program.clone(),
+            bump,
+        )?;
+    }
+    transfer(destination_liquidity.clone(), receive_amount)?;
+
+    if ctx.accounts.temporary_wsol.is_some() {
+        unwrap_lamports(
+            destination_liquidity,
+            obligation_owner.clone(),
+            obligation_owner,
+            token_program,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,23 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Cancel obligation order context
+#[derive(Accounts)]
+pub struct CancelObligationOrder<'info> {
+    #[account(mut, close = owner)]
+    pub obligation_order: Account<'info, ObligationOrder>,
+
+    /// Owner who placed the order, receives the rent and the keeper tip back
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+/// Cancel an order that has not been executed
+pub fn handle_cancel_obligation_order(ctx: Context<CancelObligationOrder>) -> Result<()> {
+    if ctx.accounts.obligation_order.owner != ctx.accounts.owner.key() {
+        msg!("Order owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+
+    Ok(())
+}
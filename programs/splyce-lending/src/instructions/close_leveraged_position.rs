@@ -0,0 +1,339 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::update_borrow_attribution_values_after,
+    math::{Decimal, SaturatingSub, TryMul},
+    state::*,
+    utils::swap,
+};
+
+/// Close leveraged position context
+///
+/// Remaining accounts: the obligation's deposit reserves other than the withdraw reserve
+/// (writable), in order, to update their borrow attribution, followed by the accounts the swap
+/// adapter routes the swap through.
+#[derive(Accounts)]
+pub struct CloseLeveragedPosition<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    #[account(mut)]
+    pub lending_market: Box<Account<'info, LendingMarket>>,
+
+    #[account(mut)]
+    pub withdraw_reserve: AccountLoader<'info, Reserve>,
+
+    /// Withdraw reserve collateral mint
+    #[account(mut)]
+    pub withdraw_reserve_collateral_mint: Box<Account<'info, Mint>>,
+
+    /// Withdraw reserve collateral supply
+    #[account(mut)]
+    pub withdraw_reserve_collateral_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Withdraw reserve liquidity supply
+    #[account(mut)]
+    pub withdraw_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub repay_reserve: AccountLoader<'info, Reserve>,
+
+    /// Repay reserve liquidity supply
+    #[account(mut)]
+    pub repay_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// User token account of the withdraw reserve liquidity, receives the redeemed collateral
+    /// swapped away
+    #[account(mut)]
+    pub user_withdraw_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// User token account of the repay reserve liquidity, receives the swap repaid back. Any
+    /// amount above the debt stays there
+    #[account(mut)]
+    pub user_repay_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// Obligation owner, or a delegate with the withdraw permission. Authority of the user token
+    /// accounts
+    pub obligation_owner: Signer<'info>,
+
+    /// CHECK: any program implementing the swap adapter interface, the amount out is measured
+    /// on the user repay liquidity account
+    pub swap_adapter_program: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Unwind a leveraged position in a single instruction. `collateral_amount` of the withdraw
+/// reserve collateral, or the whole deposit with `u64::MAX`, is withdrawn and redeemed, swapped
+/// through the swap adapter into the repay reserve liquidity, receiving at least
+/// `min_swap_amount_out`, and repaid. The obligation must be within its allowed borrow value
+/// once the borrow is repaid.
+pub fn handle_close_leveraged_position<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CloseLeveragedPosition<'info>>,
+    collateral_amount: u64,
+    min_swap_amount_out: u64,
+) -> Result<()> {
+    if collateral_amount == 0 {
+        msg!("Collateral amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let withdraw_reserve_key = ctx.accounts.withdraw_reserve.key();
+    let repay_reserve_key = ctx.accounts.repay_reserve.key();
+    let clock = Clock::get()?;
+
+    if withdraw_reserve_key == repay_reserve_key {
+        msg!("Withdraw reserve cannot be used as the repay reserve provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let mut withdraw_reserve = ctx.accounts.withdraw_reserve.load_mut()?;
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &mut ctx.accounts.lending_market;
+
+    if withdraw_reserve.lending_market != lending_market.key() {
+        msg!("Withdraw reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.mint_pubkey != ctx.accounts.withdraw_reserve_collateral_mint.key() {
+        msg!("Withdraw reserve collateral mint does not match the withdraw reserve collateral mint provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.supply_pubkey != ctx.accounts.withdraw_reserve_collateral_supply.key() {
+        msg!("Withdraw reserve collateral supply does not match the withdraw reserve collateral supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey != ctx.accounts.withdraw_reserve_liquidity_supply.key() {
+        msg!("Withdraw reserve liquidity supply does not match the withdraw reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey == ctx.accounts.user_withdraw_liquidity.key() {
+        msg!("Withdraw reserve liquidity supply cannot be used as the user withdraw liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Withdraw reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if withdraw_reserve.reentrancy_lock != 0 {
+        msg!("Withdraw reserve is locked by an instruction in progress");
+        return Err(ProgramError::from(LendingError::ReserveLocked).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+    obligation.validate_authority(
+        &ctx.accounts.obligation_owner.key(),
+        DELEGATE_PERMISSION_WITHDRAW,
+        clock.slot,
+    )?;
+    obligation.find_liquidity_in_borrows(repay_reserve_key)?;
+
+    let other_deposits_len = obligation
+        .deposits()
+        .iter()
+        .filter(|collateral| collateral.deposit_reserve != withdraw_reserve_key)
+        .count();
+    if ctx.remaining_accounts.len() < other_deposits_len {
+        msg!("Expected {} other deposit reserves", other_deposits_len);
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    let (other_deposit_reserve_infos, adapter_accounts) =
+        ctx.remaining_accounts.split_at(other_deposits_len);
+
+    let (collateral, collateral_index) =
+        obligation.find_collateral_in_deposits(withdraw_reserve_key)?;
+    let collateral = *collateral;
+    let withdraw_amount = collateral.deposited_amount.min(collateral_amount);
+    if withdraw_amount == 0 {
+        msg!("Withdraw amount is too small to transfer collateral");
+        return Err(ProgramError::from(LendingError::WithdrawTooSmall).into());
+    }
+
+    let withdraw_liquidity_amount = withdraw_reserve
+        .collateral_exchange_rate()?
+        .decimal_collateral_to_liquidity(Decimal::from(withdraw_amount))?;
+    let withdraw_value = withdraw_reserve.market_value(withdraw_liquidity_amount)?;
+
+    if let Err(err) = lending_market.rate_limiter.update(clock.slot, withdraw_value) {
+        msg!("Market outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+    if let Err(err) = withdraw_reserve
+        .rate_limiter
+        .update(clock.slot, withdraw_liquidity_amount)
+    {
+        msg!("Reserve outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+
+    obligation.withdraw(withdraw_amount, collateral_index)?;
+    obligation.deposited_value = obligation.deposited_value.saturating_sub(withdraw_value);
+    obligation.allowed_borrow_value = obligation.allowed_borrow_value.saturating_sub(
+        withdraw_value.try_mul(
+            withdraw_reserve.loan_to_value_rate(lending_market, obligation.emode_category),
+        )?,
+    );
+    if let Ok((_, collateral_index)) = obligation.find_collateral_in_deposits(withdraw_reserve_key) {
+        let collateral = &mut obligation.deposits_mut()[collateral_index];
+        collateral.market_value = collateral.market_value.saturating_sub(withdraw_value);
+    } else {
+        // the deposit was emptied, release the borrow value attributed to it
+        withdraw_reserve.attributed_borrow_value = withdraw_reserve
+            .attributed_borrow_value
+            .saturating_sub(collateral.attributed_borrow_value);
+    }
+    obligation.last_update.mark_stale();
+
+    let liquidity_amount = withdraw_reserve.redeem_collateral(withdraw_amount)?;
+    if liquidity_amount == 0 {
+        msg!("Collateral amount is too small to redeem liquidity");
+        return Err(ProgramError::from(LendingError::WithdrawTooSmall).into());
+    }
+    withdraw_reserve.last_update.mark_stale();
+
+    // the reserves are locked and left stale across the swap, so that the adapter cannot refresh
+    // or use them through the lending program with the owner's signature, see refresh_reserve
+    withdraw_reserve.reentrancy_lock = 1;
+    drop(withdraw_reserve);
+    drop(obligation);
+
+    {
+        let mut repay_reserve = ctx.accounts.repay_reserve.load_mut()?;
+        if repay_reserve.lending_market != ctx.accounts.lending_market.key() {
+            msg!("Repay reserve lending market does not match the lending market provided");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        }
+        if repay_reserve.last_update.is_stale(clock.slot)? {
+            msg!("Repay reserve is stale and must be refreshed in the current slot");
+            return Err(ProgramError::from(LendingError::ReserveStale).into());
+        }
+        if repay_reserve.reentrancy_lock != 0 {
+            msg!("Repay reserve is locked by an instruction in progress");
+            return Err(ProgramError::from(LendingError::ReserveLocked).into());
+        }
+        repay_reserve.reentrancy_lock = 1;
+        repay_reserve.last_update.mark_stale();
+    }
+
+    // persist the rate limiter before the swap, the market is reloaded after it so that the
+    // account written back on exit includes anything the swap changed
+    ctx.accounts.lending_market.exit(&crate::ID)?;
+
+    let lending_market = &ctx.accounts.lending_market;
+    let token_program = ctx.accounts.token_program.to_account_info();
+    let obligation_owner = ctx.accounts.obligation_owner.to_account_info();
+    let user_withdraw_liquidity = ctx.accounts.user_withdraw_liquidity.to_account_info();
+    let user_repay_liquidity = ctx.accounts.user_repay_liquidity.to_account_info();
+    let signer_seeds = lending_market.signer_seeds();
+    token::burn(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Burn {
+                mint: ctx.accounts.withdraw_reserve_collateral_mint.to_account_info(),
+                from: ctx.accounts.withdraw_reserve_collateral_supply.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+        withdraw_amount,
+    )?;
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Transfer {
+                from: ctx.accounts.withdraw_reserve_liquidity_supply.to_account_info(),
+                to: user_withdraw_liquidity.clone(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+        liquidity_amount,
+    )?;
+
+    let swap_amount = swap(
+        &ctx.accounts.swap_adapter_program.to_account_info(),
+        &user_withdraw_liquidity,
+        &user_repay_liquidity,
+        &obligation_owner,
+        adapter_accounts,
+        liquidity_amount,
+        min_swap_amount_out,
+    )?;
+
+    ctx.accounts.lending_market.reload()?;
+    ctx.accounts.withdraw_reserve.load_mut()?.reentrancy_lock = 0;
+
+    let mut repay_reserve = ctx.accounts.repay_reserve.load_mut()?;
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    // fresh before the swap, which could not refresh or use it
+    repay_reserve.reentrancy_lock = 0;
+    repay_reserve.last_update.update_slot(clock.slot);
+
+    if repay_reserve.liquidity.supply_pubkey != ctx.accounts.repay_reserve_liquidity_supply.key() {
+        msg!("Repay reserve liquidity supply does not match the repay reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.liquidity.supply_pubkey == user_repay_liquidity.key() {
+        msg!("Repay reserve liquidity supply cannot be used as the user repay liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let (liquidity, liquidity_index) = obligation.find_liquidity_in_borrows(repay_reserve_key)?;
+    let CalculateRepayResult {
+        settle_amount,
+        repay_amount,
+    } = repay_reserve.calculate_repay(swap_amount, liquidity.borrowed_amount_wads)?;
+    if repay_amount == 0 {
+        msg!("Repay amount is too small to transfer liquidity");
+        return Err(ProgramError::from(LendingError::RepayTooSmall).into());
+    }
+    let repay_value = repay_reserve.market_value(settle_amount)?;
+    let weighted_repay_value = repay_reserve.borrow_weighted_market_value(settle_amount)?;
+
+    repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+    repay_reserve.last_update.mark_stale();
+    drop(repay_reserve);
+
+    obligation.repay(settle_amount, liquidity_index)?;
+    obligation.borrowed_value = obligation.borrowed_value.saturating_sub(weighted_repay_value);
+    obligation.unweighted_borrowed_value =
+        obligation.unweighted_borrowed_value.saturating_sub(repay_value);
+
+    update_borrow_attribution_values_after(
+        &mut obligation,
+        &mut *ctx.accounts.withdraw_reserve.load_mut()?,
+        &withdraw_reserve_key,
+        other_deposit_reserve_infos,
+    )?;
+
+    if obligation.borrowed_value > obligation.allowed_borrow_value {
+        msg!(
+            "Deleveraged borrowed value {} exceeds the allowed borrow value {}",
+            obligation.borrowed_value,
+            obligation.allowed_borrow_value
+        );
+        return Err(ProgramError::from(LendingError::WithdrawTooLarge).into());
+    }
+    drop(obligation);
+
+    token::transfer(
+        CpiContext::new(
+            token_program,
+            Transfer {
+                from: user_repay_liquidity,
+                to: ctx.accounts.repay_reserve_liquidity_supply.to_account_info(),
+                authority: obligation_owner,
+            },
+        ),
+        repay_amount,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Close obligation context
+#[derive(Accounts)]
+pub struct CloseObligation<'info> {
+    #[account(mut, close = owner)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    /// Obligation owner, receives the rent back
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+/// Close an obligation without any deposit or borrow and refund its rent to the owner
+pub fn handle_close_obligation(ctx: Context<CloseObligation>) -> Result<()> {
+    let obligation = ctx.accounts.obligation.load()?;
+
+    if obligation.owner != ctx.accounts.owner.key() {
+        msg!("Obligation owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+    if !obligation.deposits().is_empty() {
+        msg!("Obligation deposits must be withdrawn before closing it");
+        return Err(ProgramError::from(LendingError::ObligationDepositsNotEmpty).into());
+    }
+    if !obligation.borrows().is_empty() {
+        msg!("Obligation borrows must be repaid before closing it");
+        return Err(ProgramError::from(LendingError::ObligationBorrowsNotEmpty).into());
+    }
+
+    Ok(())
+}
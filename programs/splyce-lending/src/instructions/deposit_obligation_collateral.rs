@@ -0,0 +1,120 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::{error::LendingError, state::*};
+
+/// Deposit obligation collateral context
+#[derive(Accounts)]
+pub struct DepositObligationCollateral<'info> {
+    /// User collateral token account
+    #[account(mut)]
+    pub source_collateral: Account<'info, TokenAccount>,
+
+    /// Reserve collateral supply
+    #[account(mut)]
+    pub destination_collateral: Account<'info, TokenAccount>,
+
+    pub deposit_reserve: AccountLoader<'info, Reserve>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Obligation owner, or a delegate with the deposit permission
+    pub obligation_owner: Signer<'info>,
+
+    /// Authority of the user collateral token account
+    pub user_transfer_authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+pub fn handle_deposit_obligation_collateral(
+    ctx: Context<DepositObligationCollateral>,
+    collateral_amount: u64,
+) -> Result<()> {
+    if collateral_amount == 0 {
+        msg!("Collateral amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let source_collateral = &ctx.accounts.source_collateral;
+    let destination_collateral = &ctx.accounts.destination_collateral;
+    let deposit_reserve = ctx.accounts.deposit_reserve.load()?;
+
+    if deposit_reserve.collateral.supply_pubkey == source_collateral.key() {
+        msg!("Deposit reserve collateral supply cannot be used as the source collateral provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.collateral.supply_pubkey != destination_collateral.key() {
+        msg!("Deposit reserve collateral supply must be used as the destination collateral provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    _deposit_obligation_collateral(
+        &deposit_reserve,
+        ctx.accounts.deposit_reserve.key(),
+        &mut *ctx.accounts.obligation.load_mut()?,
+        &ctx.accounts.lending_market,
+        &ctx.accounts.obligation_owner,
+        collateral_amount,
+    )?;
+
+    token::transfer(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: source_collateral.to_account_info(),
+                to: destination_collateral.to_account_info(),
+                authority: ctx.accounts.user_transfer_authority.to_account_info(),
+            },
+        ),
+        collateral_amount,
+    )?;
+
+    Ok(())
+}
+
+/// Add collateral to the obligation after checking the reserve, the obligation owner and the
+/// tier and e-mode restrictions
+pub fn _deposit_obligation_collateral<'info>(
+    deposit_reserve: &Reserve,
+    deposit_reserve_key: Pubkey,
+    obligation: &mut Obligation,
+    lending_market: &Account<'info, LendingMarket>,
+    obligation_owner: &Signer<'info>,
+    collateral_amount: u64,
+) -> Result<()> {
+    let clock = Clock::get()?;
+
+    if deposit_reserve.lending_market != lending_market.key() {
+        msg!("Deposit reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Deposit reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    obligation.validate_authority(
+        &obligation_owner.key(),
+        DELEGATE_PERMISSION_DEPOSIT,
+        clock.slot,
+    )?;
+
+    deposit_reserve
+        .config
+        .reserve_type()?
+        .validate_deposit(!obligation.borrows().is_empty())?;
+    deposit_reserve.validate_emode_category(obligation.emode_category)?;
+
+    obligation
+        .find_or_add_collateral_to_deposits(deposit_reserve_key)?
+        .deposit(collateral_amount)?;
+    obligation.last_update.mark_stale();
+
+    Ok(())
+}
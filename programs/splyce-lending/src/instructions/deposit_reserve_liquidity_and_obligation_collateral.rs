@@ -0,0 +1,199 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, spl_token, Mint, MintTo, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::_deposit_obligation_collateral,
+    math::{Decimal, TryAdd},
+    state::*,
+    utils::{
+        create_temporary_wsol, is_native_mint, unwrap_lamports, wrap_lamports, TEMPORARY_WSOL_SEED,
+    },
+};
+
+/// Deposit reserve liquidity and obligation collateral context
+///
+/// Exactly one of `source_liquidity` and `temporary_wsol` is expected. The temporary wrapped SOL
+/// account deposits native SOL from the user's system wallet into the native-mint reserve, it is
+/// created and closed within the instruction.
+#[derive(Accounts)]
+pub struct DepositReserveLiquidityAndObligationCollateral<'info> {
+    /// User liquidity token account
+    #[account(mut)]
+    pub source_liquidity: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Reserve collateral mint
+    #[account(mut)]
+    pub reserve_collateral_mint: Box<Account<'info, Mint>>,
+
+    /// Reserve collateral supply, receives the minted collateral
+    #[account(mut)]
+    pub reserve_collateral_supply: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub deposit_reserve: AccountLoader<'info, Reserve>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Obligation owner, or a delegate with the deposit permission
+    pub obligation_owner: Signer<'info>,
+
+    /// Authority of the user liquidity token account, pays for the temporary wrapped SOL account
+    #[account(mut)]
+    pub user_transfer_authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: temporary wrapped SOL account of the user, created and closed by the instruction
+    #[account(mut, seeds = [TEMPORARY_WSOL_SEED, user_transfer_authority.key().as_ref()], bump)]
+    pub temporary_wsol: Option<UncheckedAccount<'info>>,
+
+    #[account(address = spl_token::native_mint::ID)]
+    pub native_mint: Option<Box<Account<'info, Mint>>>,
+
+    pub system_program: Option<Program<'info, System>>,
+}
+
+/// Deposit `liquidity_amount` of liquidity into the reserve and the minted collateral into the
+/// obligation, without the collateral going through a user token account
+pub fn handle_deposit_reserve_liquidity_and_obligation_collateral(
+    ctx: Context<DepositReserveLiquidityAndObligationCollateral>,
+    liquidity_amount: u64,
+) -> Result<()> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let deposit_reserve_key = ctx.accounts.deposit_reserve.key();
+    let mut deposit_reserve = ctx.accounts.deposit_reserve.load_mut()?;
+
+    if deposit_reserve.liquidity.supply_pubkey != ctx.accounts.reserve_liquidity_supply.key() {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.collateral.mint_pubkey != ctx.accounts.reserve_collateral_mint.key() {
+        msg!("Reserve collateral mint does not match the reserve collateral mint provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.collateral.supply_pubkey != ctx.accounts.reserve_collateral_supply.key() {
+        msg!("Reserve collateral supply does not match the reserve collateral supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    let source_liquidity = match (&ctx.accounts.source_liquidity, &ctx.accounts.temporary_wsol) {
+        (Some(source_liquidity), None) => {
+            if deposit_reserve.liquidity.supply_pubkey == source_liquidity.key() {
+                msg!("Reserve liquidity supply cannot be used as the source liquidity provided");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+            source_liquidity.to_account_info()
+        }
+        (None, Some(temporary_wsol)) => {
+            if !is_native_mint(&deposit_reserve.liquidity.mint_pubkey) {
+                msg!("Native SOL can only be deposited into the native mint reserve");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+            temporary_wsol.to_account_info()
+        }
+        _ => {
+            msg!("Exactly one of source liquidity and temporary wrapped SOL must be provided");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        }
+    };
+
+    if Decimal::from(liquidity_amount).try_add(deposit_reserve.liquidity.total_supply()?)?
+        > Decimal::from(deposit_reserve.config.deposit_limit)
+    {
+        msg!("Cannot deposit liquidity above the reserve deposit limit");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let collateral_amount = deposit_reserve.deposit_liquidity(liquidity_amount)?;
+    if collateral_amount == 0 {
+        msg!("Liquidity amount is too small to receive collateral");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    _deposit_obligation_collateral(
+        &deposit_reserve,
+        deposit_reserve_key,
+        &mut *ctx.accounts.obligation.load_mut()?,
+        &ctx.accounts.lending_market,
+        &ctx.accounts.obligation_owner,
+        collateral_amount,
+    )?;
+    deposit_reserve.last_update.mark_stale();
+    drop(deposit_reserve);
+
+    let user_transfer_authority = ctx.accounts.user_transfer_authority.to_account_info();
+    let token_program = ctx.accounts.token_program.to_account_info();
+    if ctx.accounts.temporary_wsol.is_some() {
+        let (Some(native_mint), Some(system_program), Some(bump)) = (
+            &ctx.accounts.native_mint,
+            &ctx.accounts.system_program,
+            ctx.bumps.temporary_wsol,
+        ) else {
+            msg!("Native mint and system program must be provided to wrap native SOL");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        };
+        create_temporary_wsol(
+            user_transfer_authority.clone(),
+            source_liquidity.clone(),
+            native_mint.to_account_info(),
+            system_program.to_account_info(),
+            token_program.clone(),
+            bump,
+        )?;
+        wrap_lamports(
+            user_transfer_authority.clone(),
+            source_liquidity.clone(),
+            system_program.to_account_info(),
+            token_program.clone(),
+            liquidity_amount,
+        )?;
+    }
+
+    token::transfer(
+        CpiContext::new(
+            token_program.clone(),
+            Transfer {
+                from: source_liquidity.clone(),
+                to: ctx.accounts.reserve_liquidity_supply.to_account_info(),
+                authority: user_transfer_authority.clone(),
+            },
+        ),
+        liquidity_amount,
+    )?;
+
+    let lending_market = &ctx.accounts.lending_market;
+    token::mint_to(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            MintTo {
+                mint: ctx.accounts.reserve_collateral_mint.to_account_info(),
+                to: ctx.accounts.reserve_collateral_supply.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&lending_market.signer_seeds()],
+        ),
+        collateral_amount,
+    )?;
+
+    if ctx.accounts.temporary_wsol.is_some() {
+        // the wrapped balance is fully deposited, only the rent is returned
+        unwrap_lamports(
+            source_liquidity,
+            user_transfer_authority.clone(),
+            user_transfer_authority,
+            token_program,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,341 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::update_borrow_attribution_values_after,
+    math::{Decimal, SaturatingSub, TryDiv, TryMul},
+    state::*,
+};
+
+/// Execute obligation order context
+///
+/// Remaining accounts: the obligation's deposit reserves other than the withdraw reserve
+/// (writable), in order, to update their borrow attribution.
+#[derive(Accounts)]
+pub struct ExecuteObligationOrder<'info> {
+    #[account(mut, close = owner)]
+    pub obligation_order: Box<Account<'info, ObligationOrder>>,
+
+    /// Owner who placed the order, receives the rent back
+    /// CHECK: checked against the order owner
+    #[account(mut)]
+    pub owner: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Keeper liquidity token account, repays the borrow
+    #[account(mut)]
+    pub source_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// Keeper token account of the withdraw reserve liquidity, receives the repaid value
+    #[account(mut)]
+    pub destination_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// Owner token account of the withdraw reserve liquidity, receives the rest of the redeemed
+    /// collateral
+    #[account(mut)]
+    pub owner_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// May be the withdraw reserve
+    #[account(mut)]
+    pub repay_reserve: AccountLoader<'info, Reserve>,
+
+    /// Repay reserve liquidity supply
+    #[account(mut)]
+    pub repay_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub withdraw_reserve: AccountLoader<'info, Reserve>,
+
+    /// Withdraw reserve collateral mint
+    #[account(mut)]
+    pub withdraw_reserve_collateral_mint: Box<Account<'info, Mint>>,
+
+    /// Withdraw reserve collateral supply
+    #[account(mut)]
+    pub withdraw_reserve_collateral_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Withdraw reserve liquidity supply
+    #[account(mut)]
+    pub withdraw_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Keeper, authority of the source liquidity, receives the tip
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Execute a triggered order against the obligation refreshed in the current slot. The keeper
+/// repays the order's borrow amount and receives the repaid value at market price out of the
+/// order's collateral amount redeemed into liquidity, and the tip. The rest of the redeemed
+/// liquidity goes to the owner. The order must not
+/// increase the obligation's loan to value and is subject to the outflow rate limits.
+pub fn handle_execute_obligation_order<'info>(
+    ctx: Context<'_, '_, 'info, 'info, ExecuteObligationOrder<'info>>,
+) -> Result<()> {
+    let accounts = &mut *ctx.accounts;
+    let mut obligation = accounts.obligation.load_mut()?;
+    let clock = Clock::get()?;
+    let order = &accounts.obligation_order;
+    let lending_market = &accounts.lending_market;
+
+    if order.obligation != accounts.obligation.key() {
+        msg!("Order obligation does not match the obligation provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if order.owner != accounts.owner.key() {
+        msg!("Order owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.owner != order.owner {
+        msg!("Obligation changed owner since the order was placed");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+
+    // the repay and withdraw reserves may be the same account, only borrow it mutably once the
+    // shared borrows are released
+    let repay_reserve_key = accounts.repay_reserve.key();
+    let repay_reserve = accounts.repay_reserve.load()?;
+    if order.repay_reserve != repay_reserve_key {
+        msg!("Order repay reserve does not match the repay reserve provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.lending_market != lending_market.key() {
+        msg!("Repay reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.liquidity.supply_pubkey != accounts.repay_reserve_liquidity_supply.key() {
+        msg!("Repay reserve liquidity supply does not match the repay reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.liquidity.supply_pubkey == accounts.source_liquidity.key() {
+        msg!("Repay reserve liquidity supply cannot be used as the source liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Repay reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+
+    let withdraw_reserve_key = accounts.withdraw_reserve.key();
+    let withdraw_reserve = accounts.withdraw_reserve.load()?;
+    if order.withdraw_reserve != withdraw_reserve_key {
+        msg!("Order withdraw reserve does not match the withdraw reserve provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.lending_market != lending_market.key() {
+        msg!("Withdraw reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.mint_pubkey != accounts.withdraw_reserve_collateral_mint.key() {
+        msg!("Withdraw reserve collateral mint does not match the withdraw reserve collateral mint provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.supply_pubkey != accounts.withdraw_reserve_collateral_supply.key() {
+        msg!("Withdraw reserve collateral supply does not match the withdraw reserve collateral supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey != accounts.withdraw_reserve_liquidity_supply.key() {
+        msg!("Withdraw reserve liquidity supply does not match the withdraw reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey == accounts.destination_liquidity.key() {
+        msg!("Withdraw reserve liquidity supply cannot be used as the destination liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey == accounts.owner_liquidity.key() {
+        msg!("Withdraw reserve liquidity supply cannot be used as the owner liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if accounts.owner_liquidity.owner != order.owner
+        || accounts.owner_liquidity.mint != withdraw_reserve.liquidity.mint_pubkey
+    {
+        msg!("Owner liquidity must be a token account of the order owner for the withdraw reserve liquidity");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Withdraw reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+
+    if !order.is_triggered(&obligation, &withdraw_reserve)? {
+        msg!("Order trigger is not met");
+        return Err(ProgramError::from(LendingError::ObligationOrderNotTriggered).into());
+    }
+    let ltv_before = loan_to_value(obligation.borrowed_value, obligation.deposited_value)?;
+
+    let (liquidity, liquidity_index) = obligation.find_liquidity_in_borrows(repay_reserve_key)?;
+    let CalculateRepayResult {
+        settle_amount,
+        repay_amount,
+    } = repay_reserve.calculate_repay(order.repay_liquidity_amount, liquidity.borrowed_amount_wads)?;
+    if repay_amount == 0 {
+        msg!("Repay amount is too small to transfer liquidity");
+        return Err(ProgramError::from(LendingError::RepayTooSmall).into());
+    }
+    let repay_value = repay_reserve.market_value(settle_amount)?;
+    let weighted_repay_value = repay_reserve.borrow_weighted_market_value(settle_amount)?;
+
+    let (collateral, collateral_index) = obligation.find_collateral_in_deposits(withdraw_reserve_key)?;
+    let withdraw_amount = order.withdraw_collateral_amount.min(collateral.deposited_amount);
+    if withdraw_amount == 0 {
+        msg!("Obligation has no collateral left to withdraw");
+        return Err(ProgramError::from(LendingError::ObligationCollateralEmpty).into());
+    }
+    let collateral_attributed_borrow_value = collateral.attributed_borrow_value;
+    let withdraw_liquidity_amount = withdraw_reserve
+        .collateral_exchange_rate()?
+        .decimal_collateral_to_liquidity(Decimal::from(withdraw_amount))?;
+    let withdraw_value = withdraw_reserve.market_value(withdraw_liquidity_amount)?;
+
+    let borrowed_value = obligation.borrowed_value.saturating_sub(weighted_repay_value);
+    let deposited_value = obligation.deposited_value.saturating_sub(withdraw_value);
+    let ltv_after = loan_to_value(borrowed_value, deposited_value)?;
+    if ltv_after > ltv_before {
+        msg!(
+            "Order would raise the obligation loan to value from {} to {}",
+            ltv_before,
+            ltv_after
+        );
+        return Err(ProgramError::from(LendingError::ObligationOrderIncreasesLtv).into());
+    }
+
+    // the keeper is paid back the value it repays at market price, its only other reward is the
+    // tip, anything the order withdraws on top of that belongs to the owner
+    let keeper_liquidity_amount = repay_value
+        .try_mul(withdraw_reserve.liquidity.decimals_factor()?)?
+        .try_div(withdraw_reserve.liquidity.market_price)?
+        .min(withdraw_liquidity_amount);
+    drop(repay_reserve);
+    drop(withdraw_reserve);
+
+    let lending_market = &mut accounts.lending_market;
+    if let Err(err) = lending_market.rate_limiter.update(clock.slot, withdraw_value) {
+        msg!("Market outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+
+    {
+        let mut repay_reserve = accounts.repay_reserve.load_mut()?;
+        repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+        repay_reserve.last_update.mark_stale();
+    }
+
+    let mut withdraw_reserve = accounts.withdraw_reserve.load_mut()?;
+    if let Err(err) = withdraw_reserve
+        .rate_limiter
+        .update(clock.slot, withdraw_liquidity_amount)
+    {
+        msg!("Reserve outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+
+    obligation.repay(settle_amount, liquidity_index)?;
+    obligation.withdraw(withdraw_amount, collateral_index)?;
+    obligation.borrowed_value = borrowed_value;
+    obligation.unweighted_borrowed_value =
+        obligation.unweighted_borrowed_value.saturating_sub(repay_value);
+    obligation.deposited_value = deposited_value;
+    if let Ok((_, collateral_index)) = obligation.find_collateral_in_deposits(withdraw_reserve_key) {
+        let collateral = &mut obligation.deposits_mut()[collateral_index];
+        collateral.market_value = collateral.market_value.saturating_sub(withdraw_value);
+    } else {
+        // the deposit was emptied, release the borrow value attributed to it
+        withdraw_reserve.attributed_borrow_value = withdraw_reserve
+            .attributed_borrow_value
+            .saturating_sub(collateral_attributed_borrow_value);
+    }
+    update_borrow_attribution_values_after(
+        &mut obligation,
+        &mut withdraw_reserve,
+        &withdraw_reserve_key,
+        ctx.remaining_accounts,
+    )?;
+    obligation.last_update.mark_stale();
+    drop(obligation);
+
+    let liquidity_amount = withdraw_reserve.redeem_collateral(withdraw_amount)?;
+    if liquidity_amount == 0 {
+        msg!("Collateral amount is too small to redeem liquidity");
+        return Err(ProgramError::from(LendingError::WithdrawTooSmall).into());
+    }
+    let keeper_liquidity_amount = keeper_liquidity_amount.try_floor_u64()?.min(liquidity_amount);
+    let owner_liquidity_amount = liquidity_amount - keeper_liquidity_amount;
+    withdraw_reserve.last_update.mark_stale();
+    drop(withdraw_reserve);
+
+    let token_program = accounts.token_program.to_account_info();
+    token::transfer(
+        CpiContext::new(
+            token_program.clone(),
+            Transfer {
+                from: accounts.source_liquidity.to_account_info(),
+                to: accounts.repay_reserve_liquidity_supply.to_account_info(),
+                authority: accounts.keeper.to_account_info(),
+            },
+        ),
+        repay_amount,
+    )?;
+
+    let lending_market = &accounts.lending_market;
+    let signer_seeds = lending_market.signer_seeds();
+    token::burn(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Burn {
+                mint: accounts.withdraw_reserve_collateral_mint.to_account_info(),
+                from: accounts.withdraw_reserve_collateral_supply.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+        withdraw_amount,
+    )?;
+    for (destination_liquidity, amount) in [
+        (&accounts.destination_liquidity, keeper_liquidity_amount),
+        (&accounts.owner_liquidity, owner_liquidity_amount),
+    ] {
+        if amount > 0 {
+            token::transfer(
+                CpiContext::new_with_signer(
+                    token_program.clone(),
+                    Transfer {
+                        from: accounts.withdraw_reserve_liquidity_supply.to_account_info(),
+                        to: destination_liquidity.to_account_info(),
+                        authority: lending_market.to_account_info(),
+                    },
+                    &[&signer_seeds],
+                ),
+                amount,
+            )?;
+        }
+    }
+
+    // the tip is escrowed on top of the rent, the rest goes back to the owner on close
+    let order = &accounts.obligation_order;
+    let order_info = order.to_account_info();
+    let keeper_info = accounts.keeper.to_account_info();
+    **order_info.try_borrow_mut_lamports()? = order_info
+        .lamports()
+        .checked_sub(order.keeper_tip)
+        .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+    **keeper_info.try_borrow_mut_lamports()? = keeper_info
+        .lamports()
+        .checked_add(order.keeper_tip)
+        .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
+    sysvar::instructions::{
+        self as sysvar_instructions, load_current_index_checked, load_instruction_at_checked,
+    },
+};
+use anchor_lang::Discriminator;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::{error::LendingError, math::Decimal, state::*};
+
+/// Flash borrow reserve liquidity context
+#[derive(Accounts)]
+pub struct FlashBorrowReserveLiquidity<'info> {
+    /// Must stay the first account, flash repays find the borrowed reserve at this index
+    #[account(mut)]
+    pub reserve: AccountLoader<'info, Reserve>,
+
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub source_liquidity: Account<'info, TokenAccount>,
+
+    /// User liquidity token account
+    #[account(mut)]
+    pub destination_liquidity: Account<'info, TokenAccount>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// CHECK: instructions sysvar, checked by address
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Flash borrow `liquidity_amount` of the reserve liquidity. A `flash_repay_reserve_liquidity`
+/// of the same amount and reserve, pointing back at this instruction, must follow in the same
+/// transaction. Only callable as a top-level instruction.
+pub fn handle_flash_borrow_reserve_liquidity(
+    ctx: Context<FlashBorrowReserveLiquidity>,
+    liquidity_amount: u64,
+) -> Result<()> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let reserve_key = ctx.accounts.reserve.key();
+    let mut reserve = ctx.accounts.reserve.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+
+    if reserve.lending_market != lending_market.key() {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.supply_pubkey != ctx.accounts.source_liquidity.key() {
+        msg!("Reserve liquidity supply must be used as the source liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.supply_pubkey == ctx.accounts.destination_liquidity.key() {
+        msg!("Reserve liquidity supply cannot be used as the destination liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.config.fees.flash_loans_disabled() {
+        msg!("Flash loans are disabled for this reserve");
+        return Err(ProgramError::from(LendingError::FlashLoansDisabled).into());
+    }
+
+    // the repay lookup below only covers top-level instructions
+    if get_stack_height() > TRANSACTION_LEVEL_STACK_HEIGHT {
+        msg!("Flash borrows cannot be invoked through a CPI");
+        return Err(ProgramError::from(LendingError::FlashBorrowCpi).into());
+    }
+
+    let instructions = ctx.accounts.instructions.to_account_info();
+    let current_index = load_current_index_checked(&instructions)? as usize;
+    let mut index = current_index + 1;
+    loop {
+        let instruction = match load_instruction_at_checked(index, &instructions) {
+            Ok(instruction) => instruction,
+            Err(ProgramError::InvalidArgument) => {
+                msg!("No flash repay found for the flash borrow");
+                return Err(ProgramError::from(LendingError::NoFlashRepayFound).into());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        index += 1;
+
+        if instruction.program_id != crate::ID || instruction.data.len() < 8 {
+            continue;
+        }
+        let (discriminator, data) = instruction.data.split_at(8);
+        if discriminator == crate::instruction::FlashBorrowReserveLiquidity::DISCRIMINATOR {
+            msg!("Multiple flash borrows are not allowed in the same transaction");
+            return Err(ProgramError::from(LendingError::MultipleFlashBorrows).into());
+        }
+        if discriminator != crate::instruction::FlashRepayReserveLiquidity::DISCRIMINATOR {
+            continue;
+        }
+
+        let repay = crate::instruction::FlashRepayReserveLiquidity::try_from_slice(data)?;
+        if repay.liquidity_amount != liquidity_amount
+            || repay.borrow_instruction_index as usize != current_index
+            || instruction.accounts.first().map(|account| account.pubkey) != Some(reserve_key)
+        {
+            msg!("Flash repay does not match the flash borrow");
+            return Err(ProgramError::from(LendingError::InvalidFlashRepay).into());
+        }
+        break;
+    }
+
+    reserve.liquidity.borrow(Decimal::from(liquidity_amount))?;
+    reserve.last_update.mark_stale();
+    drop(reserve);
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.source_liquidity.to_account_info(),
+                to: ctx.accounts.destination_liquidity.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&lending_market.signer_seeds()],
+        ),
+        liquidity_amount,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, accessor, Token, TokenAccount, Transfer};
+use crate::{error::LendingError, math::Decimal, state::*, utils::receive_flash_loan};
+
+/// Flash loan context
+///
+/// Remaining accounts: passed through to the flash loan receiver program.
+#[derive(Accounts)]
+pub struct FlashLoan<'info> {
+    #[account(mut)]
+    pub reserve: AccountLoader<'info, Reserve>,
+
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub source_liquidity: Account<'info, TokenAccount>,
+
+    /// Receiver liquidity token account, holds the loan during the callback
+    #[account(mut)]
+    pub destination_liquidity: Account<'info, TokenAccount>,
+
+    /// Reserve liquidity fee receiver
+    #[account(mut)]
+    pub reserve_liquidity_fee_receiver: Account<'info, TokenAccount>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// CHECK: any program implementing the flash loan receiver interface, the repayment is
+    /// measured on the reserve liquidity supply
+    pub flash_loan_receiver_program: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Lend `liquidity_amount` of the reserve liquidity to the flash loan receiver program within
+/// a single CPI. The receiver must return the amount plus the reserve flash loan fee to the
+/// reserve liquidity supply before its callback returns. Unlike the flash borrow and repay pair,
+/// it can be invoked through a CPI.
+pub fn handle_flash_loan<'info>(
+    ctx: Context<'_, '_, '_, 'info, FlashLoan<'info>>,
+    liquidity_amount: u64,
+) -> Result<()> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let mut reserve = ctx.accounts.reserve.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+
+    if reserve.lending_market != lending_market.key() {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.supply_pubkey != ctx.accounts.source_liquidity.key() {
+        msg!("Reserve liquidity supply must be used as the source liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.supply_pubkey == ctx.accounts.destination_liquidity.key() {
+        msg!("Reserve liquidity supply cannot be used as the destination liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.config.fee_receiver != ctx.accounts.reserve_liquidity_fee_receiver.key() {
+        msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.config.fees.flash_loans_disabled() {
+        msg!("Flash loans are disabled for this reserve");
+        return Err(ProgramError::from(LendingError::FlashLoansDisabled).into());
+    }
+    if reserve.reentrancy_lock != 0 {
+        msg!("Reserve is locked by an instruction in progress");
+        return Err(ProgramError::from(LendingError::ReserveLocked).into());
+    }
+    if liquidity_amount > reserve.liquidity.available_amount {
+        msg!("Flash loan amount cannot exceed available liquidity");
+        return Err(ProgramError::from(LendingError::InsufficientLiquidity).into());
+    }
+
+    let (flash_loan_fee, _) = reserve
+        .config
+        .fees
+        .calculate_flash_loan_fees(Decimal::from(liquidity_amount))?;
+
+    // nothing requiring a fresh reserve can run until the loan is back, see refresh_reserve
+    reserve.reentrancy_lock = 1;
+    reserve.last_update.mark_stale();
+    drop(reserve);
+
+    let source_liquidity = ctx.accounts.source_liquidity.to_account_info();
+    let destination_liquidity = ctx.accounts.destination_liquidity.to_account_info();
+    let token_program = ctx.accounts.token_program.to_account_info();
+    let signer_seeds = lending_market.signer_seeds();
+
+    let balance_before = accessor::amount(&source_liquidity)?;
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Transfer {
+                from: source_liquidity.clone(),
+                to: destination_liquidity.clone(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+        liquidity_amount,
+    )?;
+
+    receive_flash_loan(
+        &ctx.accounts.flash_loan_receiver_program.to_account_info(),
+        &destination_liquidity,
+        &source_liquidity,
+        &token_program,
+        ctx.remaining_accounts,
+        liquidity_amount,
+        flash_loan_fee,
+    )?;
+
+    let balance_after = accessor::amount(&source_liquidity)?;
+    let required_balance = balance_before
+        .checked_add(flash_loan_fee)
+        .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+    if balance_after < required_balance {
+        msg!(
+            "Reserve liquidity supply holds {} after the flash loan, {} is required",
+            balance_after,
+            required_balance
+        );
+        return Err(ProgramError::from(LendingError::NotEnoughLiquidityAfterFlashLoan).into());
+    }
+
+    ctx.accounts.reserve.load_mut()?.reentrancy_lock = 0;
+
+    if flash_loan_fee > 0 {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program,
+                Transfer {
+                    from: source_liquidity,
+                    to: ctx.accounts.reserve_liquidity_fee_receiver.to_account_info(),
+                    authority: lending_market.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            flash_loan_fee,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT},
+    sysvar::instructions::{
+        self as sysvar_instructions, load_current_index_checked, load_instruction_at_checked,
+    },
+};
+use anchor_lang::Discriminator;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::{error::LendingError, math::Decimal, state::*};
+
+/// Flash repay reserve liquidity context
+#[derive(Accounts)]
+pub struct FlashRepayReserveLiquidity<'info> {
+    /// Must stay the first account, flash borrows find the repaid reserve at this index
+    #[account(mut)]
+    pub reserve: AccountLoader<'info, Reserve>,
+
+    /// User liquidity token account
+    #[account(mut)]
+    pub source_liquidity: Account<'info, TokenAccount>,
+
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub destination_liquidity: Account<'info, TokenAccount>,
+
+    /// Reserve liquidity fee receiver
+    #[account(mut)]
+    pub reserve_liquidity_fee_receiver: Account<'info, TokenAccount>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Authority of the user liquidity token account
+    pub user_transfer_authority: Signer<'info>,
+
+    /// CHECK: instructions sysvar, checked by address
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// Host fee receiver, receives the host share of the flash loan fee
+    #[account(mut)]
+    pub host_fee_receiver: Option<Account<'info, TokenAccount>>,
+}
+
+/// Repay the `liquidity_amount` flash borrowed by the `flash_borrow_reserve_liquidity` at
+/// `borrow_instruction_index` of the transaction, plus the reserve flash loan fee. Only callable
+/// as a top-level instruction.
+pub fn handle_flash_repay_reserve_liquidity<'info>(
+    ctx: Context<'_, '_, '_, 'info, FlashRepayReserveLiquidity<'info>>,
+    liquidity_amount: u64,
+    borrow_instruction_index: u8,
+) -> Result<()> {
+    let reserve_key = ctx.accounts.reserve.key();
+    let mut reserve = ctx.accounts.reserve.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+
+    if reserve.lending_market != lending_market.key() {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.supply_pubkey != ctx.accounts.destination_liquidity.key() {
+        msg!("Reserve liquidity supply must be used as the destination liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.supply_pubkey == ctx.accounts.source_liquidity.key() {
+        msg!("Reserve liquidity supply cannot be used as the source liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.config.fee_receiver != ctx.accounts.reserve_liquidity_fee_receiver.key() {
+        msg!("Reserve liquidity fee receiver does not match the reserve liquidity fee receiver provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.config.fees.flash_loans_disabled() {
+        msg!("Flash loans are disabled for this reserve");
+        return Err(ProgramError::from(LendingError::FlashLoansDisabled).into());
+    }
+
+    if get_stack_height() > TRANSACTION_LEVEL_STACK_HEIGHT {
+        msg!("Flash repays cannot be invoked through a CPI");
+        return Err(ProgramError::from(LendingError::FlashRepayCpi).into());
+    }
+
+    // the borrow checked this repay when it ran, check the borrow from this side too so a repay
+    // cannot stand alone
+    let instructions = ctx.accounts.instructions.to_account_info();
+    let current_index = load_current_index_checked(&instructions)? as usize;
+    if borrow_instruction_index as usize >= current_index {
+        msg!("Flash repay must follow its flash borrow");
+        return Err(ProgramError::from(LendingError::InvalidFlashRepay).into());
+    }
+    let borrow_instruction =
+        load_instruction_at_checked(borrow_instruction_index as usize, &instructions)?;
+    if borrow_instruction.program_id != crate::ID
+        || borrow_instruction.data.len() < 8
+        || borrow_instruction.data[..8]
+            != crate::instruction::FlashBorrowReserveLiquidity::DISCRIMINATOR
+    {
+        msg!("Instruction at the borrow instruction index is not a flash borrow");
+        return Err(ProgramError::from(LendingError::InvalidFlashRepay).into());
+    }
+    let borrow = crate::instruction::FlashBorrowReserveLiquidity::try_from_slice(
+        &borrow_instruction.data[8..],
+    )?;
+    if borrow.liquidity_amount != liquidity_amount
+        || borrow_instruction.accounts.first().map(|account| account.pubkey) != Some(reserve_key)
+    {
+        msg!("Flash repay does not match the flash borrow");
+        return Err(ProgramError::from(LendingError::InvalidFlashRepay).into());
+    }
+
+    let flash_loan_amount = Decimal::from(liquidity_amount);
+    let (flash_loan_fee, host_fee) = reserve
+        .config
+        .fees
+        .calculate_flash_loan_fees(flash_loan_amount)?;
+
+    reserve.liquidity.repay(liquidity_amount, flash_loan_amount)?;
+    reserve.last_update.mark_stale();
+    drop(reserve);
+
+    let source_liquidity = ctx.accounts.source_liquidity.to_account_info();
+    let token_program = ctx.accounts.token_program.to_account_info();
+    let user_transfer_authority = ctx.accounts.user_transfer_authority.to_account_info();
+    let transfer = |to: AccountInfo<'info>, amount: u64| {
+        token::transfer(
+            CpiContext::new(
+                token_program.clone(),
+                Transfer {
+                    from: source_liquidity.clone(),
+                    to,
+                    authority: user_transfer_authority.clone(),
+                },
+            ),
+            amount,
+        )
+    };
+
+    transfer(ctx.accounts.destination_liquidity.to_account_info(), liquidity_amount)?;
+
+    let mut owner_fee = flash_loan_fee;
+    if let Some(host_fee_receiver) = &ctx.accounts.host_fee_receiver {
+        if host_fee > 0 {
+            owner_fee = owner_fee
+                .checked_sub(host_fee)
+                .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+            transfer(host_fee_receiver.to_account_info(), host_fee)?;
+        }
+    }
+    if owner_fee > 0 {
+        transfer(ctx.accounts.reserve_liquidity_fee_receiver.to_account_info(), owner_fee)?;
+    }
+
+    Ok(())
+}
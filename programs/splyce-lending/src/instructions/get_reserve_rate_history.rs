@@ -0,0 +1,28 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+
+/// Max number of snapshots returned by a single call, keeps the return data under 1024 bytes
+pub const MAX_RATE_SNAPSHOTS_PER_CALL: usize = 8;
+
+/// Get reserve rate history context
+#[derive(Accounts)]
+pub struct GetReserveRateHistory<'info> {
+    pub reserve: AccountLoader<'info, Reserve>,
+}
+
+/// Return up to `limit` snapshots from oldest to newest, skipping the first `offset` ones
+pub fn handle_get_reserve_rate_history(
+    ctx: Context<GetReserveRateHistory>,
+    offset: u8,
+    limit: u8,
+) -> Result<Vec<RateSnapshot>> {
+    let reserve = ctx.accounts.reserve.load()?;
+
+    Ok(reserve
+        .rate_history
+        .iter()
+        .skip(offset as usize)
+        .take((limit as usize).min(MAX_RATE_SNAPSHOTS_PER_CALL))
+        .copied()
+        .collect())
+}
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+use crate::state::*;
+
+/// Init obligation context
+#[derive(Accounts)]
+#[instruction(seed: Pubkey)]
+pub struct InitObligation<'info> {
+    #[account(init,
+        payer = owner,
+        space = 8 + std::mem::size_of::<Obligation>(),
+        seeds = [
+            lending_market.key().as_ref(),
+            seed.as_ref(),
+        ],
+        bump)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Create an obligation at the address derived from the lending market and `seed`, any key not
+/// used by another obligation of the market
+pub fn handle_init_obligation(ctx: Context<InitObligation>, seed: Pubkey) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_init()?;
+    let clock = Clock::get()?;
+
+    obligation.init(InitObligationParams {
+        current_slot: clock.slot,
+        lending_market: ctx.accounts.lending_market.key(),
+        owner: ctx.accounts.owner.key(),
+        seed,
+        bump_seed: ctx.bumps.obligation,
+    });
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{self, Transfer};
+use crate::{error::LendingError, math::Decimal, state::*};
+
+/// Init obligation order context
+#[derive(Accounts)]
+#[instruction(order_id: u8)]
+pub struct InitObligationOrder<'info> {
+    #[account(init,
+        payer = owner,
+        space = ObligationOrder::INIT_SPACE + 8,
+        seeds = [
+            OBLIGATION_ORDER_SEED,
+            obligation.key().as_ref(),
+            &[order_id],
+        ],
+        bump)]
+    pub obligation_order: Account<'info, ObligationOrder>,
+
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    /// Reserve the collateral is withdrawn from, deposited by the obligation
+    pub withdraw_reserve: AccountLoader<'info, Reserve>,
+
+    /// Reserve whose borrow is repaid, borrowed by the obligation. May be the withdraw reserve.
+    pub repay_reserve: AccountLoader<'info, Reserve>,
+
+    /// Obligation owner, pays the rent and the keeper tip
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+/// Place an order withdrawing `withdraw_collateral_amount` of the withdraw reserve collateral
+/// and repaying `repay_liquidity_amount` of the repay reserve borrow once `trigger` is met
+/// against `trigger_value`, a wad scaled loan to value ratio or market price. `keeper_tip`
+/// lamports are escrowed for the keeper executing it.
+#[allow(clippy::too_many_arguments)]
+pub fn handle_init_obligation_order(
+    ctx: Context<InitObligationOrder>,
+    order_id: u8,
+    trigger: OrderTrigger,
+    trigger_value: u128,
+    withdraw_collateral_amount: u64,
+    repay_liquidity_amount: u64,
+    keeper_tip: u64,
+) -> Result<()> {
+    let obligation = ctx.accounts.obligation.load()?;
+    let owner = &ctx.accounts.owner;
+
+    if obligation.owner != owner.key() {
+        msg!("Obligation owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+    if withdraw_collateral_amount == 0 || repay_liquidity_amount == 0 {
+        msg!("Order withdraw and repay amounts cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let withdraw_reserve = ctx.accounts.withdraw_reserve.key();
+    if ctx.accounts.withdraw_reserve.load()?.lending_market != obligation.lending_market {
+        msg!("Withdraw reserve lending market does not match the obligation lending market");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    obligation.find_collateral_in_deposits(withdraw_reserve)?;
+
+    let repay_reserve = ctx.accounts.repay_reserve.key();
+    if ctx.accounts.repay_reserve.load()?.lending_market != obligation.lending_market {
+        msg!("Repay reserve lending market does not match the obligation lending market");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    obligation.find_liquidity_in_borrows(repay_reserve)?;
+
+    ctx.accounts.obligation_order.init(InitObligationOrderParams {
+        bump_seed: ctx.bumps.obligation_order,
+        order_id,
+        obligation: ctx.accounts.obligation.key(),
+        owner: owner.key(),
+        trigger,
+        trigger_value: Decimal::from_scaled_val(trigger_value),
+        withdraw_reserve,
+        withdraw_collateral_amount,
+        repay_reserve,
+        repay_liquidity_amount,
+        keeper_tip,
+    });
+
+    if keeper_tip > 0 {
+        system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                Transfer {
+                    from: owner.to_account_info(),
+                    to: ctx.accounts.obligation_order.to_account_info(),
+                },
+            ),
+            keeper_tip,
+        )?;
+    }
+
+    Ok(())
+}
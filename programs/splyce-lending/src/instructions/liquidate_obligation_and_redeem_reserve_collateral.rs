@@ -0,0 +1,337 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    math::{Decimal, SaturatingSub},
+    state::*,
+};
+
+/// Liquidate obligation and redeem reserve collateral context
+///
+/// Exactly one of `destination_collateral` and `destination_liquidity` is expected: the seized
+/// collateral is either transferred as is or redeemed into the withdraw reserve liquidity.
+#[derive(Accounts)]
+pub struct LiquidateObligationAndRedeemReserveCollateral<'info> {
+    /// Liquidator liquidity token account
+    #[account(mut)]
+    pub source_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// Liquidator collateral token account, receives the seized collateral
+    #[account(mut)]
+    pub destination_collateral: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Liquidator token account of the withdraw reserve liquidity, receives the redeemed
+    /// collateral
+    #[account(mut)]
+    pub destination_liquidity: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// May be the withdraw reserve
+    #[account(mut)]
+    pub repay_reserve: AccountLoader<'info, Reserve>,
+
+    /// Repay reserve liquidity supply
+    #[account(mut)]
+    pub repay_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub withdraw_reserve: AccountLoader<'info, Reserve>,
+
+    /// Withdraw reserve collateral mint
+    #[account(mut)]
+    pub withdraw_reserve_collateral_mint: Box<Account<'info, Mint>>,
+
+    /// Withdraw reserve collateral supply
+    #[account(mut)]
+    pub withdraw_reserve_collateral_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Withdraw reserve liquidity supply
+    #[account(mut)]
+    pub withdraw_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Withdraw reserve liquidity fee receiver, receives the protocol liquidation fee
+    #[account(mut)]
+    pub withdraw_reserve_liquidity_fee_receiver: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Liquidator, authority of the source liquidity
+    pub user_transfer_authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Repay up to `liquidity_amount` of an unhealthy obligation's borrow, bounded by the close
+/// factor, and seize the corresponding withdraw reserve collateral plus the liquidation bonus.
+/// `u64::MAX` liquidates as much as allowed.
+pub fn handle_liquidate_obligation_and_redeem_reserve_collateral<'info>(
+    ctx: Context<'_, '_, '_, 'info, LiquidateObligationAndRedeemReserveCollateral<'info>>,
+    liquidity_amount: u64,
+) -> Result<()> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let accounts = &*ctx.accounts;
+    let lending_market = &accounts.lending_market;
+    let mut obligation = accounts.obligation.load_mut()?;
+    let clock = Clock::get()?;
+
+    if let Some(whitelisted_liquidator) = lending_market.whitelisted_liquidator {
+        if whitelisted_liquidator != accounts.user_transfer_authority.key() {
+            msg!("Liquidator is not whitelisted");
+            return Err(ProgramError::from(LendingError::NotWhitelistedLiquidator).into());
+        }
+    }
+
+    // the repay and withdraw reserves may be the same account, only borrow it mutably once the
+    // shared borrows are released
+    let repay_reserve_key = accounts.repay_reserve.key();
+    let repay_reserve = accounts.repay_reserve.load()?;
+    if repay_reserve.lending_market != lending_market.key() {
+        msg!("Repay reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.liquidity.supply_pubkey != accounts.repay_reserve_liquidity_supply.key() {
+        msg!("Repay reserve liquidity supply does not match the repay reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.liquidity.supply_pubkey == accounts.source_liquidity.key() {
+        msg!("Repay reserve liquidity supply cannot be used as the source liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Repay reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+
+    let withdraw_reserve_key = accounts.withdraw_reserve.key();
+    let withdraw_reserve = accounts.withdraw_reserve.load()?;
+    if withdraw_reserve.lending_market != lending_market.key() {
+        msg!("Withdraw reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.mint_pubkey != accounts.withdraw_reserve_collateral_mint.key() {
+        msg!("Withdraw reserve collateral mint does not match the withdraw reserve collateral mint provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.supply_pubkey != accounts.withdraw_reserve_collateral_supply.key() {
+        msg!("Withdraw reserve collateral supply does not match the withdraw reserve collateral supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey != accounts.withdraw_reserve_liquidity_supply.key() {
+        msg!("Withdraw reserve liquidity supply does not match the withdraw reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.config.fee_receiver != accounts.withdraw_reserve_liquidity_fee_receiver.key() {
+        msg!("Withdraw reserve liquidity fee receiver does not match the withdraw reserve liquidity fee receiver provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Withdraw reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    match (&accounts.destination_collateral, &accounts.destination_liquidity) {
+        (Some(destination_collateral), None) => {
+            if withdraw_reserve.collateral.supply_pubkey == destination_collateral.key() {
+                msg!("Withdraw reserve collateral supply cannot be used as the destination collateral provided");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+        }
+        (None, Some(destination_liquidity)) => {
+            if withdraw_reserve.liquidity.supply_pubkey == destination_liquidity.key() {
+                msg!("Withdraw reserve liquidity supply cannot be used as the destination liquidity provided");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+        }
+        _ => {
+            msg!("Exactly one of destination collateral and destination liquidity must be provided");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        }
+    }
+
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+    if obligation.deposited_value == Decimal::zero() {
+        msg!("Obligation deposited value is zero");
+        return Err(ProgramError::from(LendingError::ObligationDepositsZero).into());
+    }
+    if obligation.borrowed_value == Decimal::zero() {
+        msg!("Obligation borrowed value is zero");
+        return Err(ProgramError::from(LendingError::ObligationBorrowsZero).into());
+    }
+    let healthy = obligation.borrowed_value < obligation.unhealthy_borrow_value;
+    if healthy && obligation.closeable == 0 {
+        msg!("Obligation is healthy and cannot be liquidated");
+        return Err(ProgramError::from(LendingError::ObligationHealthy).into());
+    }
+
+    let (liquidity, liquidity_index) = obligation.find_liquidity_in_borrows(repay_reserve_key)?;
+    if liquidity.market_value == Decimal::zero() {
+        msg!("Obligation borrow value is zero");
+        return Err(ProgramError::from(LendingError::ObligationLiquidityEmpty).into());
+    }
+    let (collateral, collateral_index) =
+        obligation.find_collateral_in_deposits(withdraw_reserve_key)?;
+    if collateral.market_value == Decimal::zero() {
+        msg!("Obligation deposit value is zero");
+        return Err(ProgramError::from(LendingError::ObligationCollateralEmpty).into());
+    }
+
+    // healthy obligations flagged closeable are unwound without a bonus
+    let bonus = if healthy {
+        Bonus::default()
+    } else if lending_market.liquidation_auction_slots > 0 {
+        withdraw_reserve.calculate_auction_bonus(
+            obligation.unweighted_borrowed_value,
+            obligation.deposited_value,
+            clock.slot.saturating_sub(obligation.unhealthy_since_slot),
+            lending_market.liquidation_auction_slots,
+        )?
+    } else {
+        withdraw_reserve.calculate_bonus(
+            obligation.borrowed_value,
+            obligation.unweighted_borrowed_value,
+            obligation.deposited_value,
+            obligation.unhealthy_borrow_value,
+            obligation.super_unhealthy_borrow_value,
+        )?
+    };
+
+    let CalculateLiquidationResult {
+        settle_amount,
+        repay_amount,
+        withdraw_amount,
+    } = repay_reserve.calculate_liquidation(
+        liquidity_amount,
+        lending_market,
+        liquidity,
+        collateral,
+        &bonus,
+    )?;
+
+    if repay_amount == 0 {
+        msg!("Liquidation is too small to transfer liquidity");
+        return Err(ProgramError::from(LendingError::LiquidationTooSmall).into());
+    }
+    if withdraw_amount == 0 {
+        msg!("Liquidation is too small to receive collateral");
+        return Err(ProgramError::from(LendingError::LiquidationTooSmall).into());
+    }
+
+    let collateral_withdrawn = withdraw_amount == collateral.deposited_amount;
+    let collateral_attributed_borrow_value = collateral.attributed_borrow_value;
+    drop(repay_reserve);
+    drop(withdraw_reserve);
+
+    {
+        let mut repay_reserve = accounts.repay_reserve.load_mut()?;
+        repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+        repay_reserve.last_update.mark_stale();
+    }
+
+    obligation.repay(settle_amount, liquidity_index)?;
+    obligation.withdraw(withdraw_amount, collateral_index)?;
+    obligation.last_update.mark_stale();
+
+    let mut withdraw_reserve = accounts.withdraw_reserve.load_mut()?;
+    if collateral_withdrawn {
+        withdraw_reserve.attributed_borrow_value = withdraw_reserve
+            .attributed_borrow_value
+            .saturating_sub(collateral_attributed_borrow_value);
+    }
+
+    // the protocol fee is redeemed out of the seized collateral
+    let protocol_fee = withdraw_reserve.calculate_protocol_liquidation_fee(withdraw_amount, &bonus)?;
+    let liquidator_amount = withdraw_amount
+        .checked_sub(protocol_fee)
+        .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+    let protocol_fee_liquidity = withdraw_reserve.redeem_collateral(protocol_fee)?;
+    let liquidator_liquidity = if accounts.destination_liquidity.is_some() {
+        withdraw_reserve.redeem_collateral(liquidator_amount)?
+    } else {
+        0
+    };
+    withdraw_reserve.last_update.mark_stale();
+    drop(withdraw_reserve);
+    drop(obligation);
+
+    token::transfer(
+        CpiContext::new(
+            accounts.token_program.to_account_info(),
+            Transfer {
+                from: accounts.source_liquidity.to_account_info(),
+                to: accounts.repay_reserve_liquidity_supply.to_account_info(),
+                authority: accounts.user_transfer_authority.to_account_info(),
+            },
+        ),
+        repay_amount,
+    )?;
+
+    let signer_seeds = accounts.lending_market.signer_seeds();
+    let redeem = |collateral_amount: u64, liquidity_amount: u64, to: AccountInfo<'info>| {
+        token::burn(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                Burn {
+                    mint: accounts.withdraw_reserve_collateral_mint.to_account_info(),
+                    from: accounts.withdraw_reserve_collateral_supply.to_account_info(),
+                    authority: accounts.lending_market.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            collateral_amount,
+        )?;
+        token::transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                Transfer {
+                    from: accounts.withdraw_reserve_liquidity_supply.to_account_info(),
+                    to,
+                    authority: accounts.lending_market.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            liquidity_amount,
+        )
+    };
+
+    if protocol_fee > 0 {
+        redeem(
+            protocol_fee,
+            protocol_fee_liquidity,
+            accounts.withdraw_reserve_liquidity_fee_receiver.to_account_info(),
+        )?;
+    }
+    if let Some(destination_liquidity) = &accounts.destination_liquidity {
+        redeem(
+            liquidator_amount,
+            liquidator_liquidity,
+            destination_liquidity.to_account_info(),
+        )?;
+    } else if let Some(destination_collateral) = &accounts.destination_collateral {
+        token::transfer(
+            CpiContext::new_with_signer(
+                accounts.token_program.to_account_info(),
+                Transfer {
+                    from: accounts.withdraw_reserve_collateral_supply.to_account_info(),
+                    to: destination_collateral.to_account_info(),
+                    authority: accounts.lending_market.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            liquidator_amount,
+        )?;
+    }
+
+    Ok(())
+}
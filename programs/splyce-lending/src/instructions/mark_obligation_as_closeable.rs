@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Mark obligation as closeable context
+#[derive(Accounts)]
+pub struct MarkObligationAsCloseable<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Deposit reserve of the obligation whose attributed borrow value exceeds its close limit
+    pub reserve: AccountLoader<'info, Reserve>,
+}
+
+/// Flag an obligation backed by `reserve` as closeable once the borrow value attributed to the
+/// reserve exceeds its close limit. Closeable obligations can be liquidated, without a bonus,
+/// even while healthy. Permissionless.
+pub fn handle_mark_obligation_as_closeable(ctx: Context<MarkObligationAsCloseable>) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let reserve_key = ctx.accounts.reserve.key();
+    let reserve = ctx.accounts.reserve.load()?;
+    let clock = Clock::get()?;
+
+    if reserve.lending_market != lending_market.key() {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.last_update.is_stale(clock.slot)? {
+        msg!("Reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+    if obligation.borrows().is_empty() {
+        msg!("Obligation has no borrows to unwind");
+        return Err(ProgramError::from(LendingError::ObligationBorrowsEmpty).into());
+    }
+
+    reserve.check_attributed_borrow_close_limit()?;
+    obligation.find_collateral_in_deposits(reserve_key)?;
+
+    obligation.closeable = 1;
+
+    Ok(())
+}
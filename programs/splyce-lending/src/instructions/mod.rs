@@ -1,3 +1,61 @@
+pub mod accept_obligation_transfer;
+pub mod borrow_obligation_liquidity;
+pub mod cancel_obligation_order;
+pub mod close_leveraged_position;
+pub mod close_obligation;
+pub mod deposit_obligation_collateral;
+pub mod deposit_reserve_liquidity_and_obligation_collateral;
+pub mod execute_obligation_order;
+pub mod flash_borrow_reserve_liquidity;
+pub mod flash_loan;
+pub mod flash_repay_reserve_liquidity;
+pub mod get_reserve_rate_history;
 pub mod init_lending_market;
+pub mod init_obligation;
+pub mod init_obligation_order;
+pub mod liquidate_obligation_and_redeem_reserve_collateral;
+pub mod mark_obligation_as_closeable;
+pub mod open_leveraged_position;
+pub mod refresh_obligation;
+pub mod refresh_reserve;
+pub mod repay_obligation_liquidity;
+pub mod set_emode_category;
+pub mod set_liquidation_config;
+pub mod set_obligation_delegate;
+pub mod set_obligation_emode_category;
+pub mod set_oracle_config;
+pub mod socialize_bad_debt;
+pub mod transfer_obligation;
+pub mod withdraw_obligation_collateral;
+pub mod withdraw_obligation_collateral_and_redeem_reserve_collateral;
 
+pub use accept_obligation_transfer::*;
+pub use borrow_obligation_liquidity::*;
+pub use cancel_obligation_order::*;
+pub use close_leveraged_position::*;
+pub use close_obligation::*;
+pub use deposit_obligation_collateral::*;
+pub use deposit_reserve_liquidity_and_obligation_collateral::*;
+pub use execute_obligation_order::*;
+pub use flash_borrow_reserve_liquidity::*;
+pub use flash_loan::*;
+pub use flash_repay_reserve_liquidity::*;
+pub use get_reserve_rate_history::*;
 pub use init_lending_market::*;
+pub use init_obligation::*;
+pub use init_obligation_order::*;
+pub use liquidate_obligation_and_redeem_reserve_collateral::*;
+pub use mark_obligation_as_closeable::*;
+pub use open_leveraged_position::*;
+pub use refresh_obligation::*;
+pub use refresh_reserve::*;
+pub use repay_obligation_liquidity::*;
+pub use set_emode_category::*;
+pub use set_liquidation_config::*;
+pub use set_obligation_delegate::*;
+pub use set_obligation_emode_category::*;
+pub use set_oracle_config::*;
+pub use socialize_bad_debt::*;
+pub use transfer_obligation::*;
+pub use withdraw_obligation_collateral::*;
+pub use withdraw_obligation_collateral_and_redeem_reserve_collateral::*;
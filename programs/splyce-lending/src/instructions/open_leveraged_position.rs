@@ -0,0 +1,406 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::{_deposit_obligation_collateral, update_borrow_attribution_values_after},
+    math::{Decimal, SaturatingSub, TryAdd, TryMul},
+    state::*,
+    utils::swap,
+};
+
+/// Open leveraged position context
+///
+/// Remaining accounts: the obligation's deposit reserves other than the deposit reserve
+/// (writable), in order, to update their borrow attribution, followed by the accounts the swap
+/// adapter routes the swap through.
+#[derive(Accounts)]
+pub struct OpenLeveragedPosition<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    #[account(mut)]
+    pub lending_market: Box<Account<'info, LendingMarket>>,
+
+    #[account(mut)]
+    pub borrow_reserve: AccountLoader<'info, Reserve>,
+
+    /// Borrow reserve liquidity supply
+    #[account(mut)]
+    pub borrow_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Borrow reserve liquidity fee receiver
+    #[account(mut)]
+    pub borrow_reserve_liquidity_fee_receiver: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub deposit_reserve: AccountLoader<'info, Reserve>,
+
+    /// Deposit reserve liquidity supply
+    #[account(mut)]
+    pub deposit_reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Deposit reserve collateral mint
+    #[account(mut)]
+    pub deposit_reserve_collateral_mint: Box<Account<'info, Mint>>,
+
+    /// Deposit reserve collateral supply, receives the minted collateral
+    #[account(mut)]
+    pub deposit_reserve_collateral_supply: Box<Account<'info, TokenAccount>>,
+
+    /// User token account of the borrow reserve liquidity, receives the borrow swapped away
+    #[account(mut)]
+    pub user_borrow_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// User token account of the deposit reserve liquidity, receives the swap deposited back
+    #[account(mut)]
+    pub user_deposit_liquidity: Box<Account<'info, TokenAccount>>,
+
+    /// Obligation owner, or a delegate with the borrow and deposit permissions. Authority of
+    /// the user token accounts
+    pub obligation_owner: Signer<'info>,
+
+    /// CHECK: any program implementing the swap adapter interface, the amount out is measured
+    /// on the user deposit liquidity account
+    pub swap_adapter_program: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Lever the obligation up to `target_leverage_bps` of its equity, its deposited value over its
+/// deposited value net of borrows, in a single instruction. The missing value is borrowed from
+/// the borrow reserve, swapped through the swap adapter into the deposit reserve liquidity,
+/// receiving at least `min_swap_amount_out`, and deposited as collateral. The obligation must be
+/// within its allowed borrow value once the collateral is added.
+pub fn handle_open_leveraged_position<'info>(
+    ctx: Context<'_, '_, 'info, 'info, OpenLeveragedPosition<'info>>,
+    target_leverage_bps: u64,
+    min_swap_amount_out: u64,
+) -> Result<()> {
+    if target_leverage_bps <= 10_000 {
+        msg!("Target leverage must be above 1x");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let borrow_reserve_key = ctx.accounts.borrow_reserve.key();
+    let deposit_reserve_key = ctx.accounts.deposit_reserve.key();
+    let clock = Clock::get()?;
+
+    if borrow_reserve_key == deposit_reserve_key {
+        msg!("Borrow reserve cannot be used as the deposit reserve provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let mut borrow_reserve = ctx.accounts.borrow_reserve.load_mut()?;
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &mut ctx.accounts.lending_market;
+
+    if borrow_reserve.lending_market != lending_market.key() {
+        msg!("Borrow reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if borrow_reserve.liquidity.supply_pubkey != ctx.accounts.borrow_reserve_liquidity_supply.key() {
+        msg!("Borrow reserve liquidity supply does not match the borrow reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if borrow_reserve.liquidity.supply_pubkey == ctx.accounts.user_borrow_liquidity.key() {
+        msg!("Borrow reserve liquidity supply cannot be used as the user borrow liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if borrow_reserve.config.fee_receiver != ctx.accounts.borrow_reserve_liquidity_fee_receiver.key() {
+        msg!("Borrow reserve liquidity fee receiver does not match the borrow reserve liquidity fee receiver provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if borrow_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Borrow reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if borrow_reserve.reentrancy_lock != 0 {
+        msg!("Borrow reserve is locked by an instruction in progress");
+        return Err(ProgramError::from(LendingError::ReserveLocked).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+    obligation.validate_authority(
+        &ctx.accounts.obligation_owner.key(),
+        DELEGATE_PERMISSION_BORROW,
+        clock.slot,
+    )?;
+
+    let other_deposits_len = obligation
+        .deposits()
+        .iter()
+        .filter(|collateral| collateral.deposit_reserve != deposit_reserve_key)
+        .count();
+    if ctx.remaining_accounts.len() < other_deposits_len {
+        msg!("Expected {} other deposit reserves", other_deposits_len);
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    let (other_deposit_reserve_infos, adapter_accounts) =
+        ctx.remaining_accounts.split_at(other_deposits_len);
+
+    // borrow the value that brings the deposits to the target multiple of the equity, assuming
+    // the swap keeps the value
+    let equity = obligation
+        .deposited_value
+        .saturating_sub(obligation.unweighted_borrowed_value);
+    if equity == Decimal::zero() {
+        msg!("Obligation has no equity to lever up");
+        return Err(ProgramError::from(LendingError::ObligationDepositsZero).into());
+    }
+    let borrow_value = equity
+        .try_mul(Decimal::from_bps(target_leverage_bps))?
+        .saturating_sub(obligation.deposited_value);
+    if borrow_value == Decimal::zero() {
+        msg!("Obligation is already at or above the target leverage");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    // an obligation borrowing an isolated asset holds that single borrow only
+    let existing_borrow_type = if obligation.borrowing_isolated_asset != 0 {
+        ReserveType::Isolated
+    } else {
+        ReserveType::Regular
+    };
+    let existing_borrows: Vec<(Pubkey, ReserveType)> = obligation
+        .borrows()
+        .iter()
+        .map(|liquidity| (liquidity.borrow_reserve, existing_borrow_type))
+        .collect();
+    let borrow_reserve_type = borrow_reserve.config.reserve_type()?;
+    borrow_reserve_type.validate_borrow(
+        &borrow_reserve_key,
+        &existing_borrows,
+        obligation.has_isolated_collateral != 0,
+    )?;
+    borrow_reserve.validate_emode_category(obligation.emode_category)?;
+
+    let remaining_reserve_borrow = Decimal::from(borrow_reserve.config.borrow_limit)
+        .saturating_sub(borrow_reserve.liquidity.borrowed_amount_wads);
+    if remaining_reserve_borrow == Decimal::zero() {
+        msg!("Borrow reserve has reached its borrow limit");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let max_borrow_value = borrow_value
+        .try_mul(borrow_reserve.borrow_weight())?
+        .min(lending_market.rate_limiter.remaining_outflow(clock.slot)?);
+    let remaining_reserve_borrow =
+        remaining_reserve_borrow.min(borrow_reserve.rate_limiter.remaining_outflow(clock.slot)?);
+
+    let CalculateBorrowResult {
+        borrow_amount,
+        receive_amount,
+        borrow_fee,
+        ..
+    } = borrow_reserve.calculate_borrow(u64::MAX, max_borrow_value, remaining_reserve_borrow)?;
+    if receive_amount == 0 {
+        msg!("Borrow amount is too small to receive liquidity after fees");
+        return Err(ProgramError::from(LendingError::BorrowTooSmall).into());
+    }
+
+    let borrow_value = borrow_reserve.market_value(borrow_amount)?;
+    if let Err(err) = lending_market.rate_limiter.update(clock.slot, borrow_value) {
+        msg!("Market outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+    if let Err(err) = borrow_reserve.rate_limiter.update(clock.slot, borrow_amount) {
+        msg!("Reserve outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+
+    borrow_reserve.liquidity.borrow(borrow_amount)?;
+    borrow_reserve.last_update.mark_stale();
+
+    let cumulative_borrow_rate_wads = borrow_reserve.liquidity.cumulative_borrow_rate_wads;
+    let liquidity = obligation
+        .find_or_add_liquidity_to_borrows(borrow_reserve_key, cumulative_borrow_rate_wads)?;
+    liquidity.borrow(borrow_amount)?;
+    liquidity.market_value = liquidity.market_value.try_add(borrow_value)?;
+
+    obligation.unweighted_borrowed_value =
+        obligation.unweighted_borrowed_value.try_add(borrow_value)?;
+    obligation.borrowed_value = obligation
+        .borrowed_value
+        .try_add(borrow_reserve.borrow_weighted_market_value(borrow_amount)?)?;
+    if borrow_reserve_type == ReserveType::Isolated {
+        obligation.borrowing_isolated_asset = 1;
+    }
+    obligation.last_update.mark_stale();
+
+    // the reserves are locked and left stale across the swap, so that the adapter cannot refresh
+    // or use them through the lending program with the owner's signature, see refresh_reserve
+    borrow_reserve.reentrancy_lock = 1;
+    drop(borrow_reserve);
+    drop(obligation);
+
+    {
+        let mut deposit_reserve = ctx.accounts.deposit_reserve.load_mut()?;
+        if deposit_reserve.lending_market != ctx.accounts.lending_market.key() {
+            msg!("Deposit reserve lending market does not match the lending market provided");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        }
+        if deposit_reserve.last_update.is_stale(clock.slot)? {
+            msg!("Deposit reserve is stale and must be refreshed in the current slot");
+            return Err(ProgramError::from(LendingError::ReserveStale).into());
+        }
+        if deposit_reserve.reentrancy_lock != 0 {
+            msg!("Deposit reserve is locked by an instruction in progress");
+            return Err(ProgramError::from(LendingError::ReserveLocked).into());
+        }
+        deposit_reserve.reentrancy_lock = 1;
+        deposit_reserve.last_update.mark_stale();
+    }
+
+    // persist the rate limiter before the swap, the market is reloaded after it so that the
+    // account written back on exit includes anything the swap changed
+    ctx.accounts.lending_market.exit(&crate::ID)?;
+
+    let lending_market = &ctx.accounts.lending_market;
+    let token_program = ctx.accounts.token_program.to_account_info();
+    let obligation_owner = ctx.accounts.obligation_owner.to_account_info();
+    let user_deposit_liquidity = ctx.accounts.user_deposit_liquidity.to_account_info();
+    let signer_seeds = lending_market.signer_seeds();
+    let borrow_transfer = |to: AccountInfo<'info>, amount: u64| {
+        token::transfer(
+            CpiContext::new_with_signer(
+                token_program.clone(),
+                Transfer {
+                    from: ctx.accounts.borrow_reserve_liquidity_supply.to_account_info(),
+                    to,
+                    authority: lending_market.to_account_info(),
+                },
+                &[&signer_seeds],
+            ),
+            amount,
+        )
+    };
+    if borrow_fee > 0 {
+        borrow_transfer(
+            ctx.accounts.borrow_reserve_liquidity_fee_receiver.to_account_info(),
+            borrow_fee,
+        )?;
+    }
+    borrow_transfer(ctx.accounts.user_borrow_liquidity.to_account_info(), receive_amount)?;
+
+    let liquidity_amount = swap(
+        &ctx.accounts.swap_adapter_program.to_account_info(),
+        &ctx.accounts.user_borrow_liquidity.to_account_info(),
+        &user_deposit_liquidity,
+        &obligation_owner,
+        adapter_accounts,
+        receive_amount,
+        min_swap_amount_out,
+    )?;
+
+    ctx.accounts.lending_market.reload()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let signer_seeds = lending_market.signer_seeds();
+    ctx.accounts.borrow_reserve.load_mut()?.reentrancy_lock = 0;
+
+    let mut deposit_reserve = ctx.accounts.deposit_reserve.load_mut()?;
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    // fresh before the swap, which could not refresh or use it
+    deposit_reserve.reentrancy_lock = 0;
+    deposit_reserve.last_update.update_slot(clock.slot);
+
+    if deposit_reserve.liquidity.supply_pubkey != ctx.accounts.deposit_reserve_liquidity_supply.key() {
+        msg!("Deposit reserve liquidity supply does not match the deposit reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.liquidity.supply_pubkey == user_deposit_liquidity.key() {
+        msg!("Deposit reserve liquidity supply cannot be used as the user deposit liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.collateral.mint_pubkey != ctx.accounts.deposit_reserve_collateral_mint.key() {
+        msg!("Deposit reserve collateral mint does not match the deposit reserve collateral mint provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if deposit_reserve.collateral.supply_pubkey != ctx.accounts.deposit_reserve_collateral_supply.key() {
+        msg!("Deposit reserve collateral supply does not match the deposit reserve collateral supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if Decimal::from(liquidity_amount).try_add(deposit_reserve.liquidity.total_supply()?)?
+        > Decimal::from(deposit_reserve.config.deposit_limit)
+    {
+        msg!("Cannot deposit liquidity above the reserve deposit limit");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let collateral_amount = deposit_reserve.deposit_liquidity(liquidity_amount)?;
+    if collateral_amount == 0 {
+        msg!("Liquidity amount is too small to receive collateral");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    _deposit_obligation_collateral(
+        &deposit_reserve,
+        deposit_reserve_key,
+        &mut obligation,
+        lending_market,
+        &ctx.accounts.obligation_owner,
+        collateral_amount,
+    )?;
+
+    let deposit_value = deposit_reserve.market_value(Decimal::from(liquidity_amount))?;
+    let (_, collateral_index) = obligation.find_collateral_in_deposits(deposit_reserve_key)?;
+    let collateral = &mut obligation.deposits_mut()[collateral_index];
+    collateral.market_value = collateral.market_value.try_add(deposit_value)?;
+    obligation.deposited_value = obligation.deposited_value.try_add(deposit_value)?;
+    obligation.allowed_borrow_value = obligation.allowed_borrow_value.try_add(
+        deposit_value.try_mul(
+            deposit_reserve.loan_to_value_rate(lending_market, obligation.emode_category),
+        )?,
+    )?;
+
+    update_borrow_attribution_values_after(
+        &mut obligation,
+        &mut deposit_reserve,
+        &deposit_reserve_key,
+        other_deposit_reserve_infos,
+    )?;
+
+    if obligation.borrowed_value > obligation.allowed_borrow_value {
+        msg!(
+            "Leveraged borrowed value {} exceeds the allowed borrow value {}",
+            obligation.borrowed_value,
+            obligation.allowed_borrow_value
+        );
+        return Err(ProgramError::from(LendingError::BorrowTooLarge).into());
+    }
+    obligation.last_update.mark_stale();
+    deposit_reserve.last_update.mark_stale();
+    drop(obligation);
+    drop(deposit_reserve);
+
+    token::transfer(
+        CpiContext::new(
+            token_program.clone(),
+            Transfer {
+                from: user_deposit_liquidity,
+                to: ctx.accounts.deposit_reserve_liquidity_supply.to_account_info(),
+                authority: obligation_owner,
+            },
+        ),
+        liquidity_amount,
+    )?;
+    token::mint_to(
+        CpiContext::new_with_signer(
+            token_program,
+            MintTo {
+                mint: ctx.accounts.deposit_reserve_collateral_mint.to_account_info(),
+                to: ctx.accounts.deposit_reserve_collateral_supply.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&signer_seeds],
+        ),
+        collateral_amount,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,331 @@
+use anchor_lang::prelude::*;
+use solana_program::slot_history::Slot;
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, SaturatingSub, TryAdd, TryDiv, TryMul},
+    state::*,
+};
+
+/// Refresh obligation context
+///
+/// Remaining accounts: the obligation's deposit reserves (writable) in order, followed by its
+/// borrow reserves in order. Every reserve must be refreshed in the current slot.
+#[derive(Accounts)]
+pub struct RefreshObligation<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+}
+
+pub fn handle_refresh_obligation<'info>(
+    ctx: Context<'_, '_, 'info, 'info, RefreshObligation<'info>>,
+) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let clock = Clock::get()?;
+
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    refresh_obligation_values(
+        &mut obligation,
+        lending_market,
+        ctx.remaining_accounts,
+        clock.slot,
+        false,
+    )
+}
+
+/// Load a reserve passed in remaining accounts, checking it is the expected reserve of the
+/// lending market
+pub fn load_reserve<'info>(
+    reserve_info: Option<&'info AccountInfo<'info>>,
+    expected_reserve: &Pubkey,
+    lending_market: &Pubkey,
+) -> Result<AccountLoader<'info, Reserve>> {
+    let reserve_info = match reserve_info {
+        Some(reserve_info) => reserve_info,
+        None => {
+            msg!("Reserve {} is missing from the accounts provided", expected_reserve);
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        }
+    };
+    if reserve_info.key != expected_reserve {
+        msg!(
+            "Reserve {} provided where reserve {} was expected",
+            reserve_info.key,
+            expected_reserve
+        );
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let reserve = AccountLoader::<Reserve>::try_from(reserve_info)?;
+    if reserve.load()?.lending_market != *lending_market {
+        msg!("Reserve lending market does not match the obligation lending market");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    Ok(reserve)
+}
+
+/// Load a reserve passed in remaining accounts like [`load_reserve`], also checking that it was
+/// refreshed in the current slot
+pub fn load_fresh_reserve<'info>(
+    reserve_info: Option<&'info AccountInfo<'info>>,
+    expected_reserve: &Pubkey,
+    lending_market: &Pubkey,
+    current_slot: Slot,
+) -> Result<AccountLoader<'info, Reserve>> {
+    let reserve = load_reserve(reserve_info, expected_reserve, lending_market)?;
+    if reserve.load()?.last_update.is_stale(current_slot)? {
+        msg!("Reserve {} is stale and must be refreshed in the current slot", expected_reserve);
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+
+    Ok(reserve)
+}
+
+/// Recompute the borrow value attributed to each of the obligation's deposits, proportional to
+/// its share of the deposited value, and apply the changes to `deposit_reserves`, given in the
+/// same order as the deposits.
+///
+/// With `check_open_limit`, every reserve whose attributed borrow value increased must stay
+/// within its open limit.
+pub fn update_borrow_attribution_values(
+    obligation: &mut Obligation,
+    deposit_reserves: &mut [&mut Reserve],
+    check_open_limit: bool,
+) -> Result<()> {
+    if deposit_reserves.len() != obligation.deposits().len() {
+        msg!(
+            "Expected {} deposit reserves, got {}",
+            obligation.deposits().len(),
+            deposit_reserves.len()
+        );
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let deposited_value = obligation.deposited_value;
+    let unweighted_borrowed_value = obligation.unweighted_borrowed_value;
+
+    for (collateral, deposit_reserve) in obligation.deposits_mut().iter_mut().zip(deposit_reserves.iter_mut()) {
+        let attributed_borrow_value = if deposited_value == Decimal::zero() {
+            Decimal::zero()
+        } else {
+            unweighted_borrowed_value
+                .try_mul(collateral.market_value)?
+                .try_div(deposited_value)?
+        };
+
+        deposit_reserve.attributed_borrow_value = deposit_reserve
+            .attributed_borrow_value
+            .saturating_sub(collateral.attributed_borrow_value)
+            .try_add(attributed_borrow_value)?;
+
+        if check_open_limit && attributed_borrow_value > collateral.attributed_borrow_value {
+            deposit_reserve.check_attributed_borrow_open_limit()?;
+        }
+
+        collateral.attributed_borrow_value = attributed_borrow_value;
+    }
+
+    Ok(())
+}
+
+/// Accrue interest on the obligation's borrows and recompute its deposited, borrowed, allowed and
+/// unhealthy borrow values in the lending market quote currency from `reserve_infos`, then mark
+/// it fresh. Also updates the borrow value attributed to each deposit reserve, and clears the
+/// closeable flag once none of them is over its close limit.
+///
+/// Reserves are only borrowed from their account data one at a time while reading, so a reserve
+/// both deposited and borrowed by the obligation can be passed twice.
+///
+/// With `check_emode` set, every reserve must be in the obligation's e-mode category.
+pub fn refresh_obligation_values<'info>(
+    obligation: &mut Obligation,
+    lending_market: &LendingMarket,
+    reserve_infos: &'info [AccountInfo<'info>],
+    current_slot: Slot,
+    check_emode: bool,
+) -> Result<()> {
+    if reserve_infos.len() != obligation.deposits().len() + obligation.borrows().len() {
+        msg!(
+            "Expected {} deposit and borrow reserves, got {}",
+            obligation.deposits().len() + obligation.borrows().len(),
+            reserve_infos.len()
+        );
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let mut reserve_iter = reserve_infos.iter();
+    let emode_category = obligation.emode_category;
+
+    let mut deposited_value = Decimal::zero();
+    let mut allowed_borrow_value = Decimal::zero();
+    let mut unhealthy_borrow_value = Decimal::zero();
+    let mut super_unhealthy_borrow_value = Decimal::zero();
+    let mut has_isolated_collateral = false;
+    let mut deposit_reserves: Vec<AccountLoader<Reserve>> =
+        Vec::with_capacity(obligation.deposits().len());
+    let obligation_lending_market = obligation.lending_market;
+
+    for collateral in obligation.deposits_mut().iter_mut() {
+        let deposit_reserve_loader = load_fresh_reserve(
+            reserve_iter.next(),
+            &collateral.deposit_reserve,
+            &obligation_lending_market,
+            current_slot,
+        )?;
+        let deposit_reserve = deposit_reserve_loader.load()?;
+        if check_emode {
+            deposit_reserve.validate_emode_category(emode_category)?;
+        }
+
+        let liquidity_amount = deposit_reserve
+            .collateral_exchange_rate()?
+            .decimal_collateral_to_liquidity(collateral.deposited_amount.into())?;
+        let market_value = deposit_reserve.market_value(liquidity_amount)?;
+        collateral.market_value = market_value;
+        deposited_value = deposited_value.try_add(market_value)?;
+
+        // isolated collateral cannot back borrows
+        if deposit_reserve.config.reserve_type()? == ReserveType::IsolatedCollateral {
+            has_isolated_collateral = true;
+        } else {
+            allowed_borrow_value = allowed_borrow_value.try_add(
+                market_value
+                    .try_mul(deposit_reserve.loan_to_value_rate(lending_market, emode_category))?,
+            )?;
+            unhealthy_borrow_value = unhealthy_borrow_value.try_add(market_value.try_mul(
+                deposit_reserve.liquidation_threshold_rate(lending_market, emode_category),
+            )?)?;
+            super_unhealthy_borrow_value = super_unhealthy_borrow_value.try_add(
+                market_value.try_mul(
+                    deposit_reserve.max_liquidation_threshold_rate(lending_market, emode_category),
+                )?,
+            )?;
+        }
+
+        drop(deposit_reserve);
+        deposit_reserves.push(deposit_reserve_loader);
+    }
+
+    let mut borrowed_value = Decimal::zero();
+    let mut unweighted_borrowed_value = Decimal::zero();
+    let mut borrowing_isolated_asset = false;
+
+    for liquidity in obligation.borrows_mut().iter_mut() {
+        let borrow_reserve_loader = load_fresh_reserve(
+            reserve_iter.next(),
+            &liquidity.borrow_reserve,
+            &obligation_lending_market,
+            current_slot,
+        )?;
+        let borrow_reserve = borrow_reserve_loader.load()?;
+        if check_emode {
+            borrow_reserve.validate_emode_category(emode_category)?;
+        }
+
+        liquidity.accrue_interest(borrow_reserve.liquidity.cumulative_borrow_rate_wads)?;
+
+        let market_value = borrow_reserve.market_value(liquidity.borrowed_amount_wads)?;
+        liquidity.market_value = market_value;
+
+        borrowed_value =
+            borrowed_value.try_add(market_value.try_mul(borrow_reserve.borrow_weight())?)?;
+        unweighted_borrowed_value = unweighted_borrowed_value.try_add(market_value)?;
+
+        if borrow_reserve.config.reserve_type()? == ReserveType::Isolated {
+            borrowing_isolated_asset = true;
+        }
+    }
+
+    obligation.deposited_value = deposited_value;
+    obligation.borrowed_value = borrowed_value;
+    obligation.unweighted_borrowed_value = unweighted_borrowed_value;
+    obligation.allowed_borrow_value = allowed_borrow_value;
+    obligation.unhealthy_borrow_value = unhealthy_borrow_value;
+    obligation.super_unhealthy_borrow_value = super_unhealthy_borrow_value;
+    obligation.has_isolated_collateral = has_isolated_collateral as u8;
+    obligation.borrowing_isolated_asset = borrowing_isolated_asset as u8;
+
+    // keep the slot the obligation turned unhealthy for as long as it stays unhealthy
+    let unhealthy = !obligation.borrows().is_empty() && borrowed_value >= unhealthy_borrow_value;
+    if !unhealthy {
+        obligation.unhealthy_since_slot = 0;
+    } else if obligation.unhealthy_since_slot == 0 {
+        obligation.unhealthy_since_slot = current_slot;
+    }
+
+    obligation.last_update.update_slot(current_slot);
+
+    let mut deposit_reserves = deposit_reserves
+        .iter()
+        .map(|deposit_reserve| deposit_reserve.load_mut())
+        .collect::<Result<Vec<_>>>()?;
+    update_borrow_attribution_values(
+        obligation,
+        &mut deposit_reserves
+            .iter_mut()
+            .map(|deposit_reserve| &mut **deposit_reserve)
+            .collect::<Vec<_>>(),
+        false,
+    )?;
+
+    // the closeable flag only holds while one of the deposit reserves is over its close limit
+    if !deposit_reserves
+        .iter()
+        .any(|deposit_reserve| deposit_reserve.attributed_borrow_close_limit_exceeded())
+    {
+        obligation.closeable = 0;
+    }
+
+    Ok(())
+}
+
+/// Update the borrow attribution of the obligation's deposit reserves after `reserve` was used to
+/// borrow or withdraw, checking the open limits. `reserve` may or may not be one of the deposit
+/// reserves, `other_deposit_reserve_infos` are all the other deposit reserves in order.
+pub fn update_borrow_attribution_values_after<'info>(
+    obligation: &mut Obligation,
+    reserve: &mut Reserve,
+    reserve_key: &Pubkey,
+    other_deposit_reserve_infos: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    let mut reserve_iter = other_deposit_reserve_infos.iter();
+    let mut other_deposit_reserves: Vec<AccountLoader<Reserve>> =
+        Vec::with_capacity(obligation.deposits().len());
+    for collateral in obligation.deposits().iter() {
+        if collateral.deposit_reserve != *reserve_key {
+            other_deposit_reserves.push(load_reserve(
+                reserve_iter.next(),
+                &collateral.deposit_reserve,
+                &obligation.lending_market,
+            )?);
+        }
+    }
+    if reserve_iter.next().is_some() {
+        msg!("Too many deposit reserves provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let mut other_deposit_reserves = other_deposit_reserves
+        .iter()
+        .map(|deposit_reserve| deposit_reserve.load_mut())
+        .collect::<Result<Vec<_>>>()?;
+    let mut reserve = Some(reserve);
+    let mut other_iter = other_deposit_reserves.iter_mut();
+    let mut deposit_reserves: Vec<&mut Reserve> = Vec::with_capacity(obligation.deposits().len());
+    for collateral in obligation.deposits().iter() {
+        if collateral.deposit_reserve == *reserve_key {
+            deposit_reserves.push(reserve.take().unwrap());
+        } else {
+            deposit_reserves.push(&mut **other_iter.next().unwrap());
+        }
+    }
+    update_borrow_attribution_values(obligation, &mut deposit_reserves, true)
+}
@@ -0,0 +1,55 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*, utils::get_pyth_price};
+
+/// Refresh reserve context
+#[derive(Accounts)]
+pub struct RefreshReserve<'info> {
+    #[account(mut)]
+    pub reserve: AccountLoader<'info, Reserve>,
+
+    /// Lending market of the reserve, holds the oracle price limits
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// CHECK: reserve liquidity Pyth price account, checked against the reserve
+    pub pyth_oracle: UncheckedAccount<'info>,
+}
+
+/// Accrue the reserve interest and read its market price from the oracle, making it fresh for
+/// the current slot
+pub fn handle_refresh_reserve(ctx: Context<RefreshReserve>) -> Result<()> {
+    let mut reserve = ctx.accounts.reserve.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let clock = Clock::get()?;
+
+    // the reserve is left stale while it is locked, so that nothing requiring a fresh reserve can
+    // run from the program the locking instruction calls into
+    if reserve.reentrancy_lock != 0 {
+        msg!("Reserve cannot be refreshed while it is locked by an instruction in progress");
+        return Err(ProgramError::from(LendingError::ReserveLocked).into());
+    }
+
+    if reserve.lending_market != lending_market.key() {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.liquidity.oracle_pubkey == Pubkey::default() {
+        msg!("Reserve has no oracle configured");
+        return Err(ProgramError::from(LendingError::NullOracleConfig).into());
+    }
+    if reserve.liquidity.oracle_pubkey != ctx.accounts.pyth_oracle.key() {
+        msg!("Reserve liquidity oracle does not match the oracle provided");
+        return Err(ProgramError::from(LendingError::InvalidOracleConfig).into());
+    }
+    reserve.liquidity.market_price = get_pyth_price(
+        &ctx.accounts.pyth_oracle.to_account_info(),
+        clock.slot,
+        lending_market.oracle_max_staleness_slots,
+        lending_market.oracle_max_confidence_pct,
+    )?;
+
+    reserve.accrue_interest(clock.slot)?;
+    reserve.last_update.update_slot(clock.slot);
+    reserve.record_rate_snapshot(clock.slot)?;
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, spl_token, Mint, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    math::Decimal,
+    state::*,
+    utils::{
+        create_temporary_wsol, is_native_mint, unwrap_lamports, wrap_lamports, TEMPORARY_WSOL_SEED,
+    },
+};
+
+/// Repay obligation liquidity context
+///
+/// Exactly one of `source_liquidity` and `temporary_wsol` is expected. The temporary wrapped SOL
+/// account repays the native-mint reserve from the user's system wallet, it is created and closed
+/// within the instruction.
+#[derive(Accounts)]
+pub struct RepayObligationLiquidity<'info> {
+    /// User liquidity token account
+    #[account(mut)]
+    pub source_liquidity: Option<Box<Account<'info, TokenAccount>>>,
+
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub destination_liquidity: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub repay_reserve: AccountLoader<'info, Reserve>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Authority of the user liquidity token account, need not be the obligation owner. Pays for
+    /// the temporary wrapped SOL account.
+    #[account(mut)]
+    pub user_transfer_authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: temporary wrapped SOL account of the user, created and closed by the instruction
+    #[account(mut, seeds = [TEMPORARY_WSOL_SEED, user_transfer_authority.key().as_ref()], bump)]
+    pub temporary_wsol: Option<UncheckedAccount<'info>>,
+
+    #[account(address = spl_token::native_mint::ID)]
+    pub native_mint: Option<Box<Account<'info, Mint>>>,
+
+    pub system_program: Option<Program<'info, System>>,
+}
+
+/// Repay `liquidity_amount` of the obligation's borrow, or the full debt rounded up with
+/// `u64::MAX`
+pub fn handle_repay_obligation_liquidity(
+    ctx: Context<RepayObligationLiquidity>,
+    liquidity_amount: u64,
+) -> Result<()> {
+    if liquidity_amount == 0 {
+        msg!("Liquidity amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let destination_liquidity = &ctx.accounts.destination_liquidity;
+    let repay_reserve_key = ctx.accounts.repay_reserve.key();
+    let mut repay_reserve = ctx.accounts.repay_reserve.load_mut()?;
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let clock = Clock::get()?;
+
+    if repay_reserve.lending_market != lending_market.key() {
+        msg!("Repay reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if repay_reserve.liquidity.supply_pubkey != destination_liquidity.key() {
+        msg!("Repay reserve liquidity supply must be used as the destination liquidity provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    let source_liquidity = match (&ctx.accounts.source_liquidity, &ctx.accounts.temporary_wsol) {
+        (Some(source_liquidity), None) => {
+            if repay_reserve.liquidity.supply_pubkey == source_liquidity.key() {
+                msg!("Repay reserve liquidity supply cannot be used as the source liquidity provided");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+            source_liquidity.to_account_info()
+        }
+        (None, Some(temporary_wsol)) => {
+            if !is_native_mint(&repay_reserve.liquidity.mint_pubkey) {
+                msg!("Native SOL can only repay the native mint reserve");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+            temporary_wsol.to_account_info()
+        }
+        _ => {
+            msg!("Exactly one of source liquidity and temporary wrapped SOL must be provided");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        }
+    };
+    if repay_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Repay reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let (liquidity, liquidity_index) =
+        obligation.find_liquidity_in_borrows_mut(repay_reserve_key)?;
+    if liquidity.borrowed_amount_wads == Decimal::zero() {
+        msg!("Liquidity borrowed amount is zero");
+        return Err(ProgramError::from(LendingError::ObligationLiquidityEmpty).into());
+    }
+
+    // settle against the debt as of the freshly accrued reserve
+    liquidity.accrue_interest(repay_reserve.liquidity.cumulative_borrow_rate_wads)?;
+
+    let CalculateRepayResult {
+        settle_amount,
+        repay_amount,
+    } = repay_reserve.calculate_repay(liquidity_amount, liquidity.borrowed_amount_wads)?;
+
+    if repay_amount == 0 {
+        msg!("Repay amount is too small to transfer liquidity");
+        return Err(ProgramError::from(LendingError::RepayTooSmall).into());
+    }
+
+    repay_reserve.liquidity.repay(repay_amount, settle_amount)?;
+    repay_reserve.last_update.mark_stale();
+
+    obligation.repay(settle_amount, liquidity_index)?;
+    obligation.last_update.mark_stale();
+
+    let user_transfer_authority = ctx.accounts.user_transfer_authority.to_account_info();
+    let token_program = ctx.accounts.token_program.to_account_info();
+    if ctx.accounts.temporary_wsol.is_some() {
+        let (Some(native_mint), Some(system_program), Some(bump)) = (
+            &ctx.accounts.native_mint,
+            &ctx.accounts.system_program,
+            ctx.bumps.temporary_wsol,
+        ) else {
+            msg!("Native mint and system program must be provided to wrap native SOL");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        };
+        create_temporary_wsol(
+            user_transfer_authority.clone(),
+            source_liquidity.clone(),
+            native_mint.to_account_info(),
+            system_program.to_account_info(),
+            token_program.clone(),
+            bump,
+        )?;
+        // only the amount actually repaid is wrapped, which covers u64::MAX full repays too
+        wrap_lamports(
+            user_transfer_authority.clone(),
+            source_liquidity.clone(),
+            system_program.to_account_info(),
+            token_program.clone(),
+            repay_amount,
+        )?;
+    }
+
+    token::transfer(
+        CpiContext::new(
+            token_program.clone(),
+            Transfer {
+                from: source_liquidity.clone(),
+                to: ctx.accounts.destination_liquidity.to_account_info(),
+                authority: user_transfer_authority.clone(),
+            },
+        ),
+        repay_amount,
+    )?;
+
+    if ctx.accounts.temporary_wsol.is_some() {
+        // the wrapped balance is fully repaid, only the rent is returned
+        unwrap_lamports(
+            source_liquidity,
+            user_transfer_authority.clone(),
+            user_transfer_authority,
+            token_program,
+        )?;
+    }
+
+    Ok(())
+}
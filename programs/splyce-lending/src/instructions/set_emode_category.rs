@@ -0,0 +1,49 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Set e-mode category context
+#[derive(Accounts)]
+pub struct SetEModeCategory<'info> {
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Lending market owner or risk authority
+    pub signer: Signer<'info>,
+}
+
+pub fn handle_set_emode_category(
+    ctx: Context<SetEModeCategory>,
+    category_id: u8,
+    category: EModeCategory,
+) -> Result<()> {
+    let lending_market = &mut ctx.accounts.lending_market;
+    let signer = &ctx.accounts.signer;
+
+    lending_market.validate_risk_authority(signer.key)?;
+
+    if category_id == 0 || category_id as usize > MAX_EMODE_CATEGORIES {
+        msg!("E-mode category id must be between 1 and {}", MAX_EMODE_CATEGORIES);
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+
+    // a zeroed category unsets it
+    if category.is_set() {
+        if category.loan_to_value_ratio >= 100 {
+            msg!("E-mode loan to value ratio must be in range [0, 100)");
+            return Err(ProgramError::from(LendingError::InvalidConfig).into());
+        }
+        if category.liquidation_threshold < category.loan_to_value_ratio
+            || category.liquidation_threshold > 100
+        {
+            msg!("E-mode liquidation threshold must be in range [LTV, 100]");
+            return Err(ProgramError::from(LendingError::InvalidConfig).into());
+        }
+    } else if category.loan_to_value_ratio != 0 {
+        msg!("E-mode liquidation threshold must be set");
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+
+    lending_market.emode_categories[category_id as usize - 1] = category;
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Set liquidation config context
+#[derive(Accounts)]
+pub struct SetLiquidationConfig<'info> {
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Lending market owner or risk authority
+    pub signer: Signer<'info>,
+}
+
+pub fn handle_set_liquidation_config(
+    ctx: Context<SetLiquidationConfig>,
+    liquidation_close_factor: u8,
+    liquidation_dust_threshold: u64,
+    liquidation_auction_slots: u64,
+) -> Result<()> {
+    let lending_market = &mut ctx.accounts.lending_market;
+    let signer = &ctx.accounts.signer;
+
+    lending_market.validate_risk_authority(signer.key)?;
+
+    if liquidation_close_factor == 0 || liquidation_close_factor > 100 {
+        msg!("Liquidation close factor must be in range (0, 100]");
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+
+    lending_market.liquidation_close_factor = liquidation_close_factor;
+    lending_market.liquidation_dust_threshold = liquidation_dust_threshold;
+    lending_market.liquidation_auction_slots = liquidation_auction_slots;
+
+    Ok(())
+}
@@ -0,0 +1,41 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Set obligation delegate context
+#[derive(Accounts)]
+pub struct SetObligationDelegate<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Grant `delegate` the DELEGATE_PERMISSION_* flags in `permissions` until `expiry_slot`
+/// (0 for no expiry), replacing its previous permissions. Revokes the delegate when
+/// `permissions` is 0.
+pub fn handle_set_obligation_delegate(
+    ctx: Context<SetObligationDelegate>,
+    delegate: Pubkey,
+    permissions: u8,
+    expiry_slot: u64,
+) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let owner = &ctx.accounts.owner;
+
+    if obligation.owner != owner.key() {
+        msg!("Obligation owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+    if delegate == obligation.owner {
+        msg!("Obligation owner cannot be its own delegate");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if permissions & !DELEGATE_PERMISSION_ALL != 0 {
+        msg!("Delegate permissions {:#b} contain unknown flags", permissions);
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+
+    obligation.set_delegate(delegate, permissions, expiry_slot)?;
+
+    Ok(())
+}
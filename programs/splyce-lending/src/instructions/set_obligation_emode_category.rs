@@ -0,0 +1,61 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, instructions::refresh_obligation_values, state::*};
+
+/// Set obligation e-mode category context
+///
+/// Remaining accounts: same as refresh_obligation.
+#[derive(Accounts)]
+pub struct SetObligationEModeCategory<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Opt the obligation into `emode_category`, or out of e-mode with 0. All of the obligation's
+/// reserves must be in the category, and the obligation must stay healthy at the new ratios.
+pub fn handle_set_obligation_emode_category<'info>(
+    ctx: Context<'_, '_, 'info, 'info, SetObligationEModeCategory<'info>>,
+    emode_category: u8,
+) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let owner = &ctx.accounts.owner;
+    let clock = Clock::get()?;
+
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.owner != owner.key() {
+        msg!("Obligation owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+    if emode_category != 0 && lending_market.emode_category(emode_category).is_none() {
+        msg!("E-mode category {} is not set in the lending market", emode_category);
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+
+    obligation.emode_category = emode_category;
+    refresh_obligation_values(
+        &mut obligation,
+        lending_market,
+        ctx.remaining_accounts,
+        clock.slot,
+        true,
+    )?;
+
+    if obligation.borrowed_value > obligation.allowed_borrow_value {
+        msg!(
+            "Obligation borrowed value {} exceeds its allowed borrow value {} in e-mode category {}",
+            obligation.borrowed_value,
+            obligation.allowed_borrow_value,
+            emode_category
+        );
+        return Err(ProgramError::from(LendingError::BorrowTooLarge).into());
+    }
+
+    Ok(())
+}
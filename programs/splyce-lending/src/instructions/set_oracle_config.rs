@@ -0,0 +1,37 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Set oracle config context
+#[derive(Accounts)]
+pub struct SetOracleConfig<'info> {
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Lending market owner or risk authority
+    pub signer: Signer<'info>,
+}
+
+pub fn handle_set_oracle_config(
+    ctx: Context<SetOracleConfig>,
+    oracle_max_staleness_slots: u64,
+    oracle_max_confidence_pct: u8,
+) -> Result<()> {
+    let lending_market = &mut ctx.accounts.lending_market;
+    let signer = &ctx.accounts.signer;
+
+    lending_market.validate_risk_authority(signer.key)?;
+
+    if oracle_max_staleness_slots == 0 {
+        msg!("Oracle max staleness must be at least one slot");
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+    if oracle_max_confidence_pct == 0 || oracle_max_confidence_pct > 100 {
+        msg!("Oracle max confidence must be in range (0, 100]");
+        return Err(ProgramError::from(LendingError::InvalidConfig).into());
+    }
+
+    lending_market.oracle_max_staleness_slots = oracle_max_staleness_slots;
+    lending_market.oracle_max_confidence_pct = oracle_max_confidence_pct;
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, math::Decimal, state::*};
+
+/// Socialize bad debt context
+#[derive(Accounts)]
+pub struct SocializeBadDebt<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Reserve of the borrow written off
+    #[account(mut)]
+    pub reserve: AccountLoader<'info, Reserve>,
+}
+
+/// Emitted when the debt of an obligation without collateral is written off
+#[event]
+pub struct BadDebtSocialized {
+    /// Obligation whose borrow was written off
+    pub obligation: Pubkey,
+    /// Reserve of the borrow
+    pub reserve: Pubkey,
+    /// Liquidity amount written off
+    pub debt_amount: Decimal,
+    /// Part of the debt covered by the reserve accumulated protocol fees
+    pub insurance_amount: Decimal,
+    /// Part of the debt taken from the reserve depositors
+    pub socialized_amount: Decimal,
+}
+
+/// Write off the `reserve` borrow of an obligation left without any collateral. The reserve
+/// accumulated protocol fees cover the debt first, the rest is spread over the depositors
+/// through a lower collateral exchange rate. Permissionless.
+pub fn handle_socialize_bad_debt(ctx: Context<SocializeBadDebt>) -> Result<()> {
+    let obligation_key = ctx.accounts.obligation.key();
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let lending_market = &ctx.accounts.lending_market;
+    let reserve_key = ctx.accounts.reserve.key();
+    let mut reserve = ctx.accounts.reserve.load_mut()?;
+    let clock = Clock::get()?;
+
+    if reserve.lending_market != lending_market.key() {
+        msg!("Reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if reserve.last_update.is_stale(clock.slot)? {
+        msg!("Reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if !obligation.deposits().is_empty() {
+        msg!("Obligation collateral must be liquidated before its debt is written off");
+        return Err(ProgramError::from(LendingError::ObligationDepositsNotEmpty).into());
+    }
+
+    let (liquidity, liquidity_index) = obligation.find_liquidity_in_borrows_mut(reserve_key)?;
+    if liquidity.borrowed_amount_wads == Decimal::zero() {
+        msg!("Liquidity borrowed amount is zero");
+        return Err(ProgramError::from(LendingError::ObligationLiquidityEmpty).into());
+    }
+
+    // write off the debt as of the freshly accrued reserve
+    liquidity.accrue_interest(reserve.liquidity.cumulative_borrow_rate_wads)?;
+    let debt_amount = liquidity.borrowed_amount_wads;
+
+    let WriteOffResult {
+        insurance_amount,
+        socialized_amount,
+    } = reserve.liquidity.write_off(debt_amount)?;
+    reserve.last_update.mark_stale();
+
+    obligation.repay(debt_amount, liquidity_index)?;
+    obligation.last_update.mark_stale();
+
+    msg!(
+        "Wrote off {} of debt, {} covered by protocol fees and {} socialized",
+        debt_amount,
+        insurance_amount,
+        socialized_amount
+    );
+    emit!(BadDebtSocialized {
+        obligation: obligation_key,
+        reserve: reserve_key,
+        debt_amount,
+        insurance_amount,
+        socialized_amount,
+    });
+
+    Ok(())
+}
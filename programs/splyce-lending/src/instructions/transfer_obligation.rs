@@ -0,0 +1,34 @@
+use anchor_lang::prelude::*;
+use crate::{error::LendingError, state::*};
+
+/// Transfer obligation context
+#[derive(Accounts)]
+pub struct TransferObligation<'info> {
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    pub owner: Signer<'info>,
+}
+
+/// Propose `new_owner` as the obligation owner, or cancel a pending transfer with `None`. The
+/// transfer completes once the new owner accepts it.
+pub fn handle_transfer_obligation(
+    ctx: Context<TransferObligation>,
+    new_owner: Option<Pubkey>,
+) -> Result<()> {
+    let mut obligation = ctx.accounts.obligation.load_mut()?;
+    let owner = &ctx.accounts.owner;
+
+    if obligation.owner != owner.key() {
+        msg!("Obligation owner does not match the owner provided");
+        return Err(ProgramError::from(LendingError::InvalidObligationOwner).into());
+    }
+    if new_owner == Some(obligation.owner) {
+        msg!("Obligation is already owned by the new owner provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    obligation.pending_owner = new_owner.unwrap_or_default();
+
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::update_borrow_attribution_values_after,
+    math::{Decimal, SaturatingSub},
+    state::*,
+};
+
+/// Withdraw obligation collateral context
+///
+/// Remaining accounts: the obligation's deposit reserves other than the withdraw reserve
+/// (writable), in order, to update their borrow attribution.
+#[derive(Accounts)]
+pub struct WithdrawObligationCollateral<'info> {
+    /// Reserve collateral supply
+    #[account(mut)]
+    pub source_collateral: Account<'info, TokenAccount>,
+
+    /// User collateral token account
+    #[account(mut)]
+    pub destination_collateral: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub withdraw_reserve: AccountLoader<'info, Reserve>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Obligation owner, or a delegate with the withdraw permission
+    pub obligation_owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Withdraw `collateral_amount` of collateral, or as much as the obligation's health allows with
+/// `u64::MAX`
+pub fn handle_withdraw_obligation_collateral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawObligationCollateral<'info>>,
+    collateral_amount: u64,
+) -> Result<()> {
+    if collateral_amount == 0 {
+        msg!("Collateral amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+    let mut withdraw_reserve = ctx.accounts.withdraw_reserve.load_mut()?;
+    if withdraw_reserve.collateral.supply_pubkey == ctx.accounts.destination_collateral.key() {
+        msg!("Withdraw reserve collateral supply cannot be used as the destination collateral provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let withdraw_amount = _withdraw_obligation_collateral(
+        &mut withdraw_reserve,
+        ctx.accounts.withdraw_reserve.key(),
+        &mut *ctx.accounts.obligation.load_mut()?,
+        &mut ctx.accounts.lending_market,
+        &ctx.accounts.source_collateral,
+        &ctx.accounts.obligation_owner,
+        ctx.remaining_accounts,
+        collateral_amount,
+    )?;
+
+    let lending_market = &ctx.accounts.lending_market;
+    token::transfer(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.source_collateral.to_account_info(),
+                to: ctx.accounts.destination_collateral.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&lending_market.signer_seeds()],
+        ),
+        withdraw_amount,
+    )?;
+
+    Ok(())
+}
+
+/// Remove collateral from the obligation after checking its health, the rate limits and the
+/// borrow attribution limits. Returns the collateral amount to transfer out of the reserve.
+#[allow(clippy::too_many_arguments)]
+pub fn _withdraw_obligation_collateral<'info>(
+    withdraw_reserve: &mut Reserve,
+    withdraw_reserve_key: Pubkey,
+    obligation: &mut Obligation,
+    lending_market: &mut Account<'info, LendingMarket>,
+    source_collateral: &Account<'info, TokenAccount>,
+    obligation_owner: &Signer<'info>,
+    other_deposit_reserve_infos: &'info [AccountInfo<'info>],
+    collateral_amount: u64,
+) -> Result<u64> {
+    let clock = Clock::get()?;
+
+    if withdraw_reserve.lending_market != lending_market.key() {
+        msg!("Withdraw reserve lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.collateral.supply_pubkey != source_collateral.key() {
+        msg!("Withdraw reserve collateral supply must be used as the source collateral provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.last_update.is_stale(clock.slot)? {
+        msg!("Withdraw reserve is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ReserveStale).into());
+    }
+    if obligation.lending_market != lending_market.key() {
+        msg!("Obligation lending market does not match the lending market provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if obligation.last_update.is_stale(clock.slot)? {
+        msg!("Obligation is stale and must be refreshed in the current slot");
+        return Err(ProgramError::from(LendingError::ObligationStale).into());
+    }
+    obligation.validate_authority(
+        &obligation_owner.key(),
+        DELEGATE_PERMISSION_WITHDRAW,
+        clock.slot,
+    )?;
+
+    let (collateral, collateral_index) =
+        obligation.find_collateral_in_deposits(withdraw_reserve_key)?;
+    let collateral = *collateral;
+    if collateral.deposited_amount == 0 {
+        msg!("Collateral deposited amount is zero");
+        return Err(ProgramError::from(LendingError::ObligationCollateralEmpty).into());
+    }
+
+    let withdraw_amount = if obligation.borrows().is_empty() {
+        if collateral_amount == u64::MAX {
+            collateral.deposited_amount
+        } else {
+            collateral.deposited_amount.min(collateral_amount)
+        }
+    } else if obligation.deposited_value == Decimal::zero() {
+        msg!("Obligation deposited value is zero");
+        return Err(ProgramError::from(LendingError::ObligationDepositsZero).into());
+    } else {
+        let max_withdraw_amount =
+            obligation.max_withdraw_amount(&collateral, withdraw_reserve, lending_market)?;
+        if max_withdraw_amount == 0 {
+            msg!("Maximum withdraw value is zero");
+            return Err(ProgramError::from(LendingError::WithdrawTooLarge).into());
+        }
+
+        if collateral_amount == u64::MAX {
+            max_withdraw_amount
+        } else if collateral_amount > max_withdraw_amount {
+            msg!(
+                "Withdraw amount {} is larger than the maximum withdraw amount {}",
+                collateral_amount,
+                max_withdraw_amount
+            );
+            return Err(ProgramError::from(LendingError::WithdrawTooLarge).into());
+        } else {
+            collateral_amount
+        }
+    };
+
+    if withdraw_amount == 0 {
+        msg!("Withdraw amount is too small to transfer collateral");
+        return Err(ProgramError::from(LendingError::WithdrawTooSmall).into());
+    }
+
+    let liquidity_amount = withdraw_reserve
+        .collateral_exchange_rate()?
+        .decimal_collateral_to_liquidity(Decimal::from(withdraw_amount))?;
+    let withdraw_value = withdraw_reserve.market_value(liquidity_amount)?;
+
+    if let Err(err) = lending_market.rate_limiter.update(clock.slot, withdraw_value) {
+        msg!("Market outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+    if let Err(err) = withdraw_reserve.rate_limiter.update(clock.slot, liquidity_amount) {
+        msg!("Reserve outflow limit exceeded! Please try again later.");
+        return Err(err.into());
+    }
+
+    obligation.withdraw(withdraw_amount, collateral_index)?;
+    obligation.deposited_value = obligation.deposited_value.saturating_sub(withdraw_value);
+    if let Ok((_, collateral_index)) = obligation.find_collateral_in_deposits(withdraw_reserve_key) {
+        let collateral = &mut obligation.deposits_mut()[collateral_index];
+        collateral.market_value = collateral.market_value.saturating_sub(withdraw_value);
+    } else {
+        // the deposit was emptied, release the borrow value attributed to it
+        withdraw_reserve.attributed_borrow_value = withdraw_reserve
+            .attributed_borrow_value
+            .saturating_sub(collateral.attributed_borrow_value);
+    }
+
+    update_borrow_attribution_values_after(
+        obligation,
+        withdraw_reserve,
+        &withdraw_reserve_key,
+        other_deposit_reserve_infos,
+    )?;
+    obligation.last_update.mark_stale();
+
+    Ok(withdraw_amount)
+}
@@ -0,0 +1,181 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, spl_token, Burn, Mint, Token, TokenAccount, Transfer};
+use crate::{
+    error::LendingError,
+    instructions::_withdraw_obligation_collateral,
+    state::*,
+    utils::{create_temporary_wsol, is_native_mint, unwrap_lamports, TEMPORARY_WSOL_SEED},
+};
+
+/// Withdraw obligation collateral and redeem reserve collateral context
+///
+/// Exactly one of `destination_liquidity` and `temporary_wsol` is expected. The temporary wrapped
+/// SOL account withdraws from the native-mint reserve straight to the owner's system wallet, it
+/// is created and closed within the instruction.
+///
+/// Remaining accounts: the obligation's deposit reserves other than the withdraw reserve
+/// (writable), in order, to update their borrow attribution.
+#[derive(Accounts)]
+pub struct WithdrawObligationCollateralAndRedeemReserveCollateral<'info> {
+    /// Reserve collateral supply
+    #[account(mut)]
+    pub reserve_collateral_supply: Box<Account<'info, TokenAccount>>,
+
+    /// Reserve collateral mint
+    #[account(mut)]
+    pub reserve_collateral_mint: Box<Account<'info, Mint>>,
+
+    /// Reserve liquidity supply
+    #[account(mut)]
+    pub reserve_liquidity_supply: Box<Account<'info, TokenAccount>>,
+
+    /// User liquidity token account
+    #[account(mut)]
+    pub destination_liquidity: Option<Box<Account<'info, TokenAccount>>>,
+
+    #[account(mut)]
+    pub withdraw_reserve: AccountLoader<'info, Reserve>,
+
+    #[account(mut)]
+    pub obligation: AccountLoader<'info, Obligation>,
+
+    #[account(mut)]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    /// Obligation owner, or a delegate with the withdraw permission. Pays for the temporary
+    /// wrapped SOL account.
+    #[account(mut)]
+    pub obligation_owner: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: temporary wrapped SOL account of the owner, created and closed by the instruction
+    #[account(mut, seeds = [TEMPORARY_WSOL_SEED, obligation_owner.key().as_ref()], bump)]
+    pub temporary_wsol: Option<UncheckedAccount<'info>>,
+
+    #[account(address = spl_token::native_mint::ID)]
+    pub native_mint: Option<Box<Account<'info, Mint>>>,
+
+    pub system_program: Option<Program<'info, System>>,
+}
+
+/// Withdraw `collateral_amount` of collateral from the obligation and redeem it for the reserve
+/// liquidity, or as much as the obligation's health allows with `u64::MAX`
+pub fn handle_withdraw_obligation_collateral_and_redeem_reserve_collateral<'info>(
+    ctx: Context<'_, '_, 'info, 'info, WithdrawObligationCollateralAndRedeemReserveCollateral<'info>>,
+    collateral_amount: u64,
+) -> Result<()> {
+    if collateral_amount == 0 {
+        msg!("Collateral amount provided cannot be zero");
+        return Err(ProgramError::from(LendingError::InvalidAmount).into());
+    }
+
+    let mut withdraw_reserve = ctx.accounts.withdraw_reserve.load_mut()?;
+    if withdraw_reserve.collateral.mint_pubkey != ctx.accounts.reserve_collateral_mint.key() {
+        msg!("Reserve collateral mint does not match the reserve collateral mint provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    if withdraw_reserve.liquidity.supply_pubkey != ctx.accounts.reserve_liquidity_supply.key() {
+        msg!("Reserve liquidity supply does not match the reserve liquidity supply provided");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+    let destination_liquidity =
+        match (&ctx.accounts.destination_liquidity, &ctx.accounts.temporary_wsol) {
+            (Some(destination_liquidity), None) => {
+                if withdraw_reserve.liquidity.supply_pubkey == destination_liquidity.key() {
+                    msg!("Reserve liquidity supply cannot be used as the destination liquidity provided");
+                    return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+                }
+                destination_liquidity.to_account_info()
+            }
+            (None, Some(temporary_wsol)) => {
+                if !is_native_mint(&withdraw_reserve.liquidity.mint_pubkey) {
+                    msg!("Native SOL can only be withdrawn from the native mint reserve");
+                    return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+                }
+                temporary_wsol.to_account_info()
+            }
+            _ => {
+                msg!("Exactly one of destination liquidity and temporary wrapped SOL must be provided");
+                return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+            }
+        };
+
+    let withdraw_amount = _withdraw_obligation_collateral(
+        &mut withdraw_reserve,
+        ctx.accounts.withdraw_reserve.key(),
+        &mut *ctx.accounts.obligation.load_mut()?,
+        &mut ctx.accounts.lending_market,
+        &ctx.accounts.reserve_collateral_supply,
+        &ctx.accounts.obligation_owner,
+        ctx.remaining_accounts,
+        collateral_amount,
+    )?;
+
+    let liquidity_amount = withdraw_reserve.redeem_collateral(withdraw_amount)?;
+    if liquidity_amount == 0 {
+        msg!("Collateral amount is too small to redeem liquidity");
+        return Err(ProgramError::from(LendingError::WithdrawTooSmall).into());
+    }
+    withdraw_reserve.last_update.mark_stale();
+    drop(withdraw_reserve);
+
+    let lending_market = &ctx.accounts.lending_market;
+    let token_program = ctx.accounts.token_program.to_account_info();
+    token::burn(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Burn {
+                mint: ctx.accounts.reserve_collateral_mint.to_account_info(),
+                from: ctx.accounts.reserve_collateral_supply.to_account_info(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&lending_market.signer_seeds()],
+        ),
+        withdraw_amount,
+    )?;
+
+    let obligation_owner = ctx.accounts.obligation_owner.to_account_info();
+    if ctx.accounts.temporary_wsol.is_some() {
+        let (Some(native_mint), Some(system_program), Some(bump)) = (
+            &ctx.accounts.native_mint,
+            &ctx.accounts.system_program,
+            ctx.bumps.temporary_wsol,
+        ) else {
+            msg!("Native mint and system program must be provided to unwrap native SOL");
+            return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+        };
+        create_temporary_wsol(
+            obligation_owner.clone(),
+            destination_liquidity.clone(),
+            native_mint.to_account_info(),
+            system_program.to_account_info(),
+            token_program.clone(),
+            bump,
+        )?;
+    }
+
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            Transfer {
+                from: ctx.accounts.reserve_liquidity_supply.to_account_info(),
+                to: destination_liquidity.clone(),
+                authority: lending_market.to_account_info(),
+            },
+            &[&lending_market.signer_seeds()],
+        ),
+        liquidity_amount,
+    )?;
+
+    if ctx.accounts.temporary_wsol.is_some() {
+        unwrap_lamports(
+            destination_liquidity,
+            obligation_owner.clone(),
+            obligation_owner,
+            token_program,
+        )?;
+    }
+
+    Ok(())
+}
@@ -4,6 +4,7 @@ pub mod instructions;
 pub mod state;
 pub mod error;
 pub mod math;
+pub mod utils;
 
 
 pub use instructions::*;
@@ -22,4 +23,242 @@ pub mod splyce_lending {
         msg!("Instruction: init_lending_market");
         handle_init_lending_market(ctx, quote_currency)
     }
+
+    pub fn set_liquidation_config(
+        ctx: Context<SetLiquidationConfig>,
+        liquidation_close_factor: u8,
+        liquidation_dust_threshold: u64,
+        liquidation_auction_slots: u64,
+    ) -> Result<()> {
+        msg!("Instruction: set_liquidation_config");
+        handle_set_liquidation_config(
+            ctx,
+            liquidation_close_factor,
+            liquidation_dust_threshold,
+            liquidation_auction_slots,
+        )
+    }
+
+    pub fn set_oracle_config(
+        ctx: Context<SetOracleConfig>,
+        oracle_max_staleness_slots: u64,
+        oracle_max_confidence_pct: u8,
+    ) -> Result<()> {
+        msg!("Instruction: set_oracle_config");
+        handle_set_oracle_config(ctx, oracle_max_staleness_slots, oracle_max_confidence_pct)
+    }
+
+    pub fn set_emode_category(
+        ctx: Context<SetEModeCategory>,
+        category_id: u8,
+        category: EModeCategory,
+    ) -> Result<()> {
+        msg!("Instruction: set_emode_category");
+        handle_set_emode_category(ctx, category_id, category)
+    }
+
+    pub fn init_obligation(ctx: Context<InitObligation>, seed: Pubkey) -> Result<()> {
+        msg!("Instruction: init_obligation");
+        handle_init_obligation(ctx, seed)
+    }
+
+    pub fn refresh_reserve(ctx: Context<RefreshReserve>) -> Result<()> {
+        msg!("Instruction: refresh_reserve");
+        handle_refresh_reserve(ctx)
+    }
+
+    pub fn refresh_obligation<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefreshObligation<'info>>,
+    ) -> Result<()> {
+        msg!("Instruction: refresh_obligation");
+        handle_refresh_obligation(ctx)
+    }
+
+    pub fn set_obligation_emode_category<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SetObligationEModeCategory<'info>>,
+        emode_category: u8,
+    ) -> Result<()> {
+        msg!("Instruction: set_obligation_emode_category");
+        handle_set_obligation_emode_category(ctx, emode_category)
+    }
+
+    pub fn set_obligation_delegate(
+        ctx: Context<SetObligationDelegate>,
+        delegate: Pubkey,
+        permissions: u8,
+        expiry_slot: u64,
+    ) -> Result<()> {
+        msg!("Instruction: set_obligation_delegate");
+        handle_set_obligation_delegate(ctx, delegate, permissions, expiry_slot)
+    }
+
+    pub fn transfer_obligation(
+        ctx: Context<TransferObligation>,
+        new_owner: Option<Pubkey>,
+    ) -> Result<()> {
+        msg!("Instruction: transfer_obligation");
+        handle_transfer_obligation(ctx, new_owner)
+    }
+
+    pub fn accept_obligation_transfer(ctx: Context<AcceptObligationTransfer>) -> Result<()> {
+        msg!("Instruction: accept_obligation_transfer");
+        handle_accept_obligation_transfer(ctx)
+    }
+
+    pub fn close_obligation(ctx: Context<CloseObligation>) -> Result<()> {
+        msg!("Instruction: close_obligation");
+        handle_close_obligation(ctx)
+    }
+
+    pub fn deposit_obligation_collateral(
+        ctx: Context<DepositObligationCollateral>,
+        collateral_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: deposit_obligation_collateral");
+        handle_deposit_obligation_collateral(ctx, collateral_amount)
+    }
+
+    pub fn deposit_reserve_liquidity_and_obligation_collateral(
+        ctx: Context<DepositReserveLiquidityAndObligationCollateral>,
+        liquidity_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: deposit_reserve_liquidity_and_obligation_collateral");
+        handle_deposit_reserve_liquidity_and_obligation_collateral(ctx, liquidity_amount)
+    }
+
+    pub fn withdraw_obligation_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawObligationCollateral<'info>>,
+        collateral_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: withdraw_obligation_collateral");
+        handle_withdraw_obligation_collateral(ctx, collateral_amount)
+    }
+
+    pub fn withdraw_obligation_collateral_and_redeem_reserve_collateral<'info>(
+        ctx: Context<'_, '_, 'info, 'info, WithdrawObligationCollateralAndRedeemReserveCollateral<'info>>,
+        collateral_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: withdraw_obligation_collateral_and_redeem_reserve_collateral");
+        handle_withdraw_obligation_collateral_and_redeem_reserve_collateral(ctx, collateral_amount)
+    }
+
+    pub fn borrow_obligation_liquidity<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BorrowObligationLiquidity<'info>>,
+        liquidity_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: borrow_obligation_liquidity");
+        handle_borrow_obligation_liquidity(ctx, liquidity_amount)
+    }
+
+    pub fn repay_obligation_liquidity(
+        ctx: Context<RepayObligationLiquidity>,
+        liquidity_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: repay_obligation_liquidity");
+        handle_repay_obligation_liquidity(ctx, liquidity_amount)
+    }
+
+    pub fn liquidate_obligation_and_redeem_reserve_collateral<'info>(
+        ctx: Context<'_, '_, '_, 'info, LiquidateObligationAndRedeemReserveCollateral<'info>>,
+        liquidity_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: liquidate_obligation_and_redeem_reserve_collateral");
+        handle_liquidate_obligation_and_redeem_reserve_collateral(ctx, liquidity_amount)
+    }
+
+    pub fn mark_obligation_as_closeable(ctx: Context<MarkObligationAsCloseable>) -> Result<()> {
+        msg!("Instruction: mark_obligation_as_closeable");
+        handle_mark_obligation_as_closeable(ctx)
+    }
+
+    pub fn socialize_bad_debt(ctx: Context<SocializeBadDebt>) -> Result<()> {
+        msg!("Instruction: socialize_bad_debt");
+        handle_socialize_bad_debt(ctx)
+    }
+
+    pub fn flash_borrow_reserve_liquidity(
+        ctx: Context<FlashBorrowReserveLiquidity>,
+        liquidity_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: flash_borrow_reserve_liquidity");
+        handle_flash_borrow_reserve_liquidity(ctx, liquidity_amount)
+    }
+
+    pub fn flash_repay_reserve_liquidity<'info>(
+        ctx: Context<'_, '_, '_, 'info, FlashRepayReserveLiquidity<'info>>,
+        liquidity_amount: u64,
+        borrow_instruction_index: u8,
+    ) -> Result<()> {
+        msg!("Instruction: flash_repay_reserve_liquidity");
+        handle_flash_repay_reserve_liquidity(ctx, liquidity_amount, borrow_instruction_index)
+    }
+
+    pub fn flash_loan<'info>(
+        ctx: Context<'_, '_, '_, 'info, FlashLoan<'info>>,
+        liquidity_amount: u64,
+    ) -> Result<()> {
+        msg!("Instruction: flash_loan");
+        handle_flash_loan(ctx, liquidity_amount)
+    }
+
+    pub fn open_leveraged_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, OpenLeveragedPosition<'info>>,
+        target_leverage_bps: u64,
+        min_swap_amount_out: u64,
+    ) -> Result<()> {
+        msg!("Instruction: open_leveraged_position");
+        handle_open_leveraged_position(ctx, target_leverage_bps, min_swap_amount_out)
+    }
+
+    pub fn close_leveraged_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CloseLeveragedPosition<'info>>,
+        collateral_amount: u64,
+        min_swap_amount_out: u64,
+    ) -> Result<()> {
+        msg!("Instruction: close_leveraged_position");
+        handle_close_leveraged_position(ctx, collateral_amount, min_swap_amount_out)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn init_obligation_order(
+        ctx: Context<InitObligationOrder>,
+        order_id: u8,
+        trigger: OrderTrigger,
+        trigger_value: u128,
+        withdraw_collateral_amount: u64,
+        repay_liquidity_amount: u64,
+        keeper_tip: u64,
+    ) -> Result<()> {
+        msg!("Instruction: init_obligation_order");
+        handle_init_obligation_order(
+            ctx,
+            order_id,
+            trigger,
+            trigger_value,
+            withdraw_collateral_amount,
+            repay_liquidity_amount,
+            keeper_tip,
+        )
+    }
+
+    pub fn cancel_obligation_order(ctx: Context<CancelObligationOrder>) -> Result<()> {
+        msg!("Instruction: cancel_obligation_order");
+        handle_cancel_obligation_order(ctx)
+    }
+
+    pub fn execute_obligation_order<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteObligationOrder<'info>>,
+    ) -> Result<()> {
+        msg!("Instruction: execute_obligation_order");
+        handle_execute_obligation_order(ctx)
+    }
+
+    pub fn get_reserve_rate_history(
+        ctx: Context<GetReserveRateHistory>,
+        offset: u8,
+        limit: u8,
+    ) -> Result<Vec<RateSnapshot>> {
+        msg!("Instruction: get_reserve_rate_history");
+        handle_get_reserve_rate_history(ctx, offset, limit)
+    }
 }
@@ -17,8 +17,9 @@ use crate::{
     math::{common::*, Rate},
 };
 use solana_program::program_error::ProgramError;
-use anchor_lang::{AnchorSerialize, AnchorDeserialize};
+use anchor_lang::{AnchorSerialize, AnchorDeserialize, Space};
 use borsh::io::{self, Write, Read};
+use bytemuck::{Pod, Zeroable};
 use std::{convert::TryFrom, fmt};
 use uint::construct_uint;
 
@@ -29,8 +30,16 @@ construct_uint! {
 
 /// Large decimal values, precise to 18 digits
 #[derive(Clone, Copy, Default, PartialEq, PartialOrd, Eq, Ord)]
+#[repr(transparent)]
 pub struct Decimal(pub U192);
 
+// SAFETY: U192 is a `#[repr(C)]` array of three u64 words, least significant first, with no
+// padding and no invalid bit patterns. On the little-endian targets the program runs on its
+// memory layout is the same as the borsh encoding below, so zero-copy accounts and borsh
+// accounts store decimals identically.
+unsafe impl Zeroable for Decimal {}
+unsafe impl Pod for Decimal {}
+
 impl Decimal {
     /// One
     pub fn one() -> Self {
@@ -263,3 +272,8 @@ impl AnchorDeserialize for Decimal {
         Ok(Decimal(U192(words))) // Reconstruct the U192 from the three words
     }
 }
+
+/// Implement Space for Decimal
+impl Space for Decimal {
+    const INIT_SPACE: usize = 24; // 3 x 8 bytes for the underlying U192 words
+}
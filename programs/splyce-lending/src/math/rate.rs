@@ -22,6 +22,7 @@ use crate::{
     error::LendingError,
     math::{common::*, decimal::Decimal},
 };
+use bytemuck::{Pod, Zeroable};
 use solana_program::program_error::ProgramError;
 
 use std::{convert::TryFrom, fmt};
@@ -34,8 +35,14 @@ construct_uint! {
 
 /// Small decimal values, precise to 18 digits
 #[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, Eq, Ord)]
+#[repr(transparent)]
 pub struct Rate(pub U128);
 
+// SAFETY: U128 is a `#[repr(C)]` array of two u64 words, least significant first, with no
+// padding and no invalid bit patterns.
+unsafe impl Zeroable for Rate {}
+unsafe impl Pod for Rate {}
+
 impl Rate {
     /// One
     pub fn one() -> Self {
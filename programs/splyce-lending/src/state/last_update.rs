@@ -0,0 +1,53 @@
+use anchor_lang::prelude::*;
+use solana_program::slot_history::Slot;
+
+use crate::error::LendingError;
+
+/// Number of slots to consider stale after
+pub const STALE_AFTER_SLOTS_ELAPSED: u64 = 1;
+
+/// Last update state
+#[zero_copy]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LastUpdate {
+    /// Last slot when updated
+    pub slot: u64,
+    /// 1 when marked stale, 0 when slot updated
+    pub stale: u8,
+    pub _padding: [u8; 7],
+}
+
+impl LastUpdate {
+    /// Create new last update
+    pub fn new(slot: Slot) -> Self {
+        Self {
+            slot,
+            stale: 1,
+            _padding: [0; 7],
+        }
+    }
+
+    /// Return slots elapsed since given slot
+    pub fn slots_elapsed(&self, slot: Slot) -> std::result::Result<u64, ProgramError> {
+        let slots_elapsed = slot
+            .checked_sub(self.slot)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(slots_elapsed)
+    }
+
+    /// Set last update slot
+    pub fn update_slot(&mut self, slot: Slot) {
+        self.slot = slot;
+        self.stale = 0;
+    }
+
+    /// Set stale to true
+    pub fn mark_stale(&mut self) {
+        self.stale = 1;
+    }
+
+    /// Check if marked stale or last update slot is too long ago
+    pub fn is_stale(&self, slot: Slot) -> std::result::Result<bool, ProgramError> {
+        Ok(self.stale != 0 || self.slots_elapsed(slot)? >= STALE_AFTER_SLOTS_ELAPSED)
+    }
+}
@@ -1,6 +1,24 @@
 use super::*;
 use anchor_lang::prelude::*;
 
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryMul},
+};
+
+/// Default percentage of a borrow that can be repaid in a single liquidation
+pub const LIQUIDATION_CLOSE_FACTOR: u8 = 20;
+
+/// Default borrow value in the quote currency below which a borrow can be liquidated in full
+pub const LIQUIDATION_DUST_THRESHOLD: u64 = 1;
+
+/// Default number of slots after which an oracle price is too old to refresh a reserve with,
+/// about a minute and a half
+pub const ORACLE_MAX_STALENESS_SLOTS: u64 = 240;
+
+/// Default max width of an oracle price confidence interval, as a percentage of the price
+pub const ORACLE_MAX_CONFIDENCE_PCT: u8 = 10;
+
 /// Lending market state
 /// 2024-09-10 may need to bring/drive more attributes later.
 #[account]
@@ -23,6 +41,19 @@ pub struct LendingMarket {
     pub whitelisted_liquidator: Option<Pubkey>,
     /// risk authority (additional pubkey used for setting params)
     pub risk_authority: Pubkey,
+    /// Max percentage of a borrow that can be repaid in a single liquidation
+    pub liquidation_close_factor: u8,
+    /// Borrow value in the quote currency below which a borrow can be liquidated in full
+    pub liquidation_dust_threshold: u64,
+    /// Number of slots after which an oracle price is too old to refresh a reserve with
+    pub oracle_max_staleness_slots: u64,
+    /// Max width of an oracle price confidence interval, as a percentage of the price
+    pub oracle_max_confidence_pct: u8,
+    /// Efficiency mode categories, category id `n` is stored at index `n - 1`
+    pub emode_categories: [EModeCategory; MAX_EMODE_CATEGORIES],
+    /// Slots over which the liquidation bonus of an unhealthy obligation rises from zero to the
+    /// max bonus, Dutch auction style. 0 uses the fixed bonus scaled by the obligation's health
+    pub liquidation_auction_slots: u64,
 }
 
 impl LendingMarket {
@@ -43,6 +74,84 @@ impl LendingMarket {
         self.rate_limiter = RateLimiter::default(); // 2024-09-10 commented out temporarily before RateLimiter implementation imports
         self.whitelisted_liquidator = None;
         self.risk_authority = params.owner;
+        self.liquidation_close_factor = LIQUIDATION_CLOSE_FACTOR;
+        self.liquidation_dust_threshold = LIQUIDATION_DUST_THRESHOLD;
+        self.oracle_max_staleness_slots = ORACLE_MAX_STALENESS_SLOTS;
+        self.oracle_max_confidence_pct = ORACLE_MAX_CONFIDENCE_PCT;
+        self.emode_categories = [EModeCategory::default(); MAX_EMODE_CATEGORIES];
+        self.liquidation_auction_slots = 0;
+    }
+
+    /// Signer seeds of the lending market address, which is the authority of the reserve supply
+    /// accounts and collateral mints
+    pub fn signer_seeds(&self) -> [&[u8]; 2] {
+        [self.owner.as_ref(), std::slice::from_ref(&self.bump_seed)]
+    }
+
+    /// Check that the signer is the owner or the risk authority of the lending market
+    pub fn validate_risk_authority(&self, signer: &Pubkey) -> std::result::Result<(), ProgramError> {
+        if *signer != self.owner && *signer != self.risk_authority {
+            msg!("Signer must be the lending market owner or risk authority");
+            return Err(LendingError::InvalidMarketOwner.into());
+        }
+
+        Ok(())
+    }
+
+    /// Max amount of a borrow that can be repaid in a single liquidation. Borrows whose market
+    /// value is at or below the dust threshold can be liquidated in full, others are limited to
+    /// the close factor.
+    pub fn max_liquidation_amount(
+        &self,
+        borrowed_amount_wads: Decimal,
+        borrow_market_value: Decimal,
+    ) -> std::result::Result<Decimal, ProgramError> {
+        if borrow_market_value <= Decimal::from(self.liquidation_dust_threshold) {
+            return Ok(borrowed_amount_wads);
+        }
+
+        let max_amount = borrowed_amount_wads
+            .try_mul(Decimal::from_percent(self.liquidation_close_factor))?;
+        if max_amount == Decimal::zero() {
+            msg!("Liquidation close factor leaves nothing to liquidate");
+            return Err(LendingError::LiquidationTooSmall.into());
+        }
+
+        Ok(max_amount)
+    }
+
+    /// Get an e-mode category by id. Returns None for id 0 (no e-mode) and unset categories
+    pub fn emode_category(&self, id: u8) -> Option<&EModeCategory> {
+        if id == 0 {
+            return None;
+        }
+        self.emode_categories
+            .get(id as usize - 1)
+            .filter(|category| category.is_set())
+    }
+}
+
+/// Max number of e-mode categories in a lending market
+pub const MAX_EMODE_CATEGORIES: usize = 8;
+
+/// Efficiency mode category
+///
+/// Groups correlated reserves (e.g. USD stablecoins, SOL LSTs). An obligation opted into a
+/// category whose borrows and collateral are all in that category gets the elevated ratios below.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct EModeCategory {
+    /// Elevated loan to value ratio, as a percentage
+    pub loan_to_value_ratio: u8,
+    /// Elevated liquidation threshold, as a percentage. 0 if the category is unset
+    pub liquidation_threshold: u8,
+    /// Category label, null padded
+    pub label: [u8; 32],
+}
+
+impl EModeCategory {
+    /// Whether the category has been configured
+    pub fn is_set(&self) -> bool {
+        self.liquidation_threshold != 0
     }
 }
 
@@ -60,3 +169,68 @@ pub struct InitLendingMarketParams {
 }
 // 2024-09-10 commented out temporarily since anchor can handle below storage size calculation
 // const LENDING_MARKET_LEN: usize = 290; // 1 + 1 + 32 + 32 + 32 + 32 + 32 + 56 + 32 + 40
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lending_market() -> LendingMarket {
+        LendingMarket::new(InitLendingMarketParams {
+            bump_seed: 255,
+            owner: Pubkey::new_unique(),
+            quote_currency: [0u8; 32],
+            token_program_id: Pubkey::new_unique(),
+        })
+    }
+
+    #[test]
+    fn test_max_liquidation_amount_close_factor() {
+        let lending_market = lending_market();
+
+        // 20% of a borrow worth more than the dust threshold
+        assert_eq!(
+            lending_market
+                .max_liquidation_amount(Decimal::from(1_000u64), Decimal::from(500u64))
+                .unwrap(),
+            Decimal::from(200u64)
+        );
+    }
+
+    #[test]
+    fn test_max_liquidation_amount_dust_closes_in_full() {
+        let mut lending_market = lending_market();
+        lending_market.liquidation_dust_threshold = 10;
+
+        // at and below the dust threshold the whole borrow can be repaid
+        assert_eq!(
+            lending_market
+                .max_liquidation_amount(Decimal::from(1_000u64), Decimal::from(10u64))
+                .unwrap(),
+            Decimal::from(1_000u64)
+        );
+        assert_eq!(
+            lending_market
+                .max_liquidation_amount(Decimal::from(1_000u64), Decimal::from(1u64))
+                .unwrap(),
+            Decimal::from(1_000u64)
+        );
+        // just above it the close factor applies again
+        assert_eq!(
+            lending_market
+                .max_liquidation_amount(Decimal::from(1_000u64), Decimal::from(11u64))
+                .unwrap(),
+            Decimal::from(200u64)
+        );
+    }
+
+    #[test]
+    fn test_max_liquidation_amount_rejects_zero() {
+        let lending_market = lending_market();
+
+        // 20% of the smallest representable borrow rounds down to zero
+        assert_eq!(
+            lending_market.max_liquidation_amount(Decimal::from_scaled_val(1), Decimal::from(500u64)),
+            Err(LendingError::LiquidationTooSmall.into())
+        );
+    }
+}
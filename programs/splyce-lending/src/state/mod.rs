@@ -1,8 +1,25 @@
+use solana_program::clock::{DEFAULT_TICKS_PER_SECOND, DEFAULT_TICKS_PER_SLOT, SECONDS_PER_DAY};
+
+mod last_update;
 mod lending_market;
+mod obligation;
+mod obligation_order;
+mod rate_history;
 mod rate_limiter;
+mod reserve;
 
+pub use last_update::*;
 pub use lending_market::*;
+pub use obligation::*;
+pub use obligation_order::*;
+pub use rate_history::*;
 pub use rate_limiter::*;
+pub use reserve::*;
 
 /// Current version of the program and all new accounts created
-pub const PROGRAM_VERSION: u8 = 1;
\ No newline at end of file
+pub const PROGRAM_VERSION: u8 = 1;
+
+/// Number of slots per year
+// 2 (slots per second) * 60 * 60 * 24 * 365 = 63072000
+pub const SLOTS_PER_YEAR: u64 =
+    DEFAULT_TICKS_PER_SECOND / DEFAULT_TICKS_PER_SLOT * SECONDS_PER_DAY * 365;
@@ -0,0 +1,617 @@
+use super::*;
+use anchor_lang::prelude::*;
+use bytemuck::Zeroable;
+use solana_program::slot_history::Slot;
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, Rate, SaturatingSub, TryAdd, TryDiv, TryMul, TrySub},
+};
+use std::cmp::{min, Ordering};
+
+/// Max number of collateral and liquidity reserve accounts combined for an obligation
+pub const MAX_OBLIGATION_RESERVES: usize = 10;
+
+/// Max number of delegates of an obligation
+pub const MAX_OBLIGATION_DELEGATES: usize = 4;
+
+/// Delegate may deposit collateral into the obligation
+pub const DELEGATE_PERMISSION_DEPOSIT: u8 = 1 << 0;
+/// Delegate may repay the obligation's borrows. Repaying is open to anyone, the bit only
+/// documents the delegate's intended scope.
+pub const DELEGATE_PERMISSION_REPAY: u8 = 1 << 1;
+/// Delegate may withdraw collateral from the obligation
+pub const DELEGATE_PERMISSION_WITHDRAW: u8 = 1 << 2;
+/// Delegate may borrow against the obligation
+pub const DELEGATE_PERMISSION_BORROW: u8 = 1 << 3;
+/// All delegate permissions
+pub const DELEGATE_PERMISSION_ALL: u8 = DELEGATE_PERMISSION_DEPOSIT
+    | DELEGATE_PERMISSION_REPAY
+    | DELEGATE_PERMISSION_WITHDRAW
+    | DELEGATE_PERMISSION_BORROW;
+
+/// Lending market obligation state
+///
+/// Zero-copy account: deposits, borrows and delegates are fixed size arrays of which only the
+/// first `*_len` entries are in use, read them through `deposits()`, `borrows()` and
+/// `delegates()`.
+#[account(zero_copy)]
+pub struct Obligation {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for derived obligation address
+    pub bump_seed: u8,
+    /// E-mode category the obligation opted into, 0 if none
+    pub emode_category: u8,
+    /// 1 if the obligation is currently borrowing an isolated tier asset
+    pub borrowing_isolated_asset: u8,
+    /// 1 if the obligation holds a deposit of an isolated collateral tier asset
+    pub has_isolated_collateral: u8,
+    /// 1 if the obligation can be unwound by liquidators even when healthy, set when the borrow
+    /// attribution limit of one of its collateral reserves is exceeded, cleared by the next
+    /// refresh once none is
+    pub closeable: u8,
+    /// Number of deposits in use
+    deposits_len: u8,
+    /// Number of borrows in use
+    borrows_len: u8,
+    /// Number of delegates in use
+    delegates_len: u8,
+    pub _padding: [u8; 7],
+    /// Seed of the derived obligation address, chosen at creation. Independent of the owner so
+    /// the obligation keeps its address when transferred.
+    pub seed: Pubkey,
+    /// Last update to collateral, liquidity, or their market values
+    pub last_update: LastUpdate,
+    /// Slot of the first refresh that found the obligation unhealthy, 0 while healthy. Starts
+    /// the Dutch auction liquidation bonus
+    pub unhealthy_since_slot: Slot,
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Owner authority which can borrow liquidity
+    pub owner: Pubkey,
+    /// New owner proposed by the owner, who must accept the transfer. Default pubkey if none.
+    pub pending_owner: Pubkey,
+    /// Deposited collateral for the obligation, unique by deposit reserve address
+    deposits: [ObligationCollateral; MAX_OBLIGATION_RESERVES],
+    /// Borrowed liquidity for the obligation, unique by borrow reserve address
+    borrows: [ObligationLiquidity; MAX_OBLIGATION_RESERVES],
+    /// Market value of deposits
+    pub deposited_value: Decimal,
+    /// Risk-adjusted market value of borrows, ie the sum of each borrow's market value times
+    /// its reserve's borrow weight. Used for borrow and liquidation checks.
+    pub borrowed_value: Decimal,
+    /// Market value of borrows, not adjusted by borrow weights. Used for display.
+    pub unweighted_borrowed_value: Decimal,
+    /// The maximum borrow value at the weighted average loan to value ratio
+    pub allowed_borrow_value: Decimal,
+    /// The dangerous borrow value at the weighted average liquidation threshold
+    pub unhealthy_borrow_value: Decimal,
+    /// Borrow value at the weighted average max liquidation threshold, where the liquidation
+    /// bonus reaches its max
+    pub super_unhealthy_borrow_value: Decimal,
+    /// Keys allowed to act on the obligation on behalf of the owner, unique by delegate
+    delegates: [ObligationDelegate; MAX_OBLIGATION_DELEGATES],
+}
+
+impl Obligation {
+    /// Initialize an obligation
+    pub fn init(&mut self, params: InitObligationParams) {
+        *self = Self::zeroed();
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = params.bump_seed;
+        self.seed = params.seed;
+        self.last_update = LastUpdate::new(params.current_slot);
+        self.lending_market = params.lending_market;
+        self.owner = params.owner;
+    }
+
+    /// Deposited collateral for the obligation
+    pub fn deposits(&self) -> &[ObligationCollateral] {
+        &self.deposits[..self.deposits_len as usize]
+    }
+
+    /// Deposited collateral for the obligation mut
+    pub fn deposits_mut(&mut self) -> &mut [ObligationCollateral] {
+        &mut self.deposits[..self.deposits_len as usize]
+    }
+
+    /// Borrowed liquidity for the obligation
+    pub fn borrows(&self) -> &[ObligationLiquidity] {
+        &self.borrows[..self.borrows_len as usize]
+    }
+
+    /// Borrowed liquidity for the obligation mut
+    pub fn borrows_mut(&mut self) -> &mut [ObligationLiquidity] {
+        &mut self.borrows[..self.borrows_len as usize]
+    }
+
+    /// Delegates of the obligation
+    pub fn delegates(&self) -> &[ObligationDelegate] {
+        &self.delegates[..self.delegates_len as usize]
+    }
+
+    /// Revoke all delegates
+    pub fn clear_delegates(&mut self) {
+        self.delegates = [ObligationDelegate::zeroed(); MAX_OBLIGATION_DELEGATES];
+        self.delegates_len = 0;
+    }
+
+    /// Check that `authority` is the owner, or a delegate holding `permission` at `slot`
+    pub fn validate_authority(
+        &self,
+        authority: &Pubkey,
+        permission: u8,
+        slot: Slot,
+    ) -> std::result::Result<(), ProgramError> {
+        if *authority == self.owner {
+            return Ok(());
+        }
+        match self.delegates().iter().find(|delegate| delegate.delegate == *authority) {
+            Some(delegate) if delegate.has_permission(permission, slot) => Ok(()),
+            Some(_) => {
+                msg!("Obligation delegate lacks the permission or has expired");
+                Err(LendingError::InvalidObligationOwner.into())
+            }
+            None => {
+                msg!("Obligation owner does not match the obligation owner provided");
+                Err(LendingError::InvalidObligationOwner.into())
+            }
+        }
+    }
+
+    /// Add or update a delegate, or remove it when `permissions` is 0
+    pub fn set_delegate(
+        &mut self,
+        delegate: Pubkey,
+        permissions: u8,
+        expiry_slot: Slot,
+    ) -> std::result::Result<(), ProgramError> {
+        let index = self.delegates().iter().position(|d| d.delegate == delegate);
+        match (index, permissions) {
+            (Some(index), 0) => {
+                remove_entry(&mut self.delegates, &mut self.delegates_len, index);
+            }
+            (None, 0) => {}
+            (Some(index), _) => {
+                self.delegates[index].permissions = permissions;
+                self.delegates[index].expiry_slot = expiry_slot;
+            }
+            (None, _) => {
+                if self.delegates_len as usize >= MAX_OBLIGATION_DELEGATES {
+                    msg!(
+                        "Obligation cannot have more than {} delegates",
+                        MAX_OBLIGATION_DELEGATES
+                    );
+                    return Err(LendingError::ObligationDelegateLimit.into());
+                }
+                self.delegates[self.delegates_len as usize] = ObligationDelegate {
+                    delegate,
+                    expiry_slot,
+                    permissions,
+                    _padding: [0; 7],
+                };
+                self.delegates_len += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Withdraw collateral and remove it from deposits if zeroed out
+    pub fn withdraw(
+        &mut self,
+        withdraw_amount: u64,
+        collateral_index: usize,
+    ) -> std::result::Result<(), ProgramError> {
+        let collateral = &mut self.deposits[collateral_index];
+        if withdraw_amount == collateral.deposited_amount {
+            remove_entry(&mut self.deposits, &mut self.deposits_len, collateral_index);
+        } else {
+            collateral.withdraw(withdraw_amount)?;
+        }
+        Ok(())
+    }
+
+    /// Repay liquidity and remove it from borrows if zeroed out
+    pub fn repay(
+        &mut self,
+        settle_amount: Decimal,
+        liquidity_index: usize,
+    ) -> std::result::Result<(), ProgramError> {
+        let liquidity = &mut self.borrows[liquidity_index];
+        if settle_amount == liquidity.borrowed_amount_wads {
+            remove_entry(&mut self.borrows, &mut self.borrows_len, liquidity_index);
+        } else {
+            liquidity.repay(settle_amount)?;
+        }
+        Ok(())
+    }
+
+    /// Calculate the maximum collateral amount that can be withdrawn from `withdraw_reserve`
+    /// while keeping the borrowed value under the allowed borrow value
+    pub fn max_withdraw_amount(
+        &self,
+        collateral: &ObligationCollateral,
+        withdraw_reserve: &Reserve,
+        lending_market: &LendingMarket,
+    ) -> std::result::Result<u64, ProgramError> {
+        if self.borrows().is_empty() {
+            return Ok(collateral.deposited_amount);
+        }
+
+        if self.allowed_borrow_value <= self.borrowed_value {
+            return Ok(0);
+        }
+
+        // isolated collateral does not back any borrow
+        let loan_to_value_ratio =
+            if withdraw_reserve.config.reserve_type()? == ReserveType::IsolatedCollateral {
+                Rate::zero()
+            } else {
+                withdraw_reserve.loan_to_value_rate(lending_market, self.emode_category)
+            };
+        if loan_to_value_ratio == Rate::zero() {
+            return Ok(collateral.deposited_amount);
+        }
+
+        // max quote currency value that can be withdrawn
+        let max_withdraw_value = self
+            .allowed_borrow_value
+            .try_sub(self.borrowed_value)?
+            .try_div(loan_to_value_ratio)?;
+
+        // convert max_withdraw_value to max withdraw liquidity amount
+        let max_withdraw_liquidity_amount = max_withdraw_value
+            .try_mul(withdraw_reserve.liquidity.decimals_factor()?)?
+            .try_div(withdraw_reserve.liquidity.market_price)?;
+
+        // convert max withdraw liquidity amount to max withdraw collateral amount
+        Ok(min(
+            withdraw_reserve
+                .collateral_exchange_rate()?
+                .decimal_liquidity_to_collateral(max_withdraw_liquidity_amount)?
+                .try_floor_u64()?,
+            collateral.deposited_amount,
+        ))
+    }
+
+    /// Calculate the maximum liquidity value that can be borrowed
+    pub fn remaining_borrow_value(&self) -> Decimal {
+        self.allowed_borrow_value.saturating_sub(self.borrowed_value)
+    }
+
+    /// Find collateral by deposit reserve
+    pub fn find_collateral_in_deposits(
+        &self,
+        deposit_reserve: Pubkey,
+    ) -> std::result::Result<(&ObligationCollateral, usize), ProgramError> {
+        if self.deposits().is_empty() {
+            msg!("Obligation has no deposits");
+            return Err(LendingError::ObligationDepositsEmpty.into());
+        }
+        let collateral_index = self
+            ._find_collateral_index_in_deposits(deposit_reserve)
+            .ok_or(LendingError::InvalidObligationCollateral)?;
+        Ok((&self.deposits[collateral_index], collateral_index))
+    }
+
+    /// Find or add collateral by deposit reserve
+    pub fn find_or_add_collateral_to_deposits(
+        &mut self,
+        deposit_reserve: Pubkey,
+    ) -> std::result::Result<&mut ObligationCollateral, ProgramError> {
+        if let Some(collateral_index) = self._find_collateral_index_in_deposits(deposit_reserve) {
+            return Ok(&mut self.deposits[collateral_index]);
+        }
+        if (self.deposits_len + self.borrows_len) as usize >= MAX_OBLIGATION_RESERVES {
+            msg!(
+                "Obligation cannot have more than {} deposits and borrows combined",
+                MAX_OBLIGATION_RESERVES
+            );
+            return Err(LendingError::ObligationReserveLimit.into());
+        }
+        let collateral_index = self.deposits_len as usize;
+        self.deposits[collateral_index] = ObligationCollateral::new(deposit_reserve);
+        self.deposits_len += 1;
+        Ok(&mut self.deposits[collateral_index])
+    }
+
+    fn _find_collateral_index_in_deposits(&self, deposit_reserve: Pubkey) -> Option<usize> {
+        self.deposits()
+            .iter()
+            .position(|collateral| collateral.deposit_reserve == deposit_reserve)
+    }
+
+    /// Find liquidity by borrow reserve
+    pub fn find_liquidity_in_borrows(
+        &self,
+        borrow_reserve: Pubkey,
+    ) -> std::result::Result<(&ObligationLiquidity, usize), ProgramError> {
+        if self.borrows().is_empty() {
+            msg!("Obligation has no borrows");
+            return Err(LendingError::ObligationBorrowsEmpty.into());
+        }
+        let liquidity_index = self
+            ._find_liquidity_index_in_borrows(borrow_reserve)
+            .ok_or(LendingError::InvalidObligationLiquidity)?;
+        Ok((&self.borrows[liquidity_index], liquidity_index))
+    }
+
+    /// Find liquidity by borrow reserve mut
+    pub fn find_liquidity_in_borrows_mut(
+        &mut self,
+        borrow_reserve: Pubkey,
+    ) -> std::result::Result<(&mut ObligationLiquidity, usize), ProgramError> {
+        if self.borrows().is_empty() {
+            msg!("Obligation has no borrows");
+            return Err(LendingError::ObligationBorrowsEmpty.into());
+        }
+        let liquidity_index = self
+            ._find_liquidity_index_in_borrows(borrow_reserve)
+            .ok_or(LendingError::InvalidObligationLiquidity)?;
+        Ok((&mut self.borrows[liquidity_index], liquidity_index))
+    }
+
+    /// Find or add liquidity by borrow reserve
+    pub fn find_or_add_liquidity_to_borrows(
+        &mut self,
+        borrow_reserve: Pubkey,
+        cumulative_borrow_rate_wads: Decimal,
+    ) -> std::result::Result<&mut ObligationLiquidity, ProgramError> {
+        if let Some(liquidity_index) = self._find_liquidity_index_in_borrows(borrow_reserve) {
+            return Ok(&mut self.borrows[liquidity_index]);
+        }
+        if (self.deposits_len + self.borrows_len) as usize >= MAX_OBLIGATION_RESERVES {
+            msg!(
+                "Obligation cannot have more than {} deposits and borrows combined",
+                MAX_OBLIGATION_RESERVES
+            );
+            return Err(LendingError::ObligationReserveLimit.into());
+        }
+        let liquidity_index = self.borrows_len as usize;
+        self.borrows[liquidity_index] =
+            ObligationLiquidity::new(borrow_reserve, cumulative_borrow_rate_wads);
+        self.borrows_len += 1;
+        Ok(&mut self.borrows[liquidity_index])
+    }
+
+    fn _find_liquidity_index_in_borrows(&self, borrow_reserve: Pubkey) -> Option<usize> {
+        self.borrows()
+            .iter()
+            .position(|liquidity| liquidity.borrow_reserve == borrow_reserve)
+    }
+}
+
+/// Initialize an obligation
+pub struct InitObligationParams {
+    /// Last update to collateral, liquidity, or their market values
+    pub current_slot: Slot,
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Owner authority which can borrow liquidity
+    pub owner: Pubkey,
+    /// Seed of the derived obligation address
+    pub seed: Pubkey,
+    /// Bump seed for derived obligation address
+    pub bump_seed: u8,
+}
+
+/// Remove entry `index` out of the first `len` entries in use, shifting the following ones down
+/// to keep them contiguous and zeroing the freed slot
+fn remove_entry<T: Zeroable + Copy>(entries: &mut [T], len: &mut u8, index: usize) {
+    let end = *len as usize;
+    entries.copy_within(index + 1..end, index);
+    entries[end - 1] = T::zeroed();
+    *len -= 1;
+}
+
+/// Obligation collateral state
+#[zero_copy]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ObligationCollateral {
+    /// Reserve collateral is deposited to
+    pub deposit_reserve: Pubkey,
+    /// Amount of collateral deposited
+    pub deposited_amount: u64,
+    /// Collateral market value in quote currency
+    pub market_value: Decimal,
+    /// Part of the obligation's borrowed value attributed to this collateral, proportional to
+    /// its share of the deposited value
+    pub attributed_borrow_value: Decimal,
+}
+
+impl ObligationCollateral {
+    /// Create new obligation collateral
+    pub fn new(deposit_reserve: Pubkey) -> Self {
+        Self {
+            deposit_reserve,
+            deposited_amount: 0,
+            market_value: Decimal::zero(),
+            attributed_borrow_value: Decimal::zero(),
+        }
+    }
+
+    /// Increase deposited collateral
+    pub fn deposit(&mut self, collateral_amount: u64) -> std::result::Result<(), ProgramError> {
+        self.deposited_amount = self
+            .deposited_amount
+            .checked_add(collateral_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Decrease deposited collateral
+    pub fn withdraw(&mut self, collateral_amount: u64) -> std::result::Result<(), ProgramError> {
+        self.deposited_amount = self
+            .deposited_amount
+            .checked_sub(collateral_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+/// Obligation liquidity state
+#[zero_copy]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ObligationLiquidity {
+    /// Reserve liquidity is borrowed from
+    pub borrow_reserve: Pubkey,
+    /// Borrow rate used for calculating interest
+    pub cumulative_borrow_rate_wads: Decimal,
+    /// Amount of liquidity borrowed plus interest
+    pub borrowed_amount_wads: Decimal,
+    /// Liquidity market value in quote currency
+    pub market_value: Decimal,
+}
+
+impl ObligationLiquidity {
+    /// Create new obligation liquidity
+    pub fn new(borrow_reserve: Pubkey, cumulative_borrow_rate_wads: Decimal) -> Self {
+        Self {
+            borrow_reserve,
+            cumulative_borrow_rate_wads,
+            borrowed_amount_wads: Decimal::zero(),
+            market_value: Decimal::zero(),
+        }
+    }
+
+    /// Increase borrowed liquidity
+    pub fn borrow(&mut self, borrow_amount: Decimal) -> std::result::Result<(), ProgramError> {
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(borrow_amount)?;
+        Ok(())
+    }
+
+    /// Decrease borrowed liquidity
+    pub fn repay(&mut self, settle_amount: Decimal) -> std::result::Result<(), ProgramError> {
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(settle_amount)?;
+        Ok(())
+    }
+
+    /// Accrue interest
+    pub fn accrue_interest(
+        &mut self,
+        cumulative_borrow_rate_wads: Decimal,
+    ) -> std::result::Result<(), ProgramError> {
+        match cumulative_borrow_rate_wads.cmp(&self.cumulative_borrow_rate_wads) {
+            Ordering::Less => {
+                msg!("Interest rate cannot be negative");
+                return Err(LendingError::NegativeInterestRate.into());
+            }
+            Ordering::Equal => {}
+            Ordering::Greater => {
+                let compounded_interest_rate: Rate = cumulative_borrow_rate_wads
+                    .try_div(self.cumulative_borrow_rate_wads)?
+                    .try_into()?;
+
+                self.borrowed_amount_wads = self
+                    .borrowed_amount_wads
+                    .try_mul(compounded_interest_rate)?;
+                self.cumulative_borrow_rate_wads = cumulative_borrow_rate_wads;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Key allowed to act on an obligation on behalf of its owner
+#[zero_copy]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ObligationDelegate {
+    /// Delegate authority
+    pub delegate: Pubkey,
+    /// Slot the delegation expires at, 0 if it never expires
+    pub expiry_slot: u64,
+    /// Bitmask of DELEGATE_PERMISSION_* flags
+    pub permissions: u8,
+    pub _padding: [u8; 7],
+}
+
+impl ObligationDelegate {
+    /// Whether the delegate holds `permission` and has not expired at `slot`
+    pub fn has_permission(&self, permission: u8, slot: Slot) -> bool {
+        self.permissions & permission == permission
+            && (self.expiry_slot == 0 || slot < self.expiry_slot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn obligation_with_borrows(borrowed_amounts: &[Decimal]) -> (Obligation, Vec<Pubkey>) {
+        let mut obligation = Obligation::zeroed();
+        let borrow_reserves: Vec<Pubkey> =
+            borrowed_amounts.iter().map(|_| Pubkey::new_unique()).collect();
+        for (borrow_reserve, borrowed_amount) in borrow_reserves.iter().zip(borrowed_amounts) {
+            obligation
+                .find_or_add_liquidity_to_borrows(*borrow_reserve, Decimal::one())
+                .unwrap()
+                .borrow(*borrowed_amount)
+                .unwrap();
+        }
+        (obligation, borrow_reserves)
+    }
+
+    #[test]
+    fn test_repay_partial_keeps_borrow() {
+        let borrowed_amount = Decimal::from(100_500u64).try_div(1_000u64).unwrap();
+        let (mut obligation, borrow_reserves) = obligation_with_borrows(&[borrowed_amount]);
+
+        obligation.repay(Decimal::from(40u64), 0).unwrap();
+
+        assert_eq!(obligation.borrows().len(), 1);
+        assert_eq!(obligation.borrows()[0].borrow_reserve, borrow_reserves[0]);
+        assert_eq!(
+            obligation.borrows()[0].borrowed_amount_wads,
+            Decimal::from(60_500u64).try_div(1_000u64).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_repay_full_removes_borrow() {
+        let borrowed_amount = Decimal::from(100_500u64).try_div(1_000u64).unwrap();
+        let (mut obligation, borrow_reserves) =
+            obligation_with_borrows(&[Decimal::from(10u64), borrowed_amount, Decimal::from(30u64)]);
+
+        obligation.repay(borrowed_amount, 1).unwrap();
+
+        // the following entries shift down and the freed slot is zeroed
+        assert_eq!(obligation.borrows().len(), 2);
+        assert_eq!(obligation.borrows()[0].borrow_reserve, borrow_reserves[0]);
+        assert_eq!(obligation.borrows()[1].borrow_reserve, borrow_reserves[2]);
+        assert_eq!(obligation.borrows()[1].borrowed_amount_wads, Decimal::from(30u64));
+        assert_eq!(obligation.borrows[2], ObligationLiquidity::default());
+    }
+
+    #[test]
+    fn test_validate_authority_checks_delegate_permission_and_expiry() {
+        let owner = Pubkey::new_unique();
+        let delegate = Pubkey::new_unique();
+        let mut obligation = Obligation::zeroed();
+        obligation.owner = owner;
+        obligation
+            .set_delegate(delegate, DELEGATE_PERMISSION_DEPOSIT | DELEGATE_PERMISSION_REPAY, 100)
+            .unwrap();
+
+        assert!(obligation.validate_authority(&owner, DELEGATE_PERMISSION_BORROW, 200).is_ok());
+        assert!(obligation.validate_authority(&delegate, DELEGATE_PERMISSION_DEPOSIT, 99).is_ok());
+        assert_eq!(
+            obligation.validate_authority(&delegate, DELEGATE_PERMISSION_BORROW, 99),
+            Err(LendingError::InvalidObligationOwner.into())
+        );
+        // expired at its expiry slot
+        assert_eq!(
+            obligation.validate_authority(&delegate, DELEGATE_PERMISSION_DEPOSIT, 100),
+            Err(LendingError::InvalidObligationOwner.into())
+        );
+
+        // permissions of 0 revoke the delegate
+        obligation.set_delegate(delegate, 0, 0).unwrap();
+        assert!(obligation.delegates().is_empty());
+        assert_eq!(
+            obligation.validate_authority(&delegate, DELEGATE_PERMISSION_DEPOSIT, 99),
+            Err(LendingError::InvalidObligationOwner.into())
+        );
+    }
+}
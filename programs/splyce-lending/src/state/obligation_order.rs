@@ -0,0 +1,137 @@
+use super::*;
+use anchor_lang::prelude::*;
+use anchor_lang::prelude::borsh;
+
+use crate::math::{Decimal, TryDiv};
+
+/// Seed prefix of obligation order addresses, followed by the obligation and the order id
+pub const OBLIGATION_ORDER_SEED: &[u8] = b"obligation_order";
+
+/// Condition under which an obligation order can be executed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub enum OrderTrigger {
+    /// Obligation loan to value, its borrowed value over its deposited value, at or above the
+    /// trigger value. Stop-loss on the obligation's health.
+    #[default]
+    LtvAbove,
+    /// Withdraw reserve liquidity market price at or below the trigger value. Stop-loss on the
+    /// collateral price.
+    PriceBelow,
+    /// Withdraw reserve liquidity market price at or above the trigger value. Take-profit on the
+    /// collateral price.
+    PriceAbove,
+}
+
+/// Deleveraging order attached to an obligation
+///
+/// Once the trigger is met, any keeper can execute the order: the keeper repays the borrow from
+/// its own liquidity and receives the repaid value out of the withdrawn collateral, redeemed into
+/// the withdraw reserve liquidity, and the tip escrowed in the order account. The rest of the
+/// redeemed liquidity goes to the owner. Orders are executed once.
+#[account]
+#[derive(Default, InitSpace)]
+pub struct ObligationOrder {
+    /// Version of the struct
+    pub version: u8,
+    /// Bump seed for derived order address
+    pub bump_seed: u8,
+    /// Order id, unique per obligation
+    pub order_id: u8,
+    /// Obligation the order applies to
+    pub obligation: Pubkey,
+    /// Obligation owner who placed the order, receives the rent back
+    pub owner: Pubkey,
+    /// Condition under which the order can be executed
+    pub trigger: OrderTrigger,
+    /// Loan to value ratio or market price in quote currency the trigger compares against
+    pub trigger_value: Decimal,
+    /// Reserve the collateral is withdrawn from, its price is the one price triggers watch
+    pub withdraw_reserve: Pubkey,
+    /// Collateral amount to withdraw, u64::MAX for the whole deposit
+    pub withdraw_collateral_amount: u64,
+    /// Reserve whose borrow is repaid
+    pub repay_reserve: Pubkey,
+    /// Liquidity amount to repay, u64::MAX for the whole borrow
+    pub repay_liquidity_amount: u64,
+    /// Lamports paid to the keeper executing the order, escrowed in the order account
+    pub keeper_tip: u64,
+}
+
+impl ObligationOrder {
+    /// Initialize an obligation order
+    pub fn init(&mut self, params: InitObligationOrderParams) {
+        self.version = PROGRAM_VERSION;
+        self.bump_seed = params.bump_seed;
+        self.order_id = params.order_id;
+        self.obligation = params.obligation;
+        self.owner = params.owner;
+        self.trigger = params.trigger;
+        self.trigger_value = params.trigger_value;
+        self.withdraw_reserve = params.withdraw_reserve;
+        self.withdraw_collateral_amount = params.withdraw_collateral_amount;
+        self.repay_reserve = params.repay_reserve;
+        self.repay_liquidity_amount = params.repay_liquidity_amount;
+        self.keeper_tip = params.keeper_tip;
+    }
+
+    /// Whether the trigger is met by the freshly refreshed `obligation` and `withdraw_reserve`
+    pub fn is_triggered(
+        &self,
+        obligation: &Obligation,
+        withdraw_reserve: &Reserve,
+    ) -> std::result::Result<bool, ProgramError> {
+        match self.trigger {
+            OrderTrigger::LtvAbove => Ok(loan_to_value(
+                obligation.borrowed_value,
+                obligation.deposited_value,
+            )? >= self.trigger_value),
+            OrderTrigger::PriceBelow => {
+                Ok(withdraw_reserve.liquidity.market_price <= self.trigger_value)
+            }
+            OrderTrigger::PriceAbove => {
+                Ok(withdraw_reserve.liquidity.market_price >= self.trigger_value)
+            }
+        }
+    }
+}
+
+/// Loan to value of an obligation with `borrowed_value` and `deposited_value`. Borrows without
+/// any deposited value are at u64::MAX.
+pub fn loan_to_value(
+    borrowed_value: Decimal,
+    deposited_value: Decimal,
+) -> std::result::Result<Decimal, ProgramError> {
+    if borrowed_value == Decimal::zero() {
+        Ok(Decimal::zero())
+    } else if deposited_value == Decimal::zero() {
+        Ok(Decimal::from(u64::MAX))
+    } else {
+        borrowed_value.try_div(deposited_value)
+    }
+}
+
+/// Initialize an obligation order
+pub struct InitObligationOrderParams {
+    /// Bump seed for derived order address
+    pub bump_seed: u8,
+    /// Order id, unique per obligation
+    pub order_id: u8,
+    /// Obligation the order applies to
+    pub obligation: Pubkey,
+    /// Obligation owner who placed the order
+    pub owner: Pubkey,
+    /// Condition under which the order can be executed
+    pub trigger: OrderTrigger,
+    /// Loan to value ratio or market price the trigger compares against
+    pub trigger_value: Decimal,
+    /// Reserve the collateral is withdrawn from
+    pub withdraw_reserve: Pubkey,
+    /// Collateral amount to withdraw
+    pub withdraw_collateral_amount: u64,
+    /// Reserve whose borrow is repaid
+    pub repay_reserve: Pubkey,
+    /// Liquidity amount to repay
+    pub repay_liquidity_amount: u64,
+    /// Lamports paid to the executing keeper
+    pub keeper_tip: u64,
+}
@@ -0,0 +1,86 @@
+use anchor_lang::prelude::*;
+use bytemuck::{Pod, Zeroable};
+use solana_program::slot_history::Slot;
+
+use crate::math::Decimal;
+
+/// Number of snapshots kept in a reserve's rate history
+pub const RATE_HISTORY_LEN: usize = 16;
+
+/// Interest rate snapshot of a reserve
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AnchorSerialize, AnchorDeserialize, InitSpace)]
+#[derive(Pod, Zeroable)]
+#[repr(C)]
+pub struct RateSnapshot {
+    /// Slot the snapshot was recorded at
+    pub slot: u64,
+    /// Utilization rate of the reserve liquidity
+    pub utilization_rate: Decimal,
+    /// Borrow APR
+    pub borrow_rate: Decimal,
+    /// Supply APR, net of the protocol take rate
+    pub supply_rate: Decimal,
+    /// Reserve liquidity cumulative borrow rate
+    pub cumulative_borrow_rate_wads: Decimal,
+}
+
+/// Fixed size ring buffer of interest rate snapshots, oldest entries are overwritten first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AnchorSerialize, AnchorDeserialize, InitSpace)]
+#[derive(Pod, Zeroable)]
+#[repr(C)]
+pub struct RateHistory {
+    /// Index the next snapshot is written to
+    head: u32,
+    /// Number of snapshots recorded, up to RATE_HISTORY_LEN
+    len: u32,
+    /// Snapshots
+    snapshots: [RateSnapshot; RATE_HISTORY_LEN],
+}
+
+impl RateHistory {
+    /// Number of snapshots recorded
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Whether no snapshot has been recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Most recent snapshot
+    pub fn last(&self) -> Option<&RateSnapshot> {
+        if self.is_empty() {
+            return None;
+        }
+        let index = (self.head as usize + RATE_HISTORY_LEN - 1) % RATE_HISTORY_LEN;
+        Some(&self.snapshots[index])
+    }
+
+    /// Whether a snapshot is due at `slot`, ie at least `interval` slots passed since the last one.
+    /// Recording is disabled when `interval` is 0.
+    pub fn is_due(&self, slot: Slot, interval: u64) -> bool {
+        if interval == 0 {
+            return false;
+        }
+        match self.last() {
+            Some(last) => slot.saturating_sub(last.slot) >= interval,
+            None => true,
+        }
+    }
+
+    /// Append a snapshot, overwriting the oldest one once the buffer is full
+    pub fn push(&mut self, snapshot: RateSnapshot) {
+        self.snapshots[self.head as usize] = snapshot;
+        self.head = ((self.head as usize + 1) % RATE_HISTORY_LEN) as u32;
+        if (self.len as usize) < RATE_HISTORY_LEN {
+            self.len += 1;
+        }
+    }
+
+    /// Snapshots from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &RateSnapshot> {
+        let start = (self.head as usize + RATE_HISTORY_LEN - self.len()) % RATE_HISTORY_LEN;
+        (0..self.len()).map(move |i| &self.snapshots[(start + i) % RATE_HISTORY_LEN])
+    }
+}
@@ -3,6 +3,7 @@ use solana_program::{slot_history::Slot};
 // use anchor_lang::prelude::{msg, AnchorSerialize, AnchorDeserialize};
 use anchor_lang::prelude::*;
 use anchor_lang::Space;
+use bytemuck::{Pod, Zeroable};
 
 
 use crate::{
@@ -15,6 +16,8 @@ use crate::{
 /// is less than 2x max_outflow.
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+#[derive(Pod, Zeroable)]
+#[repr(C)]
 pub struct RateLimiter {
     /// configuration parameters
     pub config: RateLimiterConfig,
@@ -30,6 +33,8 @@ pub struct RateLimiter {
 
 /// Lending market configuration parameters
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AnchorSerialize, AnchorDeserialize)]
+#[derive(Pod, Zeroable)]
+#[repr(C)]
 pub struct RateLimiterConfig {
     /// Rate limiter window size in slots
     pub window_duration: u64,
@@ -0,0 +1,1374 @@
+use super::*;
+use anchor_lang::prelude::*;
+use anchor_lang::prelude::borsh;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::slot_history::Slot;
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, Rate, TryAdd, TryDiv, TryMul, TrySub, WAD},
+};
+use std::cmp::{max, min, Ordering};
+
+/// Lending market reserve state
+///
+/// Zero-copy account: fields are ordered and padded for 8-byte alignment, and nested types are
+/// plain old data.
+#[account(zero_copy)]
+pub struct Reserve {
+    /// Version of the struct
+    pub version: u8,
+    /// Set while an instruction has the reserve out to another program, like the swap of a
+    /// leveraged position or the callback of a flash loan, guards against reentry
+    pub reentrancy_lock: u8,
+    pub _padding: [u8; 6],
+    /// Last slot when supply and rates updated
+    pub last_update: LastUpdate,
+    /// Lending market address
+    pub lending_market: Pubkey,
+    /// Reserve liquidity
+    pub liquidity: ReserveLiquidity,
+    /// Reserve collateral
+    pub collateral: ReserveCollateral,
+    /// Reserve configuration values
+    pub config: ReserveConfig,
+    /// Outflow Rate Limiter (denominated in tokens)
+    pub rate_limiter: RateLimiter,
+    /// Borrow value in the lending market quote currency attributed to this reserve's
+    /// collateral, summed over all obligations
+    pub attributed_borrow_value: Decimal,
+    /// Interest rate history, recorded by refresh_reserve
+    pub rate_history: RateHistory,
+}
+
+impl Reserve {
+    /// Calculate the current borrow rate
+    pub fn current_borrow_rate(&self) -> std::result::Result<Rate, ProgramError> {
+        let utilization_rate = self.liquidity.utilization_rate()?;
+        let optimal_utilization_rate = Rate::from_percent(self.config.optimal_utilization_rate);
+        let max_utilization_rate = Rate::from_percent(self.config.max_utilization_rate);
+
+        if utilization_rate <= optimal_utilization_rate {
+            let min_rate = Rate::from_percent(self.config.min_borrow_rate);
+
+            if optimal_utilization_rate == Rate::zero() {
+                return Ok(min_rate);
+            }
+
+            let normalized_rate = utilization_rate.try_div(optimal_utilization_rate)?;
+            let rate_range = Rate::from_percent(
+                self.config
+                    .optimal_borrow_rate
+                    .checked_sub(self.config.min_borrow_rate)
+                    .ok_or(LendingError::MathOverflow)?,
+            );
+
+            Ok(normalized_rate.try_mul(rate_range)?.try_add(min_rate)?)
+        } else if utilization_rate <= max_utilization_rate {
+            let weight = utilization_rate
+                .try_sub(optimal_utilization_rate)?
+                .try_div(max_utilization_rate.try_sub(optimal_utilization_rate)?)?;
+
+            let optimal_borrow_rate = Rate::from_percent(self.config.optimal_borrow_rate);
+            let max_borrow_rate = Rate::from_percent(self.config.max_borrow_rate);
+            let rate_range = max_borrow_rate.try_sub(optimal_borrow_rate)?;
+
+            weight.try_mul(rate_range)?.try_add(optimal_borrow_rate)
+        } else {
+            let weight = utilization_rate
+                .try_sub(max_utilization_rate)?
+                .try_div(Rate::from_percent(
+                    100u8
+                        .checked_sub(self.config.max_utilization_rate)
+                        .ok_or(LendingError::MathOverflow)?,
+                ))?;
+
+            let max_borrow_rate = Rate::from_percent(self.config.max_borrow_rate);
+            let super_max_borrow_rate = Rate::from_percent_u64(self.config.super_max_borrow_rate);
+            let rate_range = super_max_borrow_rate.try_sub(max_borrow_rate)?;
+
+            weight.try_mul(rate_range)?.try_add(max_borrow_rate)
+        }
+    }
+
+    /// Collateral exchange rate
+    pub fn collateral_exchange_rate(&self) -> std::result::Result<CollateralExchangeRate, ProgramError> {
+        let total_liquidity = self.liquidity.total_supply()?;
+        self.collateral.exchange_rate(total_liquidity)
+    }
+
+    /// Calculate the current supply rate, ie the borrow rate spread over the total supply and net
+    /// of the protocol take rate
+    pub fn current_supply_rate(&self) -> std::result::Result<Rate, ProgramError> {
+        let take_rate = Rate::from_percent(self.config.protocol_take_rate);
+        self.current_borrow_rate()?
+            .try_mul(self.liquidity.utilization_rate()?)?
+            .try_mul(Rate::one().try_sub(take_rate)?)
+    }
+
+    /// Update borrow rate and accrue interest
+    pub fn accrue_interest(&mut self, current_slot: Slot) -> std::result::Result<(), ProgramError> {
+        let slots_elapsed = self.last_update.slots_elapsed(current_slot)?;
+        if slots_elapsed > 0 {
+            let current_borrow_rate = self.current_borrow_rate()?;
+            let take_rate = Rate::from_percent(self.config.protocol_take_rate);
+            self.liquidity
+                .compound_interest(current_borrow_rate, slots_elapsed, take_rate)?;
+        }
+        Ok(())
+    }
+
+    /// Record an interest rate snapshot if at least `rate_history_interval` slots passed since
+    /// the last one
+    pub fn record_rate_snapshot(&mut self, current_slot: Slot) -> std::result::Result<(), ProgramError> {
+        if !self
+            .rate_history
+            .is_due(current_slot, self.config.rate_history_interval)
+        {
+            return Ok(());
+        }
+
+        self.rate_history.push(RateSnapshot {
+            slot: current_slot,
+            utilization_rate: self.liquidity.utilization_rate()?.into(),
+            borrow_rate: self.current_borrow_rate()?.into(),
+            supply_rate: self.current_supply_rate()?.into(),
+            cumulative_borrow_rate_wads: self.liquidity.cumulative_borrow_rate_wads,
+        });
+
+        Ok(())
+    }
+
+    /// Market value of a liquidity amount, denominated in the lending market quote currency
+    pub fn market_value(&self, liquidity_amount: Decimal) -> std::result::Result<Decimal, ProgramError> {
+        self.liquidity
+            .market_price
+            .try_mul(liquidity_amount)?
+            .try_div(self.liquidity.decimals_factor()?)
+    }
+
+    /// Borrow weight of the reserve. Always >= 1
+    pub fn borrow_weight(&self) -> Decimal {
+        self.config.borrow_weight()
+    }
+
+    /// Risk-adjusted market value of a borrowed liquidity amount, ie the market value scaled
+    /// by the borrow weight. Used for borrow and liquidation checks.
+    pub fn borrow_weighted_market_value(
+        &self,
+        liquidity_amount: Decimal,
+    ) -> std::result::Result<Decimal, ProgramError> {
+        self.market_value(liquidity_amount)?
+            .try_mul(self.borrow_weight())
+    }
+
+    /// E-mode category applying to this reserve for an obligation in `emode_category`, if any
+    pub fn emode_config<'a>(
+        &self,
+        lending_market: &'a LendingMarket,
+        emode_category: u8,
+    ) -> Option<&'a EModeCategory> {
+        if emode_category == 0 || emode_category != self.config.emode_category {
+            return None;
+        }
+        lending_market.emode_category(emode_category)
+    }
+
+    /// Loan to value ratio for an obligation in `emode_category`
+    pub fn loan_to_value_rate(&self, lending_market: &LendingMarket, emode_category: u8) -> Rate {
+        let ratio = match self.emode_config(lending_market, emode_category) {
+            Some(category) => category.loan_to_value_ratio.max(self.config.loan_to_value_ratio),
+            None => self.config.loan_to_value_ratio,
+        };
+        Rate::from_percent(ratio)
+    }
+
+    /// Liquidation threshold for an obligation in `emode_category`
+    pub fn liquidation_threshold_rate(
+        &self,
+        lending_market: &LendingMarket,
+        emode_category: u8,
+    ) -> Rate {
+        let threshold = match self.emode_config(lending_market, emode_category) {
+            Some(category) => category
+                .liquidation_threshold
+                .max(self.config.liquidation_threshold),
+            None => self.config.liquidation_threshold,
+        };
+        Rate::from_percent(threshold)
+    }
+
+    /// Max liquidation threshold, where the liquidation bonus reaches its max, for an obligation
+    /// in `emode_category`. Never below the liquidation threshold.
+    pub fn max_liquidation_threshold_rate(
+        &self,
+        lending_market: &LendingMarket,
+        emode_category: u8,
+    ) -> Rate {
+        max(
+            Rate::from_percent(self.config.max_liquidation_threshold),
+            self.liquidation_threshold_rate(lending_market, emode_category),
+        )
+    }
+
+    /// Check that the reserve can be used by an obligation in `emode_category`
+    pub fn validate_emode_category(&self, emode_category: u8) -> std::result::Result<(), ProgramError> {
+        if emode_category != 0 && emode_category != self.config.emode_category {
+            msg!(
+                "Reserve e-mode category {} does not match obligation e-mode category {}",
+                self.config.emode_category,
+                emode_category
+            );
+            return Err(LendingError::EModeCategoryViolation.into());
+        }
+
+        Ok(())
+    }
+
+    /// Calculate borrow result, with `u64::MAX` borrowing as much as `max_borrow_value` and
+    /// `remaining_reserve_borrow` allow
+    pub fn calculate_borrow(
+        &self,
+        amount_to_borrow: u64,
+        max_borrow_value: Decimal,
+        remaining_reserve_borrow: Decimal,
+    ) -> std::result::Result<CalculateBorrowResult, ProgramError> {
+        if amount_to_borrow == u64::MAX {
+            let borrow_amount = max_borrow_value
+                .try_mul(self.liquidity.decimals_factor()?)?
+                .try_div(self.liquidity.market_price.try_mul(self.borrow_weight())?)?
+                .min(remaining_reserve_borrow)
+                .min(self.liquidity.available_amount.into());
+            let (borrow_fee, host_fee) = self
+                .config
+                .fees
+                .calculate_borrow_fees(borrow_amount, FeeCalculation::Inclusive)?;
+            let receive_amount = borrow_amount
+                .try_floor_u64()?
+                .checked_sub(borrow_fee)
+                .ok_or(LendingError::MathOverflow)?;
+
+            Ok(CalculateBorrowResult {
+                borrow_amount,
+                receive_amount,
+                borrow_fee,
+                host_fee,
+            })
+        } else {
+            let receive_amount = amount_to_borrow;
+            let borrow_amount = Decimal::from(receive_amount);
+            let (borrow_fee, host_fee) = self
+                .config
+                .fees
+                .calculate_borrow_fees(borrow_amount, FeeCalculation::Exclusive)?;
+
+            let borrow_amount = borrow_amount.try_add(borrow_fee.into())?;
+            let borrow_value = self.borrow_weighted_market_value(borrow_amount)?;
+            if borrow_value > max_borrow_value {
+                msg!("Borrow value cannot exceed maximum borrow value");
+                return Err(LendingError::BorrowTooLarge.into());
+            }
+            if borrow_amount > remaining_reserve_borrow {
+                msg!("Borrow amount cannot exceed the reserve borrow limit");
+                return Err(LendingError::InvalidAmount.into());
+            }
+
+            Ok(CalculateBorrowResult {
+                borrow_amount,
+                receive_amount,
+                borrow_fee,
+                host_fee,
+            })
+        }
+    }
+
+    /// Calculate repay amount, with `u64::MAX` settling the whole borrowed amount
+    pub fn calculate_repay(
+        &self,
+        amount_to_repay: u64,
+        borrowed_amount: Decimal,
+    ) -> std::result::Result<CalculateRepayResult, ProgramError> {
+        let settle_amount = if amount_to_repay == u64::MAX {
+            borrowed_amount
+        } else {
+            Decimal::from(amount_to_repay).min(borrowed_amount)
+        };
+        let repay_amount = settle_amount.try_ceil_u64()?;
+
+        Ok(CalculateRepayResult {
+            settle_amount,
+            repay_amount,
+        })
+    }
+
+    /// Calculate the liquidation of up to `amount_to_liquidate` of `liquidity`, seizing
+    /// `collateral` plus `bonus`. `u64::MAX` liquidates as much as the market allows.
+    pub fn calculate_liquidation(
+        &self,
+        amount_to_liquidate: u64,
+        lending_market: &LendingMarket,
+        liquidity: &ObligationLiquidity,
+        collateral: &ObligationCollateral,
+        bonus: &Bonus,
+    ) -> std::result::Result<CalculateLiquidationResult, ProgramError> {
+        let max_amount = if amount_to_liquidate == u64::MAX {
+            liquidity.borrowed_amount_wads
+        } else {
+            Decimal::from(amount_to_liquidate).min(liquidity.borrowed_amount_wads)
+        };
+
+        let liquidation_amount = lending_market
+            .max_liquidation_amount(liquidity.borrowed_amount_wads, liquidity.market_value)?
+            .min(max_amount);
+        let liquidation_pct = liquidation_amount.try_div(liquidity.borrowed_amount_wads)?;
+        let liquidation_value = liquidity
+            .market_value
+            .try_mul(liquidation_pct)?
+            .try_mul(bonus.total_bonus.try_add(Decimal::one())?)?;
+
+        let settle_amount;
+        let repay_amount;
+        let withdraw_amount;
+        match liquidation_value.cmp(&collateral.market_value) {
+            Ordering::Greater => {
+                // not enough collateral, seize all of it and repay proportionally less
+                let repay_pct = collateral.market_value.try_div(liquidation_value)?;
+                settle_amount = liquidation_amount.try_mul(repay_pct)?;
+                repay_amount = settle_amount.try_ceil_u64()?;
+                withdraw_amount = collateral.deposited_amount;
+            }
+            Ordering::Equal => {
+                settle_amount = liquidation_amount;
+                repay_amount = settle_amount.try_ceil_u64()?;
+                withdraw_amount = collateral.deposited_amount;
+            }
+            Ordering::Less => {
+                let withdraw_pct = liquidation_value.try_div(collateral.market_value)?;
+                settle_amount = liquidation_amount;
+                repay_amount = if settle_amount == liquidity.borrowed_amount_wads {
+                    settle_amount.try_ceil_u64()?
+                } else {
+                    settle_amount.try_floor_u64()?
+                };
+                withdraw_amount = Decimal::from(collateral.deposited_amount)
+                    .try_mul(withdraw_pct)?
+                    .try_floor_u64()?;
+            }
+        }
+
+        Ok(CalculateLiquidationResult {
+            settle_amount,
+            repay_amount,
+            withdraw_amount,
+        })
+    }
+
+    /// Calculate the protocol's share of the bonus in `amount_liquidated`. At least 1 is taken
+    /// when the protocol has a share, so liquidations too small to leave the liquidator anything
+    /// on top are rejected.
+    pub fn calculate_protocol_liquidation_fee(
+        &self,
+        amount_liquidated: u64,
+        bonus: &Bonus,
+    ) -> std::result::Result<u64, ProgramError> {
+        if bonus.protocol_liquidation_fee == Decimal::zero() {
+            return Ok(0);
+        }
+        // amount_liquidated = nonbonus_amount * (1 + total_bonus)
+        let nonbonus_amount = Decimal::from(amount_liquidated)
+            .try_div(Decimal::one().try_add(bonus.total_bonus)?)?;
+        let protocol_fee = nonbonus_amount
+            .try_mul(bonus.protocol_liquidation_fee)?
+            .try_ceil_u64()?
+            .max(1);
+        if protocol_fee >= amount_liquidated {
+            msg!("Liquidation is too small to leave the liquidator anything after the protocol fee");
+            return Err(LendingError::LiquidationTooSmall.into());
+        }
+
+        Ok(protocol_fee)
+    }
+
+    /// Deposit liquidity and mint the corresponding collateral, returning its amount
+    pub fn deposit_liquidity(
+        &mut self,
+        liquidity_amount: u64,
+    ) -> std::result::Result<u64, ProgramError> {
+        let collateral_amount = self
+            .collateral_exchange_rate()?
+            .liquidity_to_collateral(liquidity_amount)?;
+        self.liquidity.deposit(liquidity_amount)?;
+        self.collateral.mint(collateral_amount)?;
+
+        Ok(collateral_amount)
+    }
+
+    /// Burn collateral and withdraw the corresponding liquidity, returning its amount
+    pub fn redeem_collateral(
+        &mut self,
+        collateral_amount: u64,
+    ) -> std::result::Result<u64, ProgramError> {
+        let liquidity_amount = self
+            .collateral_exchange_rate()?
+            .collateral_to_liquidity(collateral_amount)?;
+        self.collateral.redeem(collateral_amount)?;
+        self.liquidity.withdraw(liquidity_amount)?;
+
+        Ok(liquidity_amount)
+    }
+
+    /// Calculate the liquidation bonus for an unhealthy obligation.
+    ///
+    /// The bonus interpolates linearly from `liquidation_bonus` when the (borrow weighted)
+    /// borrowed value is at the unhealthy borrow value, to `max_liquidation_bonus` when it reaches
+    /// the super unhealthy borrow value. It is capped so that the liquidation never lowers the
+    /// obligation's ratio of deposited value to borrowed value, ie `deposited / borrowed - 1`.
+    /// The protocol takes `protocol_liquidation_fee` percent of the bonus.
+    pub fn calculate_bonus(
+        &self,
+        borrowed_value: Decimal,
+        unweighted_borrowed_value: Decimal,
+        deposited_value: Decimal,
+        unhealthy_borrow_value: Decimal,
+        super_unhealthy_borrow_value: Decimal,
+    ) -> std::result::Result<Bonus, ProgramError> {
+        if borrowed_value < unhealthy_borrow_value {
+            msg!("Obligation is healthy so a liquidation bonus can't be calculated");
+            return Err(LendingError::ObligationHealthy.into());
+        }
+
+        let liquidation_bonus = Decimal::from_percent(self.config.liquidation_bonus);
+        let max_liquidation_bonus = Decimal::from_percent(self.config.max_liquidation_bonus);
+
+        let bonus = if super_unhealthy_borrow_value <= unhealthy_borrow_value {
+            max_liquidation_bonus
+        } else {
+            // borrowed_value >= unhealthy_borrow_value and the denominator is non-zero,
+            // so weight is always between 0 and 1
+            let weight = min(
+                borrowed_value
+                    .try_sub(unhealthy_borrow_value)?
+                    .try_div(super_unhealthy_borrow_value.try_sub(unhealthy_borrow_value)?)?,
+                Decimal::one(),
+            );
+            liquidation_bonus.try_add(
+                weight.try_mul(max_liquidation_bonus.try_sub(liquidation_bonus)?)?,
+            )?
+        };
+
+        self.cap_bonus(bonus, unweighted_borrowed_value, deposited_value)
+    }
+
+    /// Calculate the Dutch auction liquidation bonus of an obligation unhealthy for
+    /// `unhealthy_slots` slots. It starts at zero and rises linearly to the max liquidation bonus
+    /// over `auction_slots` slots, then is capped like [`Reserve::calculate_bonus`].
+    pub fn calculate_auction_bonus(
+        &self,
+        unweighted_borrowed_value: Decimal,
+        deposited_value: Decimal,
+        unhealthy_slots: u64,
+        auction_slots: u64,
+    ) -> std::result::Result<Bonus, ProgramError> {
+        let max_liquidation_bonus = Decimal::from_percent(self.config.max_liquidation_bonus);
+
+        let bonus = if unhealthy_slots >= auction_slots {
+            max_liquidation_bonus
+        } else {
+            max_liquidation_bonus
+                .try_mul(unhealthy_slots)?
+                .try_div(auction_slots)?
+        };
+
+        self.cap_bonus(bonus, unweighted_borrowed_value, deposited_value)
+    }
+
+    /// Cap `bonus` so that the liquidation does not push the obligation further underwater and
+    /// split out the protocol's cut
+    fn cap_bonus(
+        &self,
+        bonus: Decimal,
+        unweighted_borrowed_value: Decimal,
+        deposited_value: Decimal,
+    ) -> std::result::Result<Bonus, ProgramError> {
+        // seizing (1 + bonus) of the repaid value keeps deposited / borrowed constant when
+        // bonus = deposited / borrowed - 1, anything higher pushes the obligation further underwater
+        let max_bonus = if unweighted_borrowed_value == Decimal::zero()
+            || deposited_value <= unweighted_borrowed_value
+        {
+            Decimal::zero()
+        } else {
+            deposited_value
+                .try_div(unweighted_borrowed_value)?
+                .try_sub(Decimal::one())?
+        };
+        let total_bonus = min(bonus, max_bonus);
+
+        Ok(Bonus {
+            total_bonus,
+            protocol_liquidation_fee: total_bonus
+                .try_mul(Decimal::from_percent(self.config.protocol_liquidation_fee))?,
+        })
+    }
+
+    /// Check that the borrow value attributed to this reserve's collateral is within the open
+    /// limit. Called after a borrow or withdraw has updated the attribution.
+    pub fn check_attributed_borrow_open_limit(&self) -> std::result::Result<(), ProgramError> {
+        if self.attributed_borrow_value
+            > Decimal::from(self.config.attributed_borrow_limit_open)
+        {
+            msg!(
+                "Attributed borrow value {} exceeds the open limit {} of reserve",
+                self.attributed_borrow_value,
+                self.config.attributed_borrow_limit_open
+            );
+            return Err(LendingError::BorrowAttributionLimitExceeded.into());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the borrow value attributed to this reserve's collateral is above the close limit
+    pub fn attributed_borrow_close_limit_exceeded(&self) -> bool {
+        self.attributed_borrow_value > Decimal::from(self.config.attributed_borrow_limit_close)
+    }
+
+    /// Check that the borrow value attributed to this reserve's collateral is above the close
+    /// limit, which allows obligations using it as collateral to be flagged as closeable.
+    pub fn check_attributed_borrow_close_limit(&self) -> std::result::Result<(), ProgramError> {
+        if !self.attributed_borrow_close_limit_exceeded() {
+            msg!(
+                "Attributed borrow value {} does not exceed the close limit {} of reserve",
+                self.attributed_borrow_value,
+                self.config.attributed_borrow_limit_close
+            );
+            return Err(LendingError::BorrowAttributionLimitNotExceeded.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Reserve liquidity
+#[zero_copy]
+#[derive(Debug, Default, PartialEq)]
+pub struct ReserveLiquidity {
+    /// Reserve liquidity mint address
+    pub mint_pubkey: Pubkey,
+    /// Reserve liquidity supply address
+    pub supply_pubkey: Pubkey,
+    /// Reserve liquidity oracle account
+    pub oracle_pubkey: Pubkey,
+    /// Reserve liquidity available
+    pub available_amount: u64,
+    /// Reserve liquidity borrowed
+    pub borrowed_amount_wads: Decimal,
+    /// Reserve liquidity cumulative borrow rate
+    pub cumulative_borrow_rate_wads: Decimal,
+    /// Reserve cumulative protocol fees
+    pub accumulated_protocol_fees_wads: Decimal,
+    /// Reserve liquidity market price in quote currency
+    pub market_price: Decimal,
+    /// Reserve liquidity mint decimals
+    pub mint_decimals: u8,
+    pub _padding: [u8; 7],
+}
+
+impl ReserveLiquidity {
+    /// Calculate the total reserve supply including active loans
+    pub fn total_supply(&self) -> std::result::Result<Decimal, ProgramError> {
+        Decimal::from(self.available_amount)
+            .try_add(self.borrowed_amount_wads)?
+            .try_sub(self.accumulated_protocol_fees_wads)
+    }
+
+    /// Calculate the liquidity utilization rate of the reserve
+    pub fn utilization_rate(&self) -> std::result::Result<Rate, ProgramError> {
+        let total_supply = self.total_supply()?;
+        if total_supply == Decimal::zero() || self.borrowed_amount_wads == Decimal::zero() {
+            return Ok(Rate::zero());
+        }
+        let denominator = self
+            .borrowed_amount_wads
+            .try_add(Decimal::from(self.available_amount))?;
+        self.borrowed_amount_wads.try_div(denominator)?.try_into()
+    }
+
+    /// Subtract borrow amount from available liquidity and add to borrows
+    pub fn borrow(&mut self, borrow_decimal: Decimal) -> std::result::Result<(), ProgramError> {
+        let borrow_amount = borrow_decimal.try_floor_u64()?;
+        if borrow_amount > self.available_amount {
+            msg!("Borrow amount cannot exceed available amount");
+            return Err(LendingError::InsufficientLiquidity.into());
+        }
+
+        self.available_amount = self
+            .available_amount
+            .checked_sub(borrow_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(borrow_decimal)?;
+
+        Ok(())
+    }
+
+    /// Add liquidity to available amount
+    pub fn deposit(&mut self, liquidity_amount: u64) -> std::result::Result<(), ProgramError> {
+        self.available_amount = self
+            .available_amount
+            .checked_add(liquidity_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Subtract liquidity from available amount
+    pub fn withdraw(&mut self, liquidity_amount: u64) -> std::result::Result<(), ProgramError> {
+        if liquidity_amount > self.available_amount {
+            msg!("Withdraw amount cannot exceed available amount");
+            return Err(LendingError::InsufficientLiquidity.into());
+        }
+        self.available_amount = self
+            .available_amount
+            .checked_sub(liquidity_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Add repay amount to available liquidity and subtract settle amount from total borrows
+    pub fn repay(
+        &mut self,
+        repay_amount: u64,
+        settle_amount: Decimal,
+    ) -> std::result::Result<(), ProgramError> {
+        self.available_amount = self
+            .available_amount
+            .checked_add(repay_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        let safe_settle_amount = settle_amount.min(self.borrowed_amount_wads);
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(safe_settle_amount)?;
+
+        Ok(())
+    }
+
+    /// Remove unrecoverable `debt_amount` from total borrows. Accumulated protocol fees absorb
+    /// the loss first, the rest lowers the total supply and so the collateral exchange rate.
+    pub fn write_off(
+        &mut self,
+        debt_amount: Decimal,
+    ) -> std::result::Result<WriteOffResult, ProgramError> {
+        let debt_amount = debt_amount.min(self.borrowed_amount_wads);
+        let insurance_amount = debt_amount.min(self.accumulated_protocol_fees_wads);
+        let socialized_amount = debt_amount.try_sub(insurance_amount)?;
+
+        self.accumulated_protocol_fees_wads =
+            self.accumulated_protocol_fees_wads.try_sub(insurance_amount)?;
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_sub(debt_amount)?;
+
+        Ok(WriteOffResult {
+            insurance_amount,
+            socialized_amount,
+        })
+    }
+
+    /// Compound current borrow rate over elapsed slots
+    fn compound_interest(
+        &mut self,
+        current_borrow_rate: Rate,
+        slots_elapsed: u64,
+        take_rate: Rate,
+    ) -> std::result::Result<(), ProgramError> {
+        let slot_interest_rate = current_borrow_rate.try_div(SLOTS_PER_YEAR)?;
+        let compounded_interest_rate = Rate::one()
+            .try_add(slot_interest_rate)?
+            .try_pow(slots_elapsed)?;
+        self.cumulative_borrow_rate_wads = self
+            .cumulative_borrow_rate_wads
+            .try_mul(compounded_interest_rate)?;
+
+        let net_new_debt = self
+            .borrowed_amount_wads
+            .try_mul(compounded_interest_rate)?
+            .try_sub(self.borrowed_amount_wads)?;
+
+        self.accumulated_protocol_fees_wads = net_new_debt
+            .try_mul(take_rate)?
+            .try_add(self.accumulated_protocol_fees_wads)?;
+
+        self.borrowed_amount_wads = self.borrowed_amount_wads.try_add(net_new_debt)?;
+        Ok(())
+    }
+
+    /// 10^mint_decimals, used to convert token amounts into whole units
+    pub fn decimals_factor(&self) -> std::result::Result<Decimal, ProgramError> {
+        Ok(Decimal::from(
+            10u64
+                .checked_pow(self.mint_decimals as u32)
+                .ok_or(LendingError::MathOverflow)?,
+        ))
+    }
+}
+
+/// Initial ratio of collateral to liquidity, collateral tokens are minted 1:1 at first
+pub const INITIAL_COLLATERAL_RATIO: u64 = 1;
+const INITIAL_COLLATERAL_RATE: u64 = INITIAL_COLLATERAL_RATIO * WAD;
+
+/// Reserve collateral
+#[zero_copy]
+#[derive(Debug, Default, PartialEq)]
+pub struct ReserveCollateral {
+    /// Reserve collateral mint address
+    pub mint_pubkey: Pubkey,
+    /// Reserve collateral mint supply, used for exchange rate
+    pub mint_total_supply: u64,
+    /// Reserve collateral supply address
+    pub supply_pubkey: Pubkey,
+}
+
+impl ReserveCollateral {
+    /// Add collateral to total mint supply
+    pub fn mint(&mut self, collateral_amount: u64) -> std::result::Result<(), ProgramError> {
+        self.mint_total_supply = self
+            .mint_total_supply
+            .checked_add(collateral_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Subtract collateral from total mint supply
+    pub fn redeem(&mut self, collateral_amount: u64) -> std::result::Result<(), ProgramError> {
+        if collateral_amount > self.mint_total_supply {
+            msg!("Collateral amount cannot exceed the collateral mint supply");
+            return Err(LendingError::InsufficientLiquidity.into());
+        }
+        self.mint_total_supply = self
+            .mint_total_supply
+            .checked_sub(collateral_amount)
+            .ok_or(LendingError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Return the current collateral exchange rate
+    fn exchange_rate(
+        &self,
+        total_liquidity: Decimal,
+    ) -> std::result::Result<CollateralExchangeRate, ProgramError> {
+        let rate = if self.mint_total_supply == 0 || total_liquidity == Decimal::zero() {
+            Rate::from_scaled_val(INITIAL_COLLATERAL_RATE)
+        } else {
+            let mint_total_supply = Decimal::from(self.mint_total_supply);
+            Rate::try_from(mint_total_supply.try_div(total_liquidity)?)?
+        };
+
+        Ok(CollateralExchangeRate(rate))
+    }
+}
+
+/// Collateral exchange rate
+#[derive(Clone, Copy, Debug)]
+pub struct CollateralExchangeRate(Rate);
+
+impl CollateralExchangeRate {
+    /// Convert reserve collateral to liquidity
+    pub fn collateral_to_liquidity(&self, collateral_amount: u64) -> std::result::Result<u64, ProgramError> {
+        self.decimal_collateral_to_liquidity(collateral_amount.into())?
+            .try_floor_u64()
+    }
+
+    /// Convert reserve collateral to liquidity
+    pub fn decimal_collateral_to_liquidity(
+        &self,
+        collateral_amount: Decimal,
+    ) -> std::result::Result<Decimal, ProgramError> {
+        collateral_amount.try_div(self.0)
+    }
+
+    /// Convert reserve liquidity to collateral
+    pub fn liquidity_to_collateral(&self, liquidity_amount: u64) -> std::result::Result<u64, ProgramError> {
+        self.decimal_liquidity_to_collateral(liquidity_amount.into())?
+            .try_floor_u64()
+    }
+
+    /// Convert reserve liquidity to collateral
+    pub fn decimal_liquidity_to_collateral(
+        &self,
+        liquidity_amount: Decimal,
+    ) -> std::result::Result<Decimal, ProgramError> {
+        liquidity_amount.try_mul(self.0)
+    }
+}
+
+impl From<CollateralExchangeRate> for Rate {
+    fn from(exchange_rate: CollateralExchangeRate) -> Self {
+        exchange_rate.0
+    }
+}
+
+/// Reserve configuration values
+#[zero_copy]
+#[derive(Debug, Default, PartialEq)]
+pub struct ReserveConfig {
+    /// Optimal utilization rate, as a percentage
+    pub optimal_utilization_rate: u8,
+    /// Unhealthy utilization rate, as a percentage
+    pub max_utilization_rate: u8,
+    /// Target ratio of the value of borrows to deposits, as a percentage
+    /// 0 if use as collateral is disabled
+    pub loan_to_value_ratio: u8,
+    /// Bonus a liquidator gets when repaying part of an obligation that just became unhealthy,
+    /// as a percentage
+    pub liquidation_bonus: u8,
+    /// Bonus a liquidator gets when repaying part of a super unhealthy obligation, as a percentage
+    pub max_liquidation_bonus: u8,
+    /// Loan to value ratio at which an obligation can be liquidated, as a percentage
+    pub liquidation_threshold: u8,
+    /// Loan to value ratio at which the obligation is super unhealthy and the liquidation bonus
+    /// reaches max_liquidation_bonus, as a percentage
+    pub max_liquidation_threshold: u8,
+    /// Min borrow APY
+    pub min_borrow_rate: u8,
+    /// Optimal (utilization) borrow APY
+    pub optimal_borrow_rate: u8,
+    /// Max borrow APY
+    pub max_borrow_rate: u8,
+    /// Cut of the interest that goes to the protocol, as a percentage
+    pub protocol_take_rate: u8,
+    /// Cut of the liquidation bonus that goes to the protocol, as a percentage
+    pub protocol_liquidation_fee: u8,
+    /// Asset tier of the reserve as a `ReserveType`, restricts how it can be combined with other
+    /// reserves in the same obligation. Read it through `reserve_type()`.
+    pub reserve_type: u8,
+    /// E-mode category of the reserve, 0 if none
+    pub emode_category: u8,
+    pub _padding: [u8; 2],
+    /// Supermax borrow APY
+    pub super_max_borrow_rate: u64,
+    /// Program owner fees assessed, separate from gains due to interest accrual
+    pub fees: ReserveFees,
+    /// Maximum deposit limit of liquidity in native units, u64::MAX for inf
+    pub deposit_limit: u64,
+    /// Maximum amount of liquidity that can be borrowed in native units, u64::MAX for inf
+    pub borrow_limit: u64,
+    /// Reserve liquidity fee receiver address
+    pub fee_receiver: Pubkey,
+    /// Added borrow weight in basis points. The borrow weight is 1 + added_borrow_weight_bps / 10000.
+    /// Volatile assets get a higher borrow weight so borrowing them consumes more borrowing power.
+    pub added_borrow_weight_bps: u64,
+    /// Max borrow value in the lending market quote currency that can be attributed to this
+    /// reserve's collateral. Borrows and withdraws that would exceed it are rejected.
+    pub attributed_borrow_limit_open: u64,
+    /// Attributed borrow value in the lending market quote currency above which obligations
+    /// using this reserve as collateral can be marked closeable and unwound
+    pub attributed_borrow_limit_close: u64,
+    /// Min number of slots between two interest rate snapshots, 0 disables the rate history
+    pub rate_history_interval: u64,
+}
+
+impl ReserveConfig {
+    /// Asset tier of the reserve
+    pub fn reserve_type(&self) -> std::result::Result<ReserveType, ProgramError> {
+        ReserveType::from_u8(self.reserve_type).ok_or_else(|| {
+            msg!("Reserve type {} is invalid", self.reserve_type);
+            LendingError::InvalidConfig.into()
+        })
+    }
+
+    /// Borrow weight of the reserve, 1 + added_borrow_weight_bps / 10000
+    pub fn borrow_weight(&self) -> Decimal {
+        Decimal::one()
+            .try_add(Decimal::from_bps(self.added_borrow_weight_bps))
+            .unwrap()
+    }
+}
+
+/// Calculate borrow result
+#[derive(Debug)]
+pub struct CalculateBorrowResult {
+    /// Total amount of borrow including fees
+    pub borrow_amount: Decimal,
+    /// Borrow amount portion of total amount
+    pub receive_amount: u64,
+    /// Loan origination fee
+    pub borrow_fee: u64,
+    /// Host fee portion of origination fee
+    pub host_fee: u64,
+}
+
+/// Calculate repay result
+#[derive(Debug)]
+pub struct CalculateRepayResult {
+    /// Amount of liquidity that is settled from the obligation.
+    pub settle_amount: Decimal,
+    /// Amount that will be repaid as u64
+    pub repay_amount: u64,
+}
+
+/// Calculate liquidation result
+#[derive(Debug)]
+pub struct CalculateLiquidationResult {
+    /// Amount of liquidity that is settled from the obligation. It includes
+    /// the amount of loan that was defaulted if collateral is depleted.
+    pub settle_amount: Decimal,
+    /// Amount that will be repaid as u64
+    pub repay_amount: u64,
+    /// Amount of collateral to withdraw in exchange for repay amount
+    pub withdraw_amount: u64,
+}
+
+/// Write off result
+#[derive(Debug)]
+pub struct WriteOffResult {
+    /// Part of the debt covered by the accumulated protocol fees
+    pub insurance_amount: Decimal,
+    /// Part of the debt taken from the depositors
+    pub socialized_amount: Decimal,
+}
+
+/// Liquidation bonus, as fractions of the repaid value
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bonus {
+    /// Total bonus paid out of the obligation's collateral, including the protocol's cut
+    pub total_bonus: Decimal,
+    /// Part of the total bonus that goes to the protocol
+    pub protocol_liquidation_fee: Decimal,
+}
+
+/// Asset tier of a reserve
+///
+/// Lets long-tail assets be listed in the same market without exposing the rest of the
+/// market to them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, FromPrimitive, AnchorSerialize, AnchorDeserialize)]
+#[repr(u8)]
+pub enum ReserveType {
+    /// Can be deposited as collateral and borrowed alongside any other regular asset
+    #[default]
+    Regular,
+    /// Can be deposited, but deposits cannot back any borrow
+    IsolatedCollateral,
+    /// Can be borrowed, but an obligation borrowing it cannot hold any other borrow
+    Isolated,
+}
+
+impl ReserveType {
+    /// Check that a new borrow from a reserve of type `self` is allowed, given the reserve being
+    /// borrowed from, the reserves the obligation already borrows from and whether the obligation
+    /// holds any `IsolatedCollateral` deposit
+    pub fn validate_borrow(
+        &self,
+        borrow_reserve: &Pubkey,
+        existing_borrows: &[(Pubkey, ReserveType)],
+        has_isolated_collateral: bool,
+    ) -> std::result::Result<(), ProgramError> {
+        if has_isolated_collateral {
+            msg!("Isolated collateral deposits cannot back borrows");
+            return Err(LendingError::IsolatedTierAssetViolation.into());
+        }
+
+        for (reserve, reserve_type) in existing_borrows {
+            if reserve == borrow_reserve {
+                continue;
+            }
+            if *self == ReserveType::Isolated || *reserve_type == ReserveType::Isolated {
+                msg!("Isolated tier asset cannot be borrowed alongside another borrow");
+                return Err(LendingError::IsolatedTierAssetViolation.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check that a deposit into a reserve of type `self` is allowed, given whether the obligation
+    /// currently has any borrow
+    pub fn validate_deposit(&self, has_borrows: bool) -> std::result::Result<(), ProgramError> {
+        if *self == ReserveType::IsolatedCollateral && has_borrows {
+            msg!("Isolated collateral deposits cannot back borrows");
+            return Err(LendingError::IsolatedTierAssetViolation.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Additional fee information on a reserve
+///
+/// These exist separately from interest accrual fees, and are specifically for the program owner
+/// and frontend host. The fees are paid out as a percentage of liquidity token amounts during
+/// repayments and liquidations.
+#[zero_copy]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReserveFees {
+    /// Fee assessed on `BorrowObligationLiquidity`, expressed as a Wad.
+    /// Must be between 0 and 10^18, such that 10^18 = 1.  A few examples for
+    /// clarity:
+    /// 1% = 10_000_000_000_000_000
+    /// 0.01% (1 basis point) = 100_000_000_000_000
+    /// 0.00001% (Aave borrow fee) = 100_000_000_000
+    pub borrow_fee_wad: u64,
+    /// Fee for flash loan, expressed as a Wad.
+    /// 0.3% (Aave flash loan fee) = 3_000_000_000_000_000
+    /// u64::MAX disables flash loans
+    pub flash_loan_fee_wad: u64,
+    /// Amount of fee going to host account, if provided in liquidate and repay
+    pub host_fee_percentage: u8,
+    pub _padding: [u8; 7],
+}
+
+impl ReserveFees {
+    /// Calculate the owner and host fees on borrow
+    pub fn calculate_borrow_fees(
+        &self,
+        borrow_amount: Decimal,
+        fee_calculation: FeeCalculation,
+    ) -> std::result::Result<(u64, u64), ProgramError> {
+        self.calculate_fees(borrow_amount, self.borrow_fee_wad, fee_calculation)
+    }
+
+    /// Whether flash loans are disabled for the reserve
+    pub fn flash_loans_disabled(&self) -> bool {
+        self.flash_loan_fee_wad == u64::MAX
+    }
+
+    /// Calculate the owner and host fees on flash loan, on top of the flash loan amount
+    pub fn calculate_flash_loan_fees(
+        &self,
+        flash_loan_amount: Decimal,
+    ) -> std::result::Result<(u64, u64), ProgramError> {
+        self.calculate_fees(flash_loan_amount, self.flash_loan_fee_wad, FeeCalculation::Exclusive)
+    }
+
+    fn calculate_fees(
+        &self,
+        amount: Decimal,
+        fee_wad: u64,
+        fee_calculation: FeeCalculation,
+    ) -> std::result::Result<(u64, u64), ProgramError> {
+        let borrow_fee_rate = Rate::from_scaled_val(fee_wad);
+        let host_fee_rate = Rate::from_percent(self.host_fee_percentage);
+        if borrow_fee_rate > Rate::zero() && amount > Decimal::zero() {
+            let need_to_assess_host_fee = host_fee_rate > Rate::zero();
+            let minimum_fee = if need_to_assess_host_fee {
+                2u64 // 1 token to owner, 1 to host
+            } else {
+                1u64 // 1 token to owner, nothing else
+            };
+
+            let borrow_fee_amount = match fee_calculation {
+                // Calculate fee to be added to borrow: fee = amount * rate
+                FeeCalculation::Exclusive => amount.try_mul(borrow_fee_rate)?,
+                // Calculate fee to be subtracted from borrow: fee = amount * (rate / (rate + 1))
+                FeeCalculation::Inclusive => {
+                    let borrow_fee_rate =
+                        borrow_fee_rate.try_div(borrow_fee_rate.try_add(Rate::one())?)?;
+                    amount.try_mul(borrow_fee_rate)?
+                }
+            };
+
+            let borrow_fee_decimal = borrow_fee_amount.max(minimum_fee.into());
+            if borrow_fee_decimal >= amount {
+                msg!("Borrow amount is too small to receive liquidity after fees");
+                return Err(LendingError::BorrowTooSmall.into());
+            }
+
+            let borrow_fee = borrow_fee_decimal.try_round_u64()?;
+            let host_fee = if need_to_assess_host_fee {
+                borrow_fee_decimal
+                    .try_mul(host_fee_rate)?
+                    .try_round_u64()?
+                    .max(1u64)
+            } else {
+                0
+            };
+
+            Ok((borrow_fee, host_fee))
+        } else {
+            Ok((0, 0))
+        }
+    }
+}
+
+/// Calculate fees exlusive or inclusive of an amount
+pub enum FeeCalculation {
+    /// Fee added to amount: fee = rate * amount
+    Exclusive,
+    /// Fee included in amount: fee = (rate / (1 + rate)) * amount
+    Inclusive,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytemuck::Zeroable;
+
+    fn percent(value: u64, denominator: u64) -> Decimal {
+        Decimal::from(value).try_div(denominator).unwrap()
+    }
+
+    fn liquidation_reserve(
+        liquidation_bonus: u8,
+        max_liquidation_bonus: u8,
+        protocol_liquidation_fee: u8,
+    ) -> Reserve {
+        let mut reserve = Reserve::zeroed();
+        reserve.config.liquidation_bonus = liquidation_bonus;
+        reserve.config.max_liquidation_bonus = max_liquidation_bonus;
+        reserve.config.protocol_liquidation_fee = protocol_liquidation_fee;
+        reserve
+    }
+
+    #[test]
+    fn test_calculate_bonus_rejects_healthy_obligation() {
+        let reserve = liquidation_reserve(5, 10, 10);
+        assert_eq!(
+            reserve.calculate_bonus(
+                Decimal::from(99u64),
+                Decimal::from(99u64),
+                Decimal::from(1_000u64),
+                Decimal::from(100u64),
+                Decimal::from(200u64),
+            ),
+            Err(LendingError::ObligationHealthy.into())
+        );
+    }
+
+    #[test]
+    fn test_calculate_bonus_interpolates_between_unhealthy_and_super_unhealthy() {
+        let reserve = liquidation_reserve(5, 10, 10);
+        let bonus_at = |borrowed_value: u64| {
+            reserve
+                .calculate_bonus(
+                    Decimal::from(borrowed_value),
+                    Decimal::from(100u64),
+                    Decimal::from(1_000u64),
+                    Decimal::from(100u64),
+                    Decimal::from(200u64),
+                )
+                .unwrap()
+                .total_bonus
+        };
+
+        // liquidation bonus at the unhealthy borrow value
+        assert_eq!(bonus_at(100), Decimal::from_percent(5));
+        // halfway to the super unhealthy borrow value
+        assert_eq!(bonus_at(150), percent(75, 1_000));
+        // max liquidation bonus at and beyond the super unhealthy borrow value
+        assert_eq!(bonus_at(200), Decimal::from_percent(10));
+        assert_eq!(bonus_at(400), Decimal::from_percent(10));
+    }
+
+    #[test]
+    fn test_calculate_bonus_without_interpolation_range() {
+        // a super unhealthy borrow value at the unhealthy one goes straight to the max bonus
+        let reserve = liquidation_reserve(5, 10, 0);
+        let bonus = reserve
+            .calculate_bonus(
+                Decimal::from(100u64),
+                Decimal::from(100u64),
+                Decimal::from(1_000u64),
+                Decimal::from(100u64),
+                Decimal::from(100u64),
+            )
+            .unwrap();
+        assert_eq!(bonus.total_bonus, Decimal::from_percent(10));
+        assert_eq!(bonus.protocol_liquidation_fee, Decimal::zero());
+    }
+
+    #[test]
+    fn test_calculate_bonus_capped_by_collateralization() {
+        let reserve = liquidation_reserve(5, 10, 10);
+        let bonus_with_deposits = |deposited_value: u64| {
+            reserve
+                .calculate_bonus(
+                    Decimal::from(200u64),
+                    Decimal::from(100u64),
+                    Decimal::from(deposited_value),
+                    Decimal::from(100u64),
+                    Decimal::from(200u64),
+                )
+                .unwrap()
+        };
+
+        // deposited / borrowed - 1 = 4% is below the 10% max bonus
+        assert_eq!(
+            bonus_with_deposits(104),
+            Bonus {
+                total_bonus: Decimal::from_percent(4),
+                protocol_liquidation_fee: percent(4, 1_000),
+            }
+        );
+        // underwater obligations get no bonus at all
+        assert_eq!(bonus_with_deposits(100).total_bonus, Decimal::zero());
+        assert_eq!(bonus_with_deposits(90).total_bonus, Decimal::zero());
+    }
+
+    #[test]
+    fn test_calculate_bonus_protocol_share() {
+        let reserve = liquidation_reserve(5, 10, 30);
+        let bonus = reserve
+            .calculate_bonus(
+                Decimal::from(200u64),
+                Decimal::from(100u64),
+                Decimal::from(1_000u64),
+                Decimal::from(100u64),
+                Decimal::from(200u64),
+            )
+            .unwrap();
+
+        // the protocol takes 30% of the 10% bonus
+        assert_eq!(bonus.total_bonus, Decimal::from_percent(10));
+        assert_eq!(bonus.protocol_liquidation_fee, Decimal::from_percent(3));
+    }
+
+    #[test]
+    fn test_calculate_auction_bonus_rises_over_the_auction() {
+        let reserve = liquidation_reserve(5, 10, 10);
+        let bonus_after = |unhealthy_slots: u64| {
+            reserve
+                .calculate_auction_bonus(
+                    Decimal::from(100u64),
+                    Decimal::from(1_000u64),
+                    unhealthy_slots,
+                    100,
+                )
+                .unwrap()
+                .total_bonus
+        };
+
+        // nothing as soon as the obligation turns unhealthy
+        assert_eq!(bonus_after(0), Decimal::zero());
+        // linear up to the max liquidation bonus
+        assert_eq!(bonus_after(50), Decimal::from_percent(5));
+        assert_eq!(bonus_after(100), Decimal::from_percent(10));
+        assert_eq!(bonus_after(1_000), Decimal::from_percent(10));
+    }
+
+    #[test]
+    fn test_calculate_auction_bonus_capped_by_collateralization() {
+        let reserve = liquidation_reserve(5, 10, 10);
+
+        // deposited / borrowed - 1 = 4% is below the 10% reached at the end of the auction
+        let bonus = reserve
+            .calculate_auction_bonus(Decimal::from(100u64), Decimal::from(104u64), 100, 100)
+            .unwrap();
+        assert_eq!(
+            bonus,
+            Bonus {
+                total_bonus: Decimal::from_percent(4),
+                protocol_liquidation_fee: percent(4, 1_000),
+            }
+        );
+
+        // underwater obligations get no bonus at all
+        let bonus = reserve
+            .calculate_auction_bonus(Decimal::from(100u64), Decimal::from(90u64), 100, 100)
+            .unwrap();
+        assert_eq!(bonus.total_bonus, Decimal::zero());
+    }
+
+    #[test]
+    fn test_calculate_repay_full_debt() {
+        let reserve = Reserve::zeroed();
+        let borrowed_amount = percent(100_500, 1_000);
+
+        // u64::MAX settles the exact debt and transfers it rounded up
+        let result = reserve.calculate_repay(u64::MAX, borrowed_amount).unwrap();
+        assert_eq!(result.settle_amount, borrowed_amount);
+        assert_eq!(result.repay_amount, 101);
+
+        // so does any amount above the debt
+        let result = reserve.calculate_repay(1_000, borrowed_amount).unwrap();
+        assert_eq!(result.settle_amount, borrowed_amount);
+        assert_eq!(result.repay_amount, 101);
+    }
+
+    #[test]
+    fn test_calculate_repay_partial() {
+        let reserve = Reserve::zeroed();
+
+        // a partial repay settles exactly the amount transferred
+        let result = reserve.calculate_repay(40, percent(100_500, 1_000)).unwrap();
+        assert_eq!(result.settle_amount, Decimal::from(40u64));
+        assert_eq!(result.repay_amount, 40);
+    }
+
+    #[test]
+    fn test_calculate_protocol_liquidation_fee() {
+        let reserve = Reserve::zeroed();
+        let bonus = Bonus {
+            total_bonus: Decimal::from_percent(10),
+            protocol_liquidation_fee: Decimal::from_percent(1),
+        };
+
+        // 1% of the 1000 repaid out of 1100 seized
+        assert_eq!(reserve.calculate_protocol_liquidation_fee(1_100, &bonus), Ok(10));
+        // rounded up, and at least 1
+        assert_eq!(reserve.calculate_protocol_liquidation_fee(1_101, &bonus), Ok(11));
+        assert_eq!(reserve.calculate_protocol_liquidation_fee(2, &bonus), Ok(1));
+        // nothing when the protocol has no share
+        assert_eq!(
+            reserve.calculate_protocol_liquidation_fee(1_100, &Bonus::default()),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn test_calculate_protocol_liquidation_fee_leaves_liquidator_nothing() {
+        let reserve = Reserve::zeroed();
+        let bonus = Bonus {
+            total_bonus: Decimal::from_percent(10),
+            protocol_liquidation_fee: Decimal::from_percent(1),
+        };
+
+        assert_eq!(
+            reserve.calculate_protocol_liquidation_fee(1, &bonus),
+            Err(LendingError::LiquidationTooSmall.into())
+        );
+    }
+
+    fn written_off_liquidity(borrowed_amount: u64, accumulated_protocol_fees: u64) -> ReserveLiquidity {
+        ReserveLiquidity {
+            available_amount: 500,
+            borrowed_amount_wads: Decimal::from(borrowed_amount),
+            accumulated_protocol_fees_wads: Decimal::from(accumulated_protocol_fees),
+            ..ReserveLiquidity::default()
+        }
+    }
+
+    #[test]
+    fn test_write_off_covered_by_protocol_fees() {
+        let mut liquidity = written_off_liquidity(1_000, 300);
+        let total_supply = liquidity.total_supply().unwrap();
+
+        let result = liquidity.write_off(Decimal::from(200u64)).unwrap();
+        assert_eq!(result.insurance_amount, Decimal::from(200u64));
+        assert_eq!(result.socialized_amount, Decimal::zero());
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::from(800u64));
+        assert_eq!(liquidity.accumulated_protocol_fees_wads, Decimal::from(100u64));
+        // the depositors lose nothing
+        assert_eq!(liquidity.total_supply().unwrap(), total_supply);
+    }
+
+    #[test]
+    fn test_write_off_socializes_what_protocol_fees_do_not_cover() {
+        let mut liquidity = written_off_liquidity(1_000, 50);
+        let total_supply = liquidity.total_supply().unwrap();
+
+        let result = liquidity.write_off(Decimal::from(200u64)).unwrap();
+        assert_eq!(result.insurance_amount, Decimal::from(50u64));
+        assert_eq!(result.socialized_amount, Decimal::from(150u64));
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::from(800u64));
+        assert_eq!(liquidity.accumulated_protocol_fees_wads, Decimal::zero());
+        // the depositors' supply shrinks by the socialized part
+        assert_eq!(
+            liquidity.total_supply().unwrap(),
+            total_supply.try_sub(Decimal::from(150u64)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_write_off_capped_at_borrowed_amount() {
+        let mut liquidity = written_off_liquidity(1_000, 300);
+
+        let result = liquidity.write_off(Decimal::from(2_000u64)).unwrap();
+        assert_eq!(result.insurance_amount, Decimal::from(300u64));
+        assert_eq!(result.socialized_amount, Decimal::from(700u64));
+        assert_eq!(liquidity.borrowed_amount_wads, Decimal::zero());
+    }
+}
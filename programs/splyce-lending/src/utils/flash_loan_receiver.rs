@@ -0,0 +1,75 @@
+//! Interface of the receiver programs of callback flash loans.
+//!
+//! A receiver is any program exposing an Anchor style
+//! `receive_flash_loan(amount: u64, fee: u64)` instruction whose first accounts are the token
+//! account holding the loan (writable), the reserve liquidity supply the loan and fee are repaid
+//! into (writable) and the token program. Any accounts the receiver needs follow and are passed
+//! through untouched. The receiver must have transferred `amount + fee` back into the supply by
+//! the time it returns.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+};
+
+use crate::error::LendingError;
+
+/// Anchor discriminator of the receiver `receive_flash_loan` instruction,
+/// `sha256("global:receive_flash_loan")[..8]`
+pub const RECEIVE_FLASH_LOAN_DISCRIMINATOR: [u8; 8] = [241, 162, 211, 4, 86, 11, 198, 206];
+
+/// Hand the `amount` flash loan held in `destination_liquidity` over to `receiver_program`,
+/// which repays it plus `fee` into `reserve_liquidity_supply`
+pub fn receive_flash_loan<'info>(
+    receiver_program: &AccountInfo<'info>,
+    destination_liquidity: &AccountInfo<'info>,
+    reserve_liquidity_supply: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    receiver_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    fee: u64,
+) -> Result<()> {
+    if !receiver_program.executable
+        || *receiver_program.key == crate::ID
+        || *receiver_program.key == *token_program.key
+    {
+        msg!("Flash loan receiver must be an executable program other than the lending and token programs");
+        return Err(ProgramError::from(LendingError::InvalidFlashLoanReceiverProgram).into());
+    }
+
+    let mut data = Vec::with_capacity(24);
+    data.extend_from_slice(&RECEIVE_FLASH_LOAN_DISCRIMINATOR);
+    data.extend_from_slice(&amount.to_le_bytes());
+    data.extend_from_slice(&fee.to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new(*destination_liquidity.key, false),
+        AccountMeta::new(*reserve_liquidity_supply.key, false),
+        AccountMeta::new_readonly(*token_program.key, false),
+    ];
+    accounts.extend(receiver_accounts.iter().map(|account| AccountMeta {
+        pubkey: *account.key,
+        is_signer: account.is_signer,
+        is_writable: account.is_writable,
+    }));
+
+    let mut account_infos = vec![
+        destination_liquidity.clone(),
+        reserve_liquidity_supply.clone(),
+        token_program.clone(),
+    ];
+    account_infos.extend_from_slice(receiver_accounts);
+    account_infos.push(receiver_program.clone());
+
+    invoke(
+        &Instruction {
+            program_id: *receiver_program.key,
+            accounts,
+            data,
+        },
+        &account_infos,
+    )?;
+
+    Ok(())
+}
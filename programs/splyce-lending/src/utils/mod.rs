@@ -0,0 +1,9 @@
+mod flash_loan_receiver;
+mod native_sol;
+mod pyth;
+mod swap_adapter;
+
+pub use flash_loan_receiver::*;
+pub use native_sol::*;
+pub use pyth::*;
+pub use swap_adapter::*;
@@ -0,0 +1,143 @@
+//! Helpers to move native SOL in and out of the native-mint reserve without the user
+//! wrapping it beforehand.
+//!
+//! Instructions create a temporary wrapped SOL token account owned by the user, seeded with
+//! [`TEMPORARY_WSOL_SEED`], wrap the lamports into it, use it as the user's liquidity account and
+//! close it again before returning, so the whole flow fits in a single transaction from a plain
+//! system wallet.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::system_program::{self, Allocate, Assign, CreateAccount, Transfer};
+use anchor_spl::token::{self, spl_token, CloseAccount, InitializeAccount3, SyncNative};
+
+/// Seed of the temporary wrapped SOL token account, followed by the user key
+pub const TEMPORARY_WSOL_SEED: &[u8] = b"temporary_wsol";
+
+/// Whether the mint is the native SOL mint
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == spl_token::native_mint::ID
+}
+
+/// Create the temporary wrapped SOL token account of `owner` at its address derived with `bump`.
+/// `owner` pays its rent and is its authority.
+pub fn create_temporary_wsol<'info>(
+    owner: AccountInfo<'info>,
+    temporary_wsol: AccountInfo<'info>,
+    native_mint: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    bump: u8,
+) -> Result<()> {
+    let owner_key = owner.key();
+    let signer_seeds: &[&[u8]] = &[TEMPORARY_WSOL_SEED, owner_key.as_ref(), &[bump]];
+    let space = spl_token::state::Account::LEN;
+    let rent = Rent::get()?.minimum_balance(space);
+
+    let current_lamports = temporary_wsol.lamports();
+    if current_lamports == 0 {
+        system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program,
+                CreateAccount {
+                    from: owner.clone(),
+                    to: temporary_wsol.clone(),
+                },
+                &[signer_seeds],
+            ),
+            rent,
+            space as u64,
+            &spl_token::ID,
+        )?;
+    } else {
+        // lamports sent to the address beforehand must not block the account creation
+        let shortfall = rent.saturating_sub(current_lamports);
+        if shortfall > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    system_program.clone(),
+                    Transfer {
+                        from: owner.clone(),
+                        to: temporary_wsol.clone(),
+                    },
+                ),
+                shortfall,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                Allocate {
+                    account_to_allocate: temporary_wsol.clone(),
+                },
+                &[signer_seeds],
+            ),
+            space as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                system_program,
+                Assign {
+                    account_to_assign: temporary_wsol.clone(),
+                },
+                &[signer_seeds],
+            ),
+            &spl_token::ID,
+        )?;
+    }
+
+    token::initialize_account3(CpiContext::new(
+        token_program,
+        InitializeAccount3 {
+            account: temporary_wsol,
+            mint: native_mint,
+            authority: owner,
+        },
+    ))
+}
+
+/// Move `amount` lamports from `payer` into the wrapped SOL token account and sync its token
+/// balance
+pub fn wrap_lamports<'info>(
+    payer: AccountInfo<'info>,
+    wsol_account: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    system_program::transfer(
+        CpiContext::new(
+            system_program,
+            Transfer {
+                from: payer,
+                to: wsol_account.clone(),
+            },
+        ),
+        amount,
+    )?;
+
+    token::sync_native(CpiContext::new(
+        token_program,
+        SyncNative {
+            account: wsol_account,
+        },
+    ))
+}
+
+/// Close the temporary wrapped SOL token account, unwrapping its whole balance and returning its
+/// rent to `destination`
+pub fn unwrap_lamports<'info>(
+    wsol_account: AccountInfo<'info>,
+    destination: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+) -> Result<()> {
+    token::close_account(CpiContext::new(
+        token_program,
+        CloseAccount {
+            account: wsol_account,
+            destination,
+            authority,
+        },
+    ))
+}
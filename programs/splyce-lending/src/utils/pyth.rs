@@ -0,0 +1,188 @@
+//! Minimal reader of Pyth v2 price accounts.
+//!
+//! Only the aggregate price of the account is read, at the fixed offsets of the v2 layout, so the
+//! program does not depend on the Pyth SDK and the Solana version it pins.
+
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::LendingError,
+    math::{Decimal, TryDiv, TryMul},
+};
+
+/// Magic number at the start of every Pyth account
+const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+/// Pyth account layout version read here
+const PYTH_VERSION: u32 = 2;
+/// Account type of Pyth price accounts
+const PYTH_ACCOUNT_TYPE_PRICE: u32 = 3;
+/// Aggregate status of a price that is currently trading
+const PYTH_STATUS_TRADING: u32 = 1;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 4;
+const ACCOUNT_TYPE_OFFSET: usize = 8;
+const EXPONENT_OFFSET: usize = 20;
+const AGGREGATE_PRICE_OFFSET: usize = 208;
+const AGGREGATE_CONFIDENCE_OFFSET: usize = 216;
+const AGGREGATE_STATUS_OFFSET: usize = 224;
+const AGGREGATE_PUBLISH_SLOT_OFFSET: usize = 232;
+/// Length of the price account prefix read here
+const PYTH_PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+/// Read the market price of one whole token from a Pyth price account, rejecting prices that are
+/// not trading, published `max_staleness_slots` or more slots ago, or whose confidence interval
+/// is wider than `max_confidence_pct` percent of the price
+pub fn get_pyth_price(
+    pyth_price_info: &AccountInfo,
+    current_slot: u64,
+    max_staleness_slots: u64,
+    max_confidence_pct: u8,
+) -> std::result::Result<Decimal, ProgramError> {
+    let data = pyth_price_info.try_borrow_data()?;
+    if data.len() < PYTH_PRICE_ACCOUNT_MIN_LEN
+        || read_u32(&data, MAGIC_OFFSET) != PYTH_MAGIC
+        || read_u32(&data, VERSION_OFFSET) != PYTH_VERSION
+        || read_u32(&data, ACCOUNT_TYPE_OFFSET) != PYTH_ACCOUNT_TYPE_PRICE
+    {
+        msg!("Oracle account is not a Pyth price account");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    if read_u32(&data, AGGREGATE_STATUS_OFFSET) != PYTH_STATUS_TRADING {
+        msg!("Oracle price is not currently trading");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let publish_slot = read_u64(&data, AGGREGATE_PUBLISH_SLOT_OFFSET);
+    if current_slot.saturating_sub(publish_slot) >= max_staleness_slots {
+        msg!("Oracle price is stale");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let price = read_u64(&data, AGGREGATE_PRICE_OFFSET) as i64;
+    if price <= 0 {
+        msg!("Oracle price cannot be negative or zero");
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+    let price = price as u64;
+
+    let confidence = read_u64(&data, AGGREGATE_CONFIDENCE_OFFSET);
+    if confidence as u128 * 100 > price as u128 * max_confidence_pct as u128 {
+        msg!(
+            "Oracle price confidence {} is too wide for price {}",
+            confidence,
+            price
+        );
+        return Err(LendingError::InvalidOracleConfig.into());
+    }
+
+    let exponent = read_u32(&data, EXPONENT_OFFSET) as i32;
+    let scale = 10u64
+        .checked_pow(exponent.unsigned_abs())
+        .ok_or(LendingError::MathOverflow)?;
+    if exponent >= 0 {
+        Decimal::from(price).try_mul(scale)
+    } else {
+        Decimal::from(price).try_div(scale)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MAX_STALENESS_SLOTS: u64 = 240;
+    const MAX_CONFIDENCE_PCT: u8 = 10;
+
+    fn price_account(price: i64, confidence: u64, exponent: i32, status: u32, slot: u64) -> Vec<u8> {
+        let mut data = vec![0u8; 3312];
+        data[MAGIC_OFFSET..][..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[VERSION_OFFSET..][..4].copy_from_slice(&PYTH_VERSION.to_le_bytes());
+        data[ACCOUNT_TYPE_OFFSET..][..4].copy_from_slice(&PYTH_ACCOUNT_TYPE_PRICE.to_le_bytes());
+        data[EXPONENT_OFFSET..][..4].copy_from_slice(&exponent.to_le_bytes());
+        data[AGGREGATE_PRICE_OFFSET..][..8].copy_from_slice(&price.to_le_bytes());
+        data[AGGREGATE_CONFIDENCE_OFFSET..][..8].copy_from_slice(&confidence.to_le_bytes());
+        data[AGGREGATE_STATUS_OFFSET..][..4].copy_from_slice(&status.to_le_bytes());
+        data[AGGREGATE_PUBLISH_SLOT_OFFSET..][..8].copy_from_slice(&slot.to_le_bytes());
+        data
+    }
+
+    fn read(data: Vec<u8>, current_slot: u64) -> std::result::Result<Decimal, ProgramError> {
+        read_with_limits(data, current_slot, MAX_STALENESS_SLOTS, MAX_CONFIDENCE_PCT)
+    }
+
+    fn read_with_limits(
+        mut data: Vec<u8>,
+        current_slot: u64,
+        max_staleness_slots: u64,
+        max_confidence_pct: u8,
+    ) -> std::result::Result<Decimal, ProgramError> {
+        let key = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        get_pyth_price(&info, current_slot, max_staleness_slots, max_confidence_pct)
+    }
+
+    #[test]
+    fn test_get_pyth_price() {
+        // 12.345 with a negative exponent
+        let price = read(price_account(12_345, 10, -3, PYTH_STATUS_TRADING, 100), 110).unwrap();
+        assert_eq!(price, Decimal::from(12_345u64).try_div(1_000u64).unwrap());
+
+        // 7000 with a positive exponent
+        let price = read(price_account(7, 0, 3, PYTH_STATUS_TRADING, 100), 100).unwrap();
+        assert_eq!(price, Decimal::from(7_000u64));
+    }
+
+    #[test]
+    fn test_get_pyth_price_rejects_unusable_prices() {
+        let invalid = Err(LendingError::InvalidOracleConfig.into());
+
+        // not trading
+        assert_eq!(read(price_account(100, 0, 0, 0, 100), 100), invalid);
+        // stale
+        assert_eq!(
+            read(price_account(100, 0, 0, PYTH_STATUS_TRADING, 100), 100 + MAX_STALENESS_SLOTS),
+            invalid
+        );
+        // negative
+        assert_eq!(read(price_account(-100, 0, 0, PYTH_STATUS_TRADING, 100), 100), invalid);
+        // confidence too wide
+        assert_eq!(read(price_account(100, 11, 0, PYTH_STATUS_TRADING, 100), 100), invalid);
+
+        // not a price account
+        let mut data = price_account(100, 0, 0, PYTH_STATUS_TRADING, 100);
+        data[ACCOUNT_TYPE_OFFSET] = 2;
+        assert_eq!(read(data, 100), invalid);
+    }
+
+    #[test]
+    fn test_get_pyth_price_configured_limits() {
+        let invalid = Err(LendingError::InvalidOracleConfig.into());
+        let price = Ok(Decimal::from(100u64));
+        let account = || price_account(100, 5, 0, PYTH_STATUS_TRADING, 100);
+
+        // usable until max_staleness_slots have passed since the publish slot
+        assert_eq!(read_with_limits(account(), 109, 10, 10), price);
+        assert_eq!(read_with_limits(account(), 110, 10, 10), invalid);
+        assert_eq!(read_with_limits(account(), 199, 100, 10), price);
+
+        // a confidence of 5% of the price is usable up to a max of 5%
+        assert_eq!(read_with_limits(account(), 100, 10, 5), price);
+        assert_eq!(read_with_limits(account(), 100, 10, 4), invalid);
+    }
+}
@@ -0,0 +1,80 @@
+//! Interface of the swap adapter programs used by the leveraged position instructions.
+//!
+//! An adapter is any program exposing an Anchor style `swap(amount_in: u64, min_amount_out: u64)`
+//! instruction whose first accounts are the source token account (writable), the destination
+//! token account (writable) and the authority of the source (signer). Any accounts the adapter
+//! needs to route the swap follow and are passed through untouched. The lending program never
+//! trusts the adapter: the amount out is measured on the destination account.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke,
+};
+use anchor_spl::token::accessor;
+
+use crate::error::LendingError;
+
+/// Anchor discriminator of the adapter `swap` instruction, `sha256("global:swap")[..8]`
+pub const SWAP_ADAPTER_SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+/// Swap `amount_in` from `source` into `destination` through `adapter_program` and return the
+/// amount received, which must be at least `min_amount_out`
+pub fn swap<'info>(
+    adapter_program: &AccountInfo<'info>,
+    source: &AccountInfo<'info>,
+    destination: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    adapter_accounts: &[AccountInfo<'info>],
+    amount_in: u64,
+    min_amount_out: u64,
+) -> Result<u64> {
+    if !adapter_program.executable || *adapter_program.key == crate::ID {
+        msg!("Swap adapter must be an executable program other than the lending program");
+        return Err(ProgramError::from(LendingError::InvalidAccountInput).into());
+    }
+
+    let mut data = Vec::with_capacity(24);
+    data.extend_from_slice(&SWAP_ADAPTER_SWAP_DISCRIMINATOR);
+    data.extend_from_slice(&amount_in.to_le_bytes());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+
+    let mut accounts = vec![
+        AccountMeta::new(*source.key, false),
+        AccountMeta::new(*destination.key, false),
+        AccountMeta::new_readonly(*authority.key, true),
+    ];
+    accounts.extend(adapter_accounts.iter().map(|account| AccountMeta {
+        pubkey: *account.key,
+        is_signer: account.is_signer,
+        is_writable: account.is_writable,
+    }));
+
+    let mut account_infos = vec![source.clone(), destination.clone(), authority.clone()];
+    account_infos.extend_from_slice(adapter_accounts);
+    account_infos.push(adapter_program.clone());
+
+    let balance_before = accessor::amount(destination)?;
+    invoke(
+        &Instruction {
+            program_id: *adapter_program.key,
+            accounts,
+            data,
+        },
+        &account_infos,
+    )?;
+    let amount_out = accessor::amount(destination)?
+        .checked_sub(balance_before)
+        .ok_or(ProgramError::from(LendingError::MathOverflow))?;
+
+    if amount_out < min_amount_out {
+        msg!(
+            "Swap returned {} which is below the minimum amount out {}",
+            amount_out,
+            min_amount_out
+        );
+        return Err(ProgramError::from(LendingError::SwapAmountTooSmall).into());
+    }
+
+    Ok(amount_out)
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use anchor_lang::{prelude::*, solana_program::instruction::Instruction, InstructionData};
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+use splyce_lending::{error::LendingError, math::Decimal, state::Obligation};
+
+fn close_obligation(obligation: Pubkey, owner: &Keypair) -> Instruction {
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::CloseObligation {
+            obligation,
+            owner: owner.pubkey(),
+        }
+        .to_account_metas(None),
+        data: splyce_lending::instruction::CloseObligation {}.data(),
+    }
+}
+
+#[tokio::test]
+async fn test_close_obligation_refunds_rent_to_owner() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let owner = env.create_wallet(1_000_000_000);
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    let obligation_lamports = env.lamports(&obligation).await;
+    let owner_lamports = env.lamports(&owner.pubkey()).await;
+
+    // only the owner can close the obligation
+    let stranger = env.create_wallet(1_000_000_000);
+    assert_eq!(
+        env.process_transaction(&[close_obligation(obligation, &stranger)], &[&stranger])
+            .await,
+        Err(lending_error(LendingError::InvalidObligationOwner))
+    );
+
+    env.process_transaction(&[close_obligation(obligation, &owner)], &[&owner])
+        .await
+        .unwrap();
+
+    assert!(env.account(&obligation).await.is_none());
+    assert_eq!(
+        env.lamports(&owner.pubkey()).await,
+        owner_lamports + obligation_lamports
+    );
+}
+
+#[tokio::test]
+async fn test_close_obligation_rejects_deposits_and_borrows() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+    let owner = env.create_wallet(1_000_000_000);
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+
+    let mut obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    obligation_state
+        .find_or_add_collateral_to_deposits(reserve.key)
+        .unwrap()
+        .deposit(100)
+        .unwrap();
+    env.set_zero_copy_account(obligation, &obligation_state);
+    assert_eq!(
+        env.process_transaction(&[close_obligation(obligation, &owner)], &[&owner])
+            .await,
+        Err(lending_error(LendingError::ObligationDepositsNotEmpty))
+    );
+
+    obligation_state.withdraw(100, 0).unwrap();
+    obligation_state
+        .find_or_add_liquidity_to_borrows(reserve.key, Decimal::one())
+        .unwrap()
+        .borrow(Decimal::from(100u64))
+        .unwrap();
+    env.set_zero_copy_account(obligation, &obligation_state);
+    assert_eq!(
+        env.process_transaction(&[close_obligation(obligation, &owner)], &[&owner])
+            .await,
+        Err(lending_error(LendingError::ObligationBorrowsNotEmpty))
+    );
+    assert!(env.account(&obligation).await.is_some());
+}
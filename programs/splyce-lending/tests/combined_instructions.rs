@@ -0,0 +1,343 @@
+mod common;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{instruction::Instruction, system_program},
+    InstructionData,
+};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+use splyce_lending::{error::LendingError, state::Obligation};
+
+fn withdraw(
+    lending_market: &TestLendingMarket,
+    reserve: &TestReserve,
+    obligation: Pubkey,
+    owner: &Keypair,
+    destination_liquidity: Option<Pubkey>,
+    collateral_amount: u64,
+) -> Instruction {
+    let unwrap_native_sol = destination_liquidity.is_none();
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::WithdrawObligationCollateralAndRedeemReserveCollateral {
+            reserve_collateral_supply: reserve.collateral_supply,
+            reserve_collateral_mint: reserve.collateral_mint,
+            reserve_liquidity_supply: reserve.liquidity_supply,
+            destination_liquidity,
+            withdraw_reserve: reserve.key,
+            obligation,
+            lending_market: lending_market.key,
+            obligation_owner: owner.pubkey(),
+            token_program: spl_token::ID,
+            temporary_wsol: unwrap_native_sol.then(|| temporary_wsol(&owner.pubkey())),
+            native_mint: unwrap_native_sol.then_some(spl_token::native_mint::ID),
+            system_program: unwrap_native_sol.then_some(system_program::ID),
+        }
+        .to_account_metas(None),
+        data: splyce_lending::instruction::WithdrawObligationCollateralAndRedeemReserveCollateral {
+            collateral_amount,
+        }
+        .data(),
+    }
+}
+
+#[tokio::test]
+async fn test_deposit_reserve_liquidity_and_obligation_collateral() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+    let owner = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(reserve.liquidity_mint, owner.pubkey(), 500_000)
+        .await;
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+
+    env.process_transaction(
+        &[deposit(
+            &lending_market,
+            &reserve,
+            obligation,
+            &owner,
+            Some(source_liquidity),
+            false,
+            100_000,
+        )],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&source_liquidity).await, 400_000);
+    assert_eq!(env.token_balance(&reserve.liquidity_supply).await, 1_100_000);
+    // the collateral is minted straight into the reserve collateral supply
+    assert_eq!(env.token_balance(&reserve.collateral_supply).await, 100_000);
+    assert_eq!(env.mint(&reserve.collateral_mint).await.supply, 1_100_000);
+
+    let reserve_state = reserve.state(&mut env).await;
+    assert_eq!(reserve_state.liquidity.available_amount, 1_100_000);
+    assert_eq!(reserve_state.collateral.mint_total_supply, 1_100_000);
+    let slot = env.slot().await;
+    assert!(reserve_state.last_update.is_stale(slot).unwrap());
+
+    let obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    assert_eq!(obligation_state.deposits().len(), 1);
+    assert_eq!(obligation_state.deposits()[0].deposit_reserve, reserve.key);
+    assert_eq!(obligation_state.deposits()[0].deposited_amount, 100_000);
+}
+
+#[tokio::test]
+async fn test_deposit_reserve_liquidity_and_obligation_collateral_native_sol() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(
+        &mut env,
+        &lending_market,
+        Some(spl_token::native_mint::ID),
+        9,
+        1,
+        1_000_000,
+        |_| {},
+    )
+    .await;
+    let owner = env.create_wallet(1_000_000_000);
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    let lamports_before = env.lamports(&owner.pubkey()).await;
+
+    env.process_transaction(
+        &[deposit(&lending_market, &reserve, obligation, &owner, None, true, 100_000)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    // only the deposit leaves the wallet, the temporary account rent is returned
+    assert_eq!(env.lamports(&owner.pubkey()).await, lamports_before - 100_000);
+    assert!(env.account(&temporary_wsol(&owner.pubkey())).await.is_none());
+    assert_eq!(env.token_balance(&reserve.liquidity_supply).await, 1_100_000);
+    assert_eq!(env.token_balance(&reserve.collateral_supply).await, 100_000);
+
+    let obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    assert_eq!(obligation_state.deposits()[0].deposited_amount, 100_000);
+}
+
+#[tokio::test]
+async fn test_deposit_reserve_liquidity_and_obligation_collateral_native_sol_prefunded() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(
+        &mut env,
+        &lending_market,
+        Some(spl_token::native_mint::ID),
+        9,
+        1,
+        1_000_000,
+        |_| {},
+    )
+    .await;
+    let owner = env.create_wallet(1_000_000_000);
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    // lamports sent to the temporary account address do not block the deposit
+    env.set_account(
+        temporary_wsol(&owner.pubkey()),
+        solana_sdk::account::Account::new(1_000, 0, &system_program::ID),
+    );
+
+    env.process_transaction(
+        &[deposit(&lending_market, &reserve, obligation, &owner, None, true, 100_000)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert!(env.account(&temporary_wsol(&owner.pubkey())).await.is_none());
+    assert_eq!(env.token_balance(&reserve.liquidity_supply).await, 1_100_000);
+}
+
+#[tokio::test]
+async fn test_deposit_reserve_liquidity_and_obligation_collateral_requires_one_source() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(
+        &mut env,
+        &lending_market,
+        Some(spl_token::native_mint::ID),
+        9,
+        1,
+        1_000_000,
+        |_| {},
+    )
+    .await;
+    let owner = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(spl_token::native_mint::ID, owner.pubkey(), 500_000)
+        .await;
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+
+    for (source_liquidity, wrap_native_sol) in [(Some(source_liquidity), true), (None, false)] {
+        assert_eq!(
+            env.process_transaction(
+                &[deposit(
+                    &lending_market,
+                    &reserve,
+                    obligation,
+                    &owner,
+                    source_liquidity,
+                    wrap_native_sol,
+                    100_000,
+                )],
+                &[&owner],
+            )
+            .await,
+            Err(lending_error(LendingError::InvalidAccountInput))
+        );
+    }
+
+    // native SOL only goes into the native mint reserve
+    let other_reserve =
+        TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+    assert_eq!(
+        env.process_transaction(
+            &[deposit(&lending_market, &other_reserve, obligation, &owner, None, true, 100_000)],
+            &[&owner],
+        )
+        .await,
+        Err(lending_error(LendingError::InvalidAccountInput))
+    );
+}
+
+#[tokio::test]
+async fn test_withdraw_obligation_collateral_and_redeem_reserve_collateral() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+    let owner = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(reserve.liquidity_mint, owner.pubkey(), 500_000)
+        .await;
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    env.process_transaction(
+        &[deposit(
+            &lending_market,
+            &reserve,
+            obligation,
+            &owner,
+            Some(source_liquidity),
+            false,
+            100_000,
+        )],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    // both the reserve and the obligation must be refreshed first
+    assert!(env
+        .process_transaction(
+            &[withdraw(
+                &lending_market,
+                &reserve,
+                obligation,
+                &owner,
+                Some(source_liquidity),
+                40_000,
+            )],
+            &[&owner],
+        )
+        .await
+        .is_err());
+
+    env.advance_slots(1).await;
+    env.set_pyth_price(reserve.oracle, 1, 0).await;
+    env.process_transaction(
+        &[
+            reserve.refresh(&lending_market),
+            refresh_obligation(&lending_market, obligation, &[&reserve], &[]),
+            withdraw(
+                &lending_market,
+                &reserve,
+                obligation,
+                &owner,
+                Some(source_liquidity),
+                40_000,
+            ),
+        ],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&source_liquidity).await, 440_000);
+    assert_eq!(env.token_balance(&reserve.liquidity_supply).await, 1_060_000);
+    assert_eq!(env.token_balance(&reserve.collateral_supply).await, 60_000);
+    assert_eq!(env.mint(&reserve.collateral_mint).await.supply, 1_060_000);
+    let obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    assert_eq!(obligation_state.deposits()[0].deposited_amount, 60_000);
+
+    // withdrawing everything removes the deposit
+    env.process_transaction(
+        &[
+            reserve.refresh(&lending_market),
+            refresh_obligation(&lending_market, obligation, &[&reserve], &[]),
+            withdraw(
+                &lending_market,
+                &reserve,
+                obligation,
+                &owner,
+                Some(source_liquidity),
+                u64::MAX,
+            ),
+        ],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&source_liquidity).await, 500_000);
+    assert_eq!(env.token_balance(&reserve.collateral_supply).await, 0);
+    let obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    assert!(obligation_state.deposits().is_empty());
+}
+
+#[tokio::test]
+async fn test_withdraw_obligation_collateral_and_redeem_reserve_collateral_native_sol() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(
+        &mut env,
+        &lending_market,
+        Some(spl_token::native_mint::ID),
+        9,
+        1,
+        1_000_000,
+        |_| {},
+    )
+    .await;
+    let owner = env.create_wallet(1_000_000_000);
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    env.process_transaction(
+        &[deposit(&lending_market, &reserve, obligation, &owner, None, true, 100_000)],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    let lamports_before = env.lamports(&owner.pubkey()).await;
+
+    env.process_transaction(
+        &[
+            reserve.refresh(&lending_market),
+            refresh_obligation(&lending_market, obligation, &[&reserve], &[]),
+            withdraw(&lending_market, &reserve, obligation, &owner, None, 100_000),
+        ],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.lamports(&owner.pubkey()).await, lamports_before + 100_000);
+    assert!(env.account(&temporary_wsol(&owner.pubkey())).await.is_none());
+    assert_eq!(env.token_balance(&reserve.liquidity_supply).await, 1_000_000);
+    let obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    assert!(obligation_state.deposits().is_empty());
+}
@@ -0,0 +1,513 @@
+//! Test harness for the lending program.
+//!
+//! Transactions run through a `solana-program-test` bank. The lending program is loaded as a
+//! native builtin through its Anchor entrypoint while the SPL token program runs from its
+//! deployed binary, so account checks, CPIs, sysvars and rollbacks are the runtime's own.
+
+#![allow(dead_code)]
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{
+        entrypoint::ProgramResult, instruction::Instruction, program_pack::Pack, system_program,
+    },
+    AccountDeserialize, AccountSerialize, ZeroCopy,
+};
+use anchor_spl::token::spl_token;
+use bytemuck::Zeroable;
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    instruction::InstructionError,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use splyce_lending::{
+    math::Decimal,
+    state::{InitLendingMarketParams, LastUpdate, LendingMarket, RateLimiter, Reserve},
+    utils::TEMPORARY_WSOL_SEED,
+};
+
+/// Anchor entrypoint of the lending program as a program-test builtin
+fn process_lending<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: the Anchor entrypoint ties the account slice to the account lifetime. The infos
+    // outlive this call and the entrypoint does not keep them once it returns.
+    let accounts = unsafe {
+        std::mem::transmute::<&'a [AccountInfo<'info>], &'a [AccountInfo<'a>]>(accounts)
+    };
+    splyce_lending::entry(program_id, accounts, data)
+}
+
+/// Bank transactions run against
+pub struct TestEnv {
+    pub context: ProgramTestContext,
+}
+
+/// Program test loading the lending program, other programs can be added before starting it
+pub fn lending_program_test() -> ProgramTest {
+    ProgramTest::new("splyce_lending", splyce_lending::ID, processor!(process_lending))
+}
+
+impl TestEnv {
+    pub async fn new() -> Self {
+        Self::with_programs(lending_program_test()).await
+    }
+
+    /// Start a bank with `program_test`, built from [`lending_program_test`]
+    pub async fn with_programs(program_test: ProgramTest) -> Self {
+        let mut env = Self {
+            context: program_test.start_with_context().await,
+        };
+        env.create_mint_at(spl_token::native_mint::ID, None, 9);
+        env
+    }
+
+    pub async fn slot(&mut self) -> u64 {
+        self.context
+            .banks_client
+            .get_sysvar::<Clock>()
+            .await
+            .unwrap()
+            .slot
+    }
+
+    pub async fn advance_slots(&mut self, slots: u64) {
+        let slot = self.slot().await;
+        self.context.warp_to_slot(slot + slots).unwrap();
+    }
+
+    pub fn set_account(&mut self, key: Pubkey, account: Account) {
+        self.context
+            .set_account(&key, &AccountSharedData::from(account));
+    }
+
+    pub async fn account(&mut self, key: &Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(*key).await.unwrap()
+    }
+
+    pub async fn lamports(&mut self, key: &Pubkey) -> u64 {
+        self.account(key).await.map_or(0, |account| account.lamports)
+    }
+
+    /// Create a system account holding `lamports`
+    pub fn create_wallet(&mut self, lamports: u64) -> Keypair {
+        let wallet = Keypair::new();
+        self.set_account(
+            wallet.pubkey(),
+            Account::new(lamports, 0, &system_program::ID),
+        );
+        wallet
+    }
+
+    /// Run `instructions` as one transaction paid by the bank payer and signed by `signers`.
+    /// Returns the error of the failing instruction, the transaction being rolled back.
+    pub async fn process_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> std::result::Result<(), InstructionError> {
+        // a new blockhash keeps a retried transaction from being deduplicated
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+
+        match self.context.banks_client.process_transaction(transaction).await {
+            Ok(()) => Ok(()),
+            Err(BanksClientError::TransactionError(TransactionError::InstructionError(_, err))) => {
+                Err(err)
+            }
+            Err(err) => panic!("transaction failed outside of an instruction: {err:?}"),
+        }
+    }
+
+    /// Store an Anchor account serialized with its discriminator
+    pub fn set_anchor_account<T: AccountSerialize>(&mut self, key: Pubkey, account: &T, space: usize) {
+        let mut data = Vec::with_capacity(space);
+        account.try_serialize(&mut data).unwrap();
+        data.resize(space, 0);
+        self.set_account(
+            key,
+            Account {
+                lamports: Rent::default().minimum_balance(space),
+                data,
+                owner: splyce_lending::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    pub async fn anchor_account<T: AccountDeserialize>(&mut self, key: &Pubkey) -> T {
+        let account = self.account(key).await.unwrap();
+        T::try_deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    /// Store a zero-copy account behind its discriminator
+    pub fn set_zero_copy_account<T: ZeroCopy>(&mut self, key: Pubkey, account: &T) {
+        let mut data = T::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(account));
+        self.set_account(
+            key,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: splyce_lending::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    pub async fn zero_copy_account<T: ZeroCopy>(&mut self, key: &Pubkey) -> T {
+        let account = self.account(key).await.unwrap();
+        assert_eq!(account.data[..8], T::DISCRIMINATOR);
+        bytemuck::pod_read_unaligned(&account.data[8..8 + std::mem::size_of::<T>()])
+    }
+
+    fn set_token_state<T: Pack>(&mut self, key: Pubkey, state: T, lamports: u64) {
+        let mut data = vec![0u8; T::LEN];
+        T::pack(state, &mut data).unwrap();
+        self.set_account(
+            key,
+            Account {
+                lamports,
+                data,
+                owner: spl_token::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    fn create_mint_at(&mut self, key: Pubkey, mint_authority: Option<Pubkey>, decimals: u8) {
+        self.set_token_state(
+            key,
+            spl_token::state::Mint {
+                mint_authority: mint_authority.into(),
+                supply: 0,
+                decimals,
+                is_initialized: true,
+                freeze_authority: None.into(),
+            },
+            Rent::default().minimum_balance(spl_token::state::Mint::LEN),
+        );
+    }
+
+    pub fn create_mint(&mut self, mint_authority: Pubkey, decimals: u8) -> Pubkey {
+        let key = Pubkey::new_unique();
+        self.create_mint_at(key, Some(mint_authority), decimals);
+        key
+    }
+
+    pub async fn mint(&mut self, key: &Pubkey) -> spl_token::state::Mint {
+        let account = self.account(key).await.unwrap();
+        spl_token::state::Mint::unpack(&account.data).unwrap()
+    }
+
+    /// Create a token account holding `amount`, wrapped SOL for the native mint. The mint supply
+    /// is raised by `amount`.
+    pub async fn create_token_account(&mut self, mint: Pubkey, owner: Pubkey, amount: u64) -> Pubkey {
+        let key = Pubkey::new_unique();
+        let rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+        let is_native = mint == spl_token::native_mint::ID;
+        self.set_token_state(
+            key,
+            spl_token::state::Account {
+                mint,
+                owner,
+                amount,
+                delegate: None.into(),
+                state: spl_token::state::AccountState::Initialized,
+                is_native: if is_native { Some(rent).into() } else { None.into() },
+                delegated_amount: 0,
+                close_authority: None.into(),
+            },
+            if is_native { rent + amount } else { rent },
+        );
+
+        let mut mint_state = self.mint(&mint).await;
+        mint_state.supply += amount;
+        let mint_lamports = self.lamports(&mint).await;
+        self.set_token_state(mint, mint_state, mint_lamports);
+        key
+    }
+
+    pub async fn token_account(&mut self, key: &Pubkey) -> spl_token::state::Account {
+        let account = self.account(key).await.unwrap();
+        spl_token::state::Account::unpack(&account.data).unwrap()
+    }
+
+    pub async fn token_balance(&mut self, key: &Pubkey) -> u64 {
+        self.token_account(key).await.amount
+    }
+
+    /// Store a trading Pyth v2 price account published in the current slot
+    pub async fn set_pyth_price(&mut self, key: Pubkey, price: i64, exponent: i32) {
+        let mut data = vec![0u8; 3_312];
+        data[0..4].copy_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        data[8..12].copy_from_slice(&3u32.to_le_bytes());
+        data[20..24].copy_from_slice(&exponent.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[224..228].copy_from_slice(&1u32.to_le_bytes());
+        let slot = self.slot().await;
+        data[232..240].copy_from_slice(&slot.to_le_bytes());
+        self.set_account(
+            key,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: Pubkey::new_unique(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+}
+
+/// Instruction error of a lending error
+pub fn lending_error(error: splyce_lending::error::LendingError) -> InstructionError {
+    InstructionError::Custom(error as u32)
+}
+
+/// Lending market owned by a funded wallet
+pub struct TestLendingMarket {
+    pub key: Pubkey,
+    pub owner: Keypair,
+}
+
+impl TestLendingMarket {
+    pub fn new(env: &mut TestEnv) -> Self {
+        let owner = env.create_wallet(1_000_000_000);
+        let (key, bump_seed) =
+            Pubkey::find_program_address(&[owner.pubkey().as_ref()], &splyce_lending::ID);
+        let mut lending_market = LendingMarket::new(InitLendingMarketParams {
+            bump_seed,
+            owner: owner.pubkey(),
+            quote_currency: [0u8; 32],
+            token_program_id: spl_token::ID,
+        });
+        lending_market.rate_limiter = RateLimiter::default();
+        env.set_anchor_account(key, &lending_market, 8 + LendingMarket::INIT_SPACE);
+
+        Self { key, owner }
+    }
+}
+
+/// Reserve and the token accounts it references
+pub struct TestReserve {
+    pub key: Pubkey,
+    pub liquidity_mint: Pubkey,
+    pub liquidity_supply: Pubkey,
+    pub collateral_mint: Pubkey,
+    pub collateral_supply: Pubkey,
+    pub fee_receiver: Pubkey,
+    pub oracle: Pubkey,
+}
+
+impl TestReserve {
+    /// Create a fresh reserve of `liquidity_mint`, or of a new mint with `decimals`, holding
+    /// `liquidity_amount` of available liquidity priced at `price` per whole token. `configure`
+    /// adjusts the reserve before it is stored.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn new(
+        env: &mut TestEnv,
+        lending_market: &TestLendingMarket,
+        liquidity_mint: Option<Pubkey>,
+        decimals: u8,
+        price: u64,
+        liquidity_amount: u64,
+        configure: impl FnOnce(&mut Reserve),
+    ) -> Self {
+        let liquidity_mint =
+            liquidity_mint.unwrap_or_else(|| env.create_mint(Pubkey::new_unique(), decimals));
+        let liquidity_supply = env
+            .create_token_account(liquidity_mint, lending_market.key, liquidity_amount)
+            .await;
+        let collateral_mint = env.create_mint(lending_market.key, decimals);
+        let collateral_supply = env
+            .create_token_account(collateral_mint, lending_market.key, 0)
+            .await;
+        // collateral of the initial liquidity, held by some depositor
+        env.create_token_account(collateral_mint, Pubkey::new_unique(), liquidity_amount)
+            .await;
+        let fee_receiver = env
+            .create_token_account(liquidity_mint, Pubkey::new_unique(), 0)
+            .await;
+        let oracle = Pubkey::new_unique();
+        env.set_pyth_price(oracle, price as i64, 0).await;
+
+        let slot = env.slot().await;
+        let mut reserve = Reserve::zeroed();
+        reserve.version = 1;
+        reserve.last_update = LastUpdate::new(slot);
+        reserve.last_update.update_slot(slot);
+        reserve.lending_market = lending_market.key;
+        reserve.liquidity.mint_pubkey = liquidity_mint;
+        reserve.liquidity.supply_pubkey = liquidity_supply;
+        reserve.liquidity.oracle_pubkey = oracle;
+        reserve.liquidity.available_amount = liquidity_amount;
+        reserve.liquidity.cumulative_borrow_rate_wads = Decimal::one();
+        reserve.liquidity.market_price = Decimal::from(price);
+        reserve.liquidity.mint_decimals = env.mint(&liquidity_mint).await.decimals;
+        reserve.collateral.mint_pubkey = collateral_mint;
+        reserve.collateral.mint_total_supply = liquidity_amount;
+        reserve.collateral.supply_pubkey = collateral_supply;
+        reserve.config.loan_to_value_ratio = 50;
+        reserve.config.liquidation_threshold = 60;
+        reserve.config.max_liquidation_threshold = 70;
+        reserve.config.liquidation_bonus = 5;
+        reserve.config.max_liquidation_bonus = 10;
+        reserve.config.deposit_limit = u64::MAX;
+        reserve.config.borrow_limit = u64::MAX;
+        reserve.config.fee_receiver = fee_receiver;
+        reserve.config.attributed_borrow_limit_open = u64::MAX;
+        reserve.config.attributed_borrow_limit_close = u64::MAX;
+        reserve.rate_limiter = RateLimiter::default();
+        configure(&mut reserve);
+
+        let key = Pubkey::new_unique();
+        env.set_zero_copy_account(key, &reserve);
+
+        Self {
+            key,
+            liquidity_mint,
+            liquidity_supply,
+            collateral_mint,
+            collateral_supply,
+            fee_receiver,
+            oracle,
+        }
+    }
+
+    pub async fn state(&self, env: &mut TestEnv) -> Reserve {
+        env.zero_copy_account(&self.key).await
+    }
+
+    /// refresh_reserve instruction for this reserve
+    pub fn refresh(&self, lending_market: &TestLendingMarket) -> Instruction {
+        Instruction {
+            program_id: splyce_lending::ID,
+            accounts: splyce_lending::accounts::RefreshReserve {
+                reserve: self.key,
+                lending_market: lending_market.key,
+                pyth_oracle: self.oracle,
+            }
+            .to_account_metas(None),
+            data: anchor_lang::InstructionData::data(&splyce_lending::instruction::RefreshReserve {}),
+        }
+    }
+}
+
+/// Create an obligation of `owner` through init_obligation
+pub async fn init_obligation(
+    env: &mut TestEnv,
+    lending_market: &TestLendingMarket,
+    owner: &Keypair,
+) -> Pubkey {
+    let seed = Pubkey::new_unique();
+    let (obligation, _) = Pubkey::find_program_address(
+        &[lending_market.key.as_ref(), seed.as_ref()],
+        &splyce_lending::ID,
+    );
+    env.process_transaction(
+        &[Instruction {
+            program_id: splyce_lending::ID,
+            accounts: splyce_lending::accounts::InitObligation {
+                obligation,
+                lending_market: lending_market.key,
+                owner: owner.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: anchor_lang::InstructionData::data(&splyce_lending::instruction::InitObligation {
+                seed,
+            }),
+        }],
+        &[owner],
+    )
+    .await
+    .unwrap();
+    obligation
+}
+
+/// refresh_obligation instruction over the obligation's deposit then borrow reserves
+pub fn refresh_obligation(
+    lending_market: &TestLendingMarket,
+    obligation: Pubkey,
+    deposit_reserves: &[&TestReserve],
+    borrow_reserves: &[&TestReserve],
+) -> Instruction {
+    let mut accounts = splyce_lending::accounts::RefreshObligation {
+        obligation,
+        lending_market: lending_market.key,
+    }
+    .to_account_metas(None);
+    accounts.extend(
+        deposit_reserves
+            .iter()
+            .map(|reserve| AccountMeta::new(reserve.key, false)),
+    );
+    accounts.extend(
+        borrow_reserves
+            .iter()
+            .map(|reserve| AccountMeta::new_readonly(reserve.key, false)),
+    );
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts,
+        data: anchor_lang::InstructionData::data(&splyce_lending::instruction::RefreshObligation {}),
+    }
+}
+
+/// Temporary wrapped SOL account of `owner`
+pub fn temporary_wsol(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[TEMPORARY_WSOL_SEED, owner.as_ref()], &splyce_lending::ID).0
+}
+
+/// deposit_reserve_liquidity_and_obligation_collateral instruction, from `source_liquidity` or
+/// from the owner's lamports with `wrap_native_sol`
+pub fn deposit(
+    lending_market: &TestLendingMarket,
+    reserve: &TestReserve,
+    obligation: Pubkey,
+    owner: &Keypair,
+    source_liquidity: Option<Pubkey>,
+    wrap_native_sol: bool,
+    liquidity_amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::DepositReserveLiquidityAndObligationCollateral {
+            source_liquidity,
+            reserve_liquidity_supply: reserve.liquidity_supply,
+            reserve_collateral_mint: reserve.collateral_mint,
+            reserve_collateral_supply: reserve.collateral_supply,
+            deposit_reserve: reserve.key,
+            obligation,
+            lending_market: lending_market.key,
+            obligation_owner: owner.pubkey(),
+            user_transfer_authority: owner.pubkey(),
+            token_program: spl_token::ID,
+            temporary_wsol: wrap_native_sol.then(|| temporary_wsol(&owner.pubkey())),
+            native_mint: wrap_native_sol.then_some(spl_token::native_mint::ID),
+            system_program: wrap_native_sol.then_some(system_program::ID),
+        }
+        .to_account_metas(None),
+        data: anchor_lang::InstructionData::data(
+            &splyce_lending::instruction::DepositReserveLiquidityAndObligationCollateral {
+                liquidity_amount,
+            },
+        ),
+    }
+}
@@ -0,0 +1,638 @@
+mod common;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{entrypoint::ProgramResult, instruction::Instruction, program::invoke, sysvar},
+    InstructionData,
+};
+use anchor_spl::token::spl_token;
+use common::*;
+use mock_flash_loan_receiver::RECEIVER_AUTHORITY_SEED;
+use solana_program_test::processor;
+use solana_sdk::signature::{Keypair, Signer};
+use splyce_lending::{error::LendingError, math::Decimal, state::Reserve};
+
+/// 0.3% flash loan fee, a fifth of it to the host
+const FLASH_LOAN_FEE_WAD: u64 = 3_000_000_000_000_000;
+const HOST_FEE_PERCENTAGE: u8 = 20;
+
+fn flash_borrow(
+    lending_market: &TestLendingMarket,
+    reserve: &TestReserve,
+    destination_liquidity: Pubkey,
+    liquidity_amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::FlashBorrowReserveLiquidity {
+            reserve: reserve.key,
+            source_liquidity: reserve.liquidity_supply,
+            destination_liquidity,
+            lending_market: lending_market.key,
+            instructions: sysvar::instructions::ID,
+            token_program: spl_token::ID,
+        }
+        .to_account_metas(None),
+        data: splyce_lending::instruction::FlashBorrowReserveLiquidity { liquidity_amount }.data(),
+    }
+}
+
+fn flash_repay(
+    lending_market: &TestLendingMarket,
+    reserve: &TestReserve,
+    source_liquidity: Pubkey,
+    user_transfer_authority: Pubkey,
+    host_fee_receiver: Option<Pubkey>,
+    liquidity_amount: u64,
+    borrow_instruction_index: u8,
+) -> Instruction {
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::FlashRepayReserveLiquidity {
+            reserve: reserve.key,
+            source_liquidity,
+            destination_liquidity: reserve.liquidity_supply,
+            reserve_liquidity_fee_receiver: reserve.fee_receiver,
+            lending_market: lending_market.key,
+            user_transfer_authority,
+            instructions: sysvar::instructions::ID,
+            token_program: spl_token::ID,
+            host_fee_receiver,
+        }
+        .to_account_metas(None),
+        data: splyce_lending::instruction::FlashRepayReserveLiquidity {
+            liquidity_amount,
+            borrow_instruction_index,
+        }
+        .data(),
+    }
+}
+
+fn flash_loan(
+    lending_market: &TestLendingMarket,
+    reserve: &TestReserve,
+    destination_liquidity: Pubkey,
+    flash_loan_receiver_program: Pubkey,
+    receiver_accounts: Vec<AccountMeta>,
+    liquidity_amount: u64,
+) -> Instruction {
+    let mut accounts = splyce_lending::accounts::FlashLoan {
+        reserve: reserve.key,
+        source_liquidity: reserve.liquidity_supply,
+        destination_liquidity,
+        reserve_liquidity_fee_receiver: reserve.fee_receiver,
+        lending_market: lending_market.key,
+        flash_loan_receiver_program,
+        token_program: spl_token::ID,
+    }
+    .to_account_metas(None);
+    accounts.extend(receiver_accounts);
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts,
+        data: splyce_lending::instruction::FlashLoan { liquidity_amount }.data(),
+    }
+}
+
+/// Anchor entrypoint of the mock flash loan receiver as a program-test builtin
+fn process_mock_flash_loan_receiver<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: same as for the lending program, the entrypoint does not keep the infos
+    let accounts = unsafe {
+        std::mem::transmute::<&'a [AccountInfo<'info>], &'a [AccountInfo<'a>]>(accounts)
+    };
+    mock_flash_loan_receiver::entry(program_id, accounts, data)
+}
+
+/// Mock receiver token account of the reserve liquidity holding `amount`, and the receiver
+/// accounts to pass along
+async fn mock_receiver(
+    env: &mut TestEnv,
+    reserve: &TestReserve,
+    amount: u64,
+) -> (Pubkey, Vec<AccountMeta>) {
+    let (receiver_authority, _) =
+        Pubkey::find_program_address(&[RECEIVER_AUTHORITY_SEED], &mock_flash_loan_receiver::ID);
+    let destination_liquidity = env
+        .create_token_account(reserve.liquidity_mint, receiver_authority, amount)
+        .await;
+    (
+        destination_liquidity,
+        vec![AccountMeta::new_readonly(receiver_authority, false)],
+    )
+}
+
+/// Flash loan receiver checking that the reserve is locked before forwarding the callback to the
+/// mock flash loan receiver, with the accounts destination, supply, token program, receiver
+/// authority, reserve and the mock flash loan receiver program
+///
+/// A receiver cannot reach the reserve through the lending program, the runtime rejects the
+/// reentrant invocation, the lock is what keeps it unusable should it ever be allowed.
+fn process_lock_checking_receiver(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let reserve_state: Reserve = bytemuck::pod_read_unaligned(
+        &accounts[4].data.borrow()[8..8 + std::mem::size_of::<Reserve>()],
+    );
+    if reserve_state.reentrancy_lock == 0 {
+        msg!("Reserve {} is not locked during the flash loan", accounts[4].key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    invoke(
+        &Instruction {
+            program_id: mock_flash_loan_receiver::ID,
+            accounts: accounts[..4]
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: *account.key,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: data.to_vec(),
+        },
+        accounts,
+    )
+}
+
+/// Program id of the lock checking flash loan receiver
+fn lock_checking_receiver() -> Pubkey {
+    Pubkey::new_from_array([9; 32])
+}
+
+/// Program forwarding its instruction to the lending program through a CPI, with the lending
+/// program as its last account
+fn process_cpi_proxy(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    invoke(
+        &Instruction {
+            program_id: splyce_lending::ID,
+            accounts: accounts[..accounts.len() - 1]
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: *account.key,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: data.to_vec(),
+        },
+        accounts,
+    )
+}
+
+/// Program id of the CPI proxy
+fn cpi_proxy() -> Pubkey {
+    Pubkey::new_from_array([8; 32])
+}
+
+/// Send a lending program instruction through the CPI proxy
+fn through_cpi_proxy(mut instruction: Instruction) -> Instruction {
+    instruction.program_id = cpi_proxy();
+    instruction
+        .accounts
+        .push(AccountMeta::new_readonly(splyce_lending::ID, false));
+    instruction
+}
+
+/// Bank with the lending program, the CPI proxy and both flash loan receivers
+async fn test_env() -> TestEnv {
+    let mut program_test = lending_program_test();
+    program_test.add_program("cpi_proxy", cpi_proxy(), processor!(process_cpi_proxy));
+    program_test.add_program(
+        "mock_flash_loan_receiver",
+        mock_flash_loan_receiver::ID,
+        processor!(process_mock_flash_loan_receiver),
+    );
+    program_test.add_program(
+        "lock_checking_receiver",
+        lock_checking_receiver(),
+        processor!(process_lock_checking_receiver),
+    );
+    TestEnv::with_programs(program_test).await
+}
+
+/// Reserve holding 10_000_000 with the test flash loan fees, and a user holding 10_000 of it
+async fn setup(env: &mut TestEnv) -> (TestLendingMarket, TestReserve, Keypair, Pubkey) {
+    let lending_market = TestLendingMarket::new(env);
+    let reserve = TestReserve::new(env, &lending_market, None, 6, 1, 10_000_000, |reserve| {
+        reserve.config.fees.flash_loan_fee_wad = FLASH_LOAN_FEE_WAD;
+        reserve.config.fees.host_fee_percentage = HOST_FEE_PERCENTAGE;
+    })
+    .await;
+    let user = env.create_wallet(1_000_000_000);
+    let user_liquidity = env
+        .create_token_account(reserve.liquidity_mint, user.pubkey(), 10_000)
+        .await;
+    (lending_market, reserve, user, user_liquidity)
+}
+
+#[tokio::test]
+async fn test_flash_borrow_and_repay() {
+    let mut env = TestEnv::new().await;
+    let (lending_market, reserve, user, user_liquidity) = setup(&mut env).await;
+    let host_fee_receiver = env
+        .create_token_account(reserve.liquidity_mint, Pubkey::new_unique(), 0)
+        .await;
+
+    // the 3_000 fee is paid on top of the 1_000_000 borrowed, 600 of it to the host
+    env.process_transaction(
+        &[
+            flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+            flash_repay(
+                &lending_market,
+                &reserve,
+                user_liquidity,
+                user.pubkey(),
+                Some(host_fee_receiver),
+                1_000_000,
+                0,
+            ),
+        ],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&user_liquidity).await, 7_000);
+    assert_eq!(
+        env.token_balance(&reserve.liquidity_supply).await,
+        10_000_000
+    );
+    assert_eq!(env.token_balance(&reserve.fee_receiver).await, 2_400);
+    assert_eq!(env.token_balance(&host_fee_receiver).await, 600);
+    let reserve_state = reserve.state(&mut env).await;
+    assert_eq!(reserve_state.liquidity.available_amount, 10_000_000);
+    assert_eq!(reserve_state.liquidity.borrowed_amount_wads, Decimal::zero());
+
+    // without a host fee receiver the whole fee goes to the reserve
+    env.process_transaction(
+        &[
+            flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+            flash_repay(
+                &lending_market,
+                &reserve,
+                user_liquidity,
+                user.pubkey(),
+                None,
+                1_000_000,
+                0,
+            ),
+        ],
+        &[&user],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&user_liquidity).await, 4_000);
+    assert_eq!(env.token_balance(&reserve.fee_receiver).await, 5_400);
+    assert_eq!(env.token_balance(&host_fee_receiver).await, 600);
+}
+
+#[tokio::test]
+async fn test_flash_borrow_requires_matching_repay() {
+    let mut env = TestEnv::new().await;
+    let (lending_market, reserve, user, user_liquidity) = setup(&mut env).await;
+    let other_reserve = TestReserve::new(
+        &mut env,
+        &lending_market,
+        Some(reserve.liquidity_mint),
+        6,
+        1,
+        10_000_000,
+        |_| {},
+    )
+    .await;
+    let repay = |reserve: &TestReserve, liquidity_amount: u64, borrow_instruction_index: u8| {
+        flash_repay(
+            &lending_market,
+            reserve,
+            user_liquidity,
+            user.pubkey(),
+            None,
+            liquidity_amount,
+            borrow_instruction_index,
+        )
+    };
+
+    assert_eq!(
+        env.process_transaction(
+            &[flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000)],
+            &[],
+        )
+        .await,
+        Err(lending_error(LendingError::NoFlashRepayFound))
+    );
+
+    // the repay must be of the same amount and reserve, and point back at the borrow
+    for repay in [
+        repay(&reserve, 999_999, 0),
+        repay(&other_reserve, 1_000_000, 0),
+        repay(&reserve, 1_000_000, 1),
+    ] {
+        assert_eq!(
+            env.process_transaction(
+                &[
+                    flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+                    repay,
+                ],
+                &[&user],
+            )
+            .await,
+            Err(lending_error(LendingError::InvalidFlashRepay))
+        );
+    }
+
+    // a repay cannot stand alone
+    assert_eq!(
+        env.process_transaction(
+            &[reserve.refresh(&lending_market), repay(&reserve, 1_000_000, 0)],
+            &[&user],
+        )
+        .await,
+        Err(lending_error(LendingError::InvalidFlashRepay))
+    );
+
+    assert_eq!(env.token_balance(&user_liquidity).await, 10_000);
+    assert_eq!(
+        env.token_balance(&reserve.liquidity_supply).await,
+        10_000_000
+    );
+}
+
+#[tokio::test]
+async fn test_flash_borrow_rejects_multiple_borrows() {
+    let mut env = TestEnv::new().await;
+    let (lending_market, reserve, user, user_liquidity) = setup(&mut env).await;
+    let repay = |borrow_instruction_index: u8| {
+        flash_repay(
+            &lending_market,
+            &reserve,
+            user_liquidity,
+            user.pubkey(),
+            None,
+            1_000_000,
+            borrow_instruction_index,
+        )
+    };
+
+    assert_eq!(
+        env.process_transaction(
+            &[
+                flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+                flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+                repay(0),
+                repay(1),
+            ],
+            &[&user],
+        )
+        .await,
+        Err(lending_error(LendingError::MultipleFlashBorrows))
+    );
+}
+
+#[tokio::test]
+async fn test_flash_borrow_and_repay_reject_cpi() {
+    let mut env = test_env().await;
+    let (lending_market, reserve, user, user_liquidity) = setup(&mut env).await;
+    let repay = || {
+        flash_repay(
+            &lending_market,
+            &reserve,
+            user_liquidity,
+            user.pubkey(),
+            None,
+            1_000_000,
+            0,
+        )
+    };
+
+    assert_eq!(
+        env.process_transaction(
+            &[
+                through_cpi_proxy(flash_borrow(
+                    &lending_market,
+                    &reserve,
+                    user_liquidity,
+                    1_000_000
+                )),
+                repay(),
+            ],
+            &[&user],
+        )
+        .await,
+        Err(lending_error(LendingError::FlashBorrowCpi))
+    );
+
+    // the borrow only looks for top-level repays, the proxied one runs in addition to it
+    assert_eq!(
+        env.process_transaction(
+            &[
+                flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+                through_cpi_proxy(repay()),
+                repay(),
+            ],
+            &[&user],
+        )
+        .await,
+        Err(lending_error(LendingError::FlashRepayCpi))
+    );
+    assert_eq!(env.token_balance(&user_liquidity).await, 10_000);
+}
+
+#[tokio::test]
+async fn test_flash_loans_disabled() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 10_000_000, |reserve| {
+        reserve.config.fees.flash_loan_fee_wad = u64::MAX;
+    })
+    .await;
+    let user = env.create_wallet(1_000_000_000);
+    let user_liquidity = env
+        .create_token_account(reserve.liquidity_mint, user.pubkey(), 10_000)
+        .await;
+
+    assert_eq!(
+        env.process_transaction(
+            &[
+                flash_borrow(&lending_market, &reserve, user_liquidity, 1_000_000),
+                flash_repay(
+                    &lending_market,
+                    &reserve,
+                    user_liquidity,
+                    user.pubkey(),
+                    None,
+                    1_000_000,
+                    0,
+                ),
+            ],
+            &[&user],
+        )
+        .await,
+        Err(lending_error(LendingError::FlashLoansDisabled))
+    );
+}
+
+#[tokio::test]
+async fn test_flash_loan() {
+    let mut env = test_env().await;
+    let (lending_market, reserve, _, _) = setup(&mut env).await;
+    // the receiver only holds the fee besides the loan
+    let (receiver_liquidity, receiver_accounts) = mock_receiver(&mut env, &reserve, 3_000).await;
+
+    env.process_transaction(
+        &[flash_loan(
+            &lending_market,
+            &reserve,
+            receiver_liquidity,
+            mock_flash_loan_receiver::ID,
+            receiver_accounts.clone(),
+            1_000_000,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&receiver_liquidity).await, 0);
+    assert_eq!(
+        env.token_balance(&reserve.liquidity_supply).await,
+        10_000_000
+    );
+    assert_eq!(env.token_balance(&reserve.fee_receiver).await, 3_000);
+    let reserve_state = reserve.state(&mut env).await;
+    assert_eq!(reserve_state.reentrancy_lock, 0);
+    assert_eq!(reserve_state.liquidity.available_amount, 10_000_000);
+
+    // unlike the flash borrow and repay pair, it can be invoked through a CPI
+    let (receiver_liquidity, _) = mock_receiver(&mut env, &reserve, 3_000).await;
+    env.process_transaction(
+        &[through_cpi_proxy(flash_loan(
+            &lending_market,
+            &reserve,
+            receiver_liquidity,
+            mock_flash_loan_receiver::ID,
+            receiver_accounts,
+            1_000_000,
+        ))],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(env.token_balance(&reserve.fee_receiver).await, 6_000);
+}
+
+#[tokio::test]
+async fn test_flash_loan_requires_repayment_with_fee() {
+    let mut env = test_env().await;
+    let (lending_market, reserve, _, _) = setup(&mut env).await;
+    let (receiver_liquidity, receiver_accounts) = mock_receiver(&mut env, &reserve, 2_999).await;
+
+    assert_eq!(
+        env.process_transaction(
+            &[flash_loan(
+                &lending_market,
+                &reserve,
+                receiver_liquidity,
+                mock_flash_loan_receiver::ID,
+                receiver_accounts,
+                1_000_000,
+            )],
+            &[],
+        )
+        .await,
+        Err(lending_error(LendingError::NotEnoughLiquidityAfterFlashLoan))
+    );
+    assert_eq!(env.token_balance(&receiver_liquidity).await, 2_999);
+    assert_eq!(
+        env.token_balance(&reserve.liquidity_supply).await,
+        10_000_000
+    );
+}
+
+#[tokio::test]
+async fn test_flash_loan_rejects_reentrancy() {
+    let mut env = test_env().await;
+    let (lending_market, reserve, _, _) = setup(&mut env).await;
+
+    // the receiver fails unless the reserve is locked while it holds the loan
+    let (receiver_liquidity, mut receiver_accounts) =
+        mock_receiver(&mut env, &reserve, 3_000).await;
+    receiver_accounts.extend([
+        AccountMeta::new_readonly(reserve.key, false),
+        AccountMeta::new_readonly(mock_flash_loan_receiver::ID, false),
+    ]);
+    env.process_transaction(
+        &[flash_loan(
+            &lending_market,
+            &reserve,
+            receiver_liquidity,
+            lock_checking_receiver(),
+            receiver_accounts,
+            1_000_000,
+        )],
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(reserve.state(&mut env).await.reentrancy_lock, 0);
+
+    // a reserve locked by an instruction in progress cannot be flash loaned
+    let mut reserve_state = reserve.state(&mut env).await;
+    reserve_state.reentrancy_lock = 1;
+    env.set_zero_copy_account(reserve.key, &reserve_state);
+    let (receiver_liquidity, receiver_accounts) = mock_receiver(&mut env, &reserve, 3_000).await;
+    assert_eq!(
+        env.process_transaction(
+            &[flash_loan(
+                &lending_market,
+                &reserve,
+                receiver_liquidity,
+                mock_flash_loan_receiver::ID,
+                receiver_accounts,
+                1_000_000,
+            )],
+            &[],
+        )
+        .await,
+        Err(lending_error(LendingError::ReserveLocked))
+    );
+    assert_eq!(
+        env.token_balance(&reserve.liquidity_supply).await,
+        10_000_000
+    );
+    assert_eq!(env.token_balance(&receiver_liquidity).await, 3_000);
+}
+
+#[tokio::test]
+async fn test_flash_loan_rejects_invalid_receiver_program() {
+    let mut env = test_env().await;
+    let (lending_market, reserve, user, user_liquidity) = setup(&mut env).await;
+
+    // the receiver must be a program, and neither the lending nor the token program
+    for receiver in [user.pubkey(), splyce_lending::ID, spl_token::ID] {
+        assert_eq!(
+            env.process_transaction(
+                &[flash_loan(
+                    &lending_market,
+                    &reserve,
+                    user_liquidity,
+                    receiver,
+                    vec![],
+                    1_000_000,
+                )],
+                &[],
+            )
+            .await,
+            Err(lending_error(LendingError::InvalidFlashLoanReceiverProgram))
+        );
+    }
+    assert_eq!(env.token_balance(&user_liquidity).await, 10_000);
+}
@@ -0,0 +1,407 @@
+mod common;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{entrypoint::ProgramResult, instruction::Instruction, program::invoke},
+    InstructionData,
+};
+use anchor_spl::token::spl_token;
+use common::*;
+use mock_swap_adapter::POOL_AUTHORITY_SEED;
+use solana_program_test::processor;
+use solana_sdk::signature::{Keypair, Signer};
+use splyce_lending::{error::LendingError, math::Decimal, state::{Obligation, Reserve}};
+
+/// Anchor entrypoint of the mock swap adapter as a program-test builtin
+fn process_mock_swap_adapter<'a, 'info>(
+    program_id: &Pubkey,
+    accounts: &'a [AccountInfo<'info>],
+    data: &[u8],
+) -> ProgramResult {
+    // SAFETY: same as for the lending program, the entrypoint does not keep the infos
+    let accounts = unsafe {
+        std::mem::transmute::<&'a [AccountInfo<'info>], &'a [AccountInfo<'a>]>(accounts)
+    };
+    mock_swap_adapter::entry(program_id, accounts, data)
+}
+
+/// Swap adapter checking that the reserves it is given are locked before forwarding the swap to
+/// the mock swap adapter, with the accounts source, destination, authority, the two reserves, the
+/// mock swap adapter accounts and the mock swap adapter program
+///
+/// A swap adapter cannot reach the reserves through the lending program, the runtime rejects the
+/// reentrant invocation, the lock is what keeps them unusable should it ever be allowed.
+fn process_lock_checking_swap_adapter(
+    _program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    for reserve in &accounts[3..5] {
+        let reserve_state: Reserve = bytemuck::pod_read_unaligned(
+            &reserve.data.borrow()[8..8 + std::mem::size_of::<Reserve>()],
+        );
+        if reserve_state.reentrancy_lock == 0 {
+            msg!("Reserve {} is not locked during the swap", reserve.key);
+            return Err(ProgramError::InvalidAccountData);
+        }
+    }
+
+    let swap_accounts: Vec<AccountInfo> = accounts[..3]
+        .iter()
+        .chain(&accounts[5..9])
+        .cloned()
+        .collect();
+    invoke(
+        &Instruction {
+            program_id: mock_swap_adapter::ID,
+            accounts: swap_accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: *account.key,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: data.to_vec(),
+        },
+        accounts,
+    )
+}
+
+/// Program id of the lock checking swap adapter
+fn lock_checking_swap_adapter() -> Pubkey {
+    Pubkey::new_from_array([7; 32])
+}
+
+/// Bank with the lending program and both swap adapters
+async fn test_env() -> TestEnv {
+    let mut program_test = lending_program_test();
+    program_test.add_program(
+        "mock_swap_adapter",
+        mock_swap_adapter::ID,
+        processor!(process_mock_swap_adapter),
+    );
+    program_test.add_program(
+        "lock_checking_swap_adapter",
+        lock_checking_swap_adapter(),
+        processor!(process_lock_checking_swap_adapter),
+    );
+    TestEnv::with_programs(program_test).await
+}
+
+/// Leveraged obligation of a funded owner, with 100_000 of collateral in the deposit reserve
+struct TestPosition {
+    lending_market: TestLendingMarket,
+    deposit_reserve: TestReserve,
+    borrow_reserve: TestReserve,
+    owner: Keypair,
+    obligation: Pubkey,
+    user_deposit_liquidity: Pubkey,
+    user_borrow_liquidity: Pubkey,
+}
+
+impl TestPosition {
+    async fn new(env: &mut TestEnv) -> Self {
+        let lending_market = TestLendingMarket::new(env);
+        let deposit_reserve =
+            TestReserve::new(env, &lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+        let borrow_reserve =
+            TestReserve::new(env, &lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+        let owner = env.create_wallet(1_000_000_000);
+        let user_deposit_liquidity = env
+            .create_token_account(deposit_reserve.liquidity_mint, owner.pubkey(), 100_000)
+            .await;
+        let user_borrow_liquidity = env
+            .create_token_account(borrow_reserve.liquidity_mint, owner.pubkey(), 0)
+            .await;
+        let obligation = init_obligation(env, &lending_market, &owner).await;
+        env.process_transaction(
+            &[deposit(
+                &lending_market,
+                &deposit_reserve,
+                obligation,
+                &owner,
+                Some(user_deposit_liquidity),
+                false,
+                100_000,
+            )],
+            &[&owner],
+        )
+        .await
+        .unwrap();
+
+        Self {
+            lending_market,
+            deposit_reserve,
+            borrow_reserve,
+            owner,
+            obligation,
+            user_deposit_liquidity,
+            user_borrow_liquidity,
+        }
+    }
+
+    /// Refresh the reserves and the obligation in the current slot
+    async fn refresh(&self, env: &mut TestEnv) -> Vec<Instruction> {
+        env.set_pyth_price(self.deposit_reserve.oracle, 1, 0).await;
+        env.set_pyth_price(self.borrow_reserve.oracle, 1, 0).await;
+        let obligation_state: Obligation = env.zero_copy_account(&self.obligation).await;
+        let borrow_reserves: &[&TestReserve] = if obligation_state.borrows().is_empty() {
+            &[]
+        } else {
+            &[&self.borrow_reserve]
+        };
+        vec![
+            self.deposit_reserve.refresh(&self.lending_market),
+            self.borrow_reserve.refresh(&self.lending_market),
+            refresh_obligation(
+                &self.lending_market,
+                self.obligation,
+                &[&self.deposit_reserve],
+                borrow_reserves,
+            ),
+        ]
+    }
+
+    fn open(
+        &self,
+        swap_adapter_program: Pubkey,
+        adapter_accounts: Vec<AccountMeta>,
+        target_leverage_bps: u64,
+    ) -> Instruction {
+        let mut accounts = splyce_lending::accounts::OpenLeveragedPosition {
+            obligation: self.obligation,
+            lending_market: self.lending_market.key,
+            borrow_reserve: self.borrow_reserve.key,
+            borrow_reserve_liquidity_supply: self.borrow_reserve.liquidity_supply,
+            borrow_reserve_liquidity_fee_receiver: self.borrow_reserve.fee_receiver,
+            deposit_reserve: self.deposit_reserve.key,
+            deposit_reserve_liquidity_supply: self.deposit_reserve.liquidity_supply,
+            deposit_reserve_collateral_mint: self.deposit_reserve.collateral_mint,
+            deposit_reserve_collateral_supply: self.deposit_reserve.collateral_supply,
+            user_borrow_liquidity: self.user_borrow_liquidity,
+            user_deposit_liquidity: self.user_deposit_liquidity,
+            obligation_owner: self.owner.pubkey(),
+            swap_adapter_program,
+            token_program: spl_token::ID,
+        }
+        .to_account_metas(None);
+        accounts.extend(adapter_accounts);
+        Instruction {
+            program_id: splyce_lending::ID,
+            accounts,
+            data: splyce_lending::instruction::OpenLeveragedPosition {
+                target_leverage_bps,
+                min_swap_amount_out: 1,
+            }
+            .data(),
+        }
+    }
+
+    fn close(
+        &self,
+        swap_adapter_program: Pubkey,
+        adapter_accounts: Vec<AccountMeta>,
+        collateral_amount: u64,
+    ) -> Instruction {
+        let mut accounts = splyce_lending::accounts::CloseLeveragedPosition {
+            obligation: self.obligation,
+            lending_market: self.lending_market.key,
+            withdraw_reserve: self.deposit_reserve.key,
+            withdraw_reserve_collateral_mint: self.deposit_reserve.collateral_mint,
+            withdraw_reserve_collateral_supply: self.deposit_reserve.collateral_supply,
+            withdraw_reserve_liquidity_supply: self.deposit_reserve.liquidity_supply,
+            repay_reserve: self.borrow_reserve.key,
+            repay_reserve_liquidity_supply: self.borrow_reserve.liquidity_supply,
+            user_withdraw_liquidity: self.user_deposit_liquidity,
+            user_repay_liquidity: self.user_borrow_liquidity,
+            obligation_owner: self.owner.pubkey(),
+            swap_adapter_program,
+            token_program: spl_token::ID,
+        }
+        .to_account_metas(None);
+        accounts.extend(adapter_accounts);
+        Instruction {
+            program_id: splyce_lending::ID,
+            accounts,
+            data: splyce_lending::instruction::CloseLeveragedPosition {
+                collateral_amount,
+                min_swap_amount_out: 1,
+            }
+            .data(),
+        }
+    }
+}
+
+/// Mock swap adapter accounts swapping `source_mint` into a pool of `destination_mint` holding
+/// `pool_amount`
+async fn mock_swap_accounts(
+    env: &mut TestEnv,
+    source_mint: Pubkey,
+    destination_mint: Pubkey,
+    pool_amount: u64,
+) -> Vec<AccountMeta> {
+    let (pool_authority, _) =
+        Pubkey::find_program_address(&[POOL_AUTHORITY_SEED], &mock_swap_adapter::ID);
+    let pool_source = env.create_token_account(source_mint, pool_authority, 0).await;
+    let pool_destination = env
+        .create_token_account(destination_mint, pool_authority, pool_amount)
+        .await;
+    vec![
+        AccountMeta::new(pool_source, false),
+        AccountMeta::new(pool_destination, false),
+        AccountMeta::new_readonly(pool_authority, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+    ]
+}
+
+/// Lock checking swap adapter accounts given both reserves of the position, forwarding the
+/// swap with `mock_swap_accounts`
+fn lock_checking_swap_accounts(
+    position: &TestPosition,
+    mock_swap_accounts: Vec<AccountMeta>,
+) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new_readonly(position.deposit_reserve.key, false),
+        AccountMeta::new_readonly(position.borrow_reserve.key, false),
+    ];
+    accounts.extend(mock_swap_accounts);
+    accounts.push(AccountMeta::new_readonly(mock_swap_adapter::ID, false));
+    accounts
+}
+
+#[tokio::test]
+async fn test_open_and_close_leveraged_position() {
+    let mut env = test_env().await;
+    let position = TestPosition::new(&mut env).await;
+
+    // 2x of the 100_000 equity borrows 100_000, swapped 1:1 and deposited
+    let adapter_accounts = mock_swap_accounts(
+        &mut env,
+        position.borrow_reserve.liquidity_mint,
+        position.deposit_reserve.liquidity_mint,
+        100_000,
+    )
+    .await;
+    let mut instructions = position.refresh(&mut env).await;
+    instructions.push(position.open(mock_swap_adapter::ID, adapter_accounts, 20_000));
+    env.process_transaction(&instructions, &[&position.owner])
+        .await
+        .unwrap();
+
+    let obligation_state: Obligation = env.zero_copy_account(&position.obligation).await;
+    assert_eq!(obligation_state.deposits()[0].deposited_amount, 200_000);
+    assert_eq!(
+        obligation_state.borrows()[0].borrow_reserve,
+        position.borrow_reserve.key
+    );
+    assert_eq!(
+        obligation_state.borrows()[0].borrowed_amount_wads,
+        Decimal::from(100_000u64)
+    );
+    assert_eq!(env.token_balance(&position.user_borrow_liquidity).await, 0);
+    assert_eq!(
+        env.token_balance(&position.deposit_reserve.liquidity_supply).await,
+        1_200_000
+    );
+    assert_eq!(
+        env.token_balance(&position.borrow_reserve.liquidity_supply).await,
+        900_000
+    );
+
+    // both reserves are unlocked once the swap returns
+    assert_eq!(position.deposit_reserve.state(&mut env).await.reentrancy_lock, 0);
+    assert_eq!(position.borrow_reserve.state(&mut env).await.reentrancy_lock, 0);
+
+    // unwinding everything repays the 100_000 debt, the other 100_000 stays with the owner
+    env.advance_slots(1).await;
+    let adapter_accounts = mock_swap_accounts(
+        &mut env,
+        position.deposit_reserve.liquidity_mint,
+        position.borrow_reserve.liquidity_mint,
+        200_000,
+    )
+    .await;
+    let mut instructions = position.refresh(&mut env).await;
+    instructions.push(position.close(mock_swap_adapter::ID, adapter_accounts, u64::MAX));
+    env.process_transaction(&instructions, &[&position.owner])
+        .await
+        .unwrap();
+
+    let obligation_state: Obligation = env.zero_copy_account(&position.obligation).await;
+    assert!(obligation_state.deposits().is_empty());
+    assert!(obligation_state.borrows().is_empty());
+    assert_eq!(
+        env.token_balance(&position.user_borrow_liquidity).await,
+        100_000
+    );
+    assert_eq!(
+        env.token_balance(&position.borrow_reserve.liquidity_supply).await,
+        1_000_000
+    );
+    assert_eq!(position.deposit_reserve.state(&mut env).await.reentrancy_lock, 0);
+    assert_eq!(position.borrow_reserve.state(&mut env).await.reentrancy_lock, 0);
+}
+
+#[tokio::test]
+async fn test_leveraged_positions_lock_reserves_across_swap() {
+    let mut env = test_env().await;
+    let position = TestPosition::new(&mut env).await;
+
+    // the adapter fails unless both reserves are locked while it swaps
+    let adapter_accounts = lock_checking_swap_accounts(
+        &position,
+        mock_swap_accounts(
+            &mut env,
+            position.borrow_reserve.liquidity_mint,
+            position.deposit_reserve.liquidity_mint,
+            100_000,
+        )
+        .await,
+    );
+    let mut instructions = position.refresh(&mut env).await;
+    instructions.push(position.open(lock_checking_swap_adapter(), adapter_accounts, 20_000));
+    env.process_transaction(&instructions, &[&position.owner])
+        .await
+        .unwrap();
+    assert_eq!(position.deposit_reserve.state(&mut env).await.reentrancy_lock, 0);
+    assert_eq!(position.borrow_reserve.state(&mut env).await.reentrancy_lock, 0);
+
+    env.advance_slots(1).await;
+    let adapter_accounts = lock_checking_swap_accounts(
+        &position,
+        mock_swap_accounts(
+            &mut env,
+            position.deposit_reserve.liquidity_mint,
+            position.borrow_reserve.liquidity_mint,
+            200_000,
+        )
+        .await,
+    );
+    let mut instructions = position.refresh(&mut env).await;
+    instructions.push(position.close(lock_checking_swap_adapter(), adapter_accounts, u64::MAX));
+    env.process_transaction(&instructions, &[&position.owner])
+        .await
+        .unwrap();
+    assert_eq!(position.deposit_reserve.state(&mut env).await.reentrancy_lock, 0);
+    assert_eq!(position.borrow_reserve.state(&mut env).await.reentrancy_lock, 0);
+
+    let obligation_state: Obligation = env.zero_copy_account(&position.obligation).await;
+    assert!(obligation_state.borrows().is_empty());
+}
+
+#[tokio::test]
+async fn test_refresh_reserve_rejects_locked_reserve() {
+    let mut env = test_env().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |reserve| {
+        reserve.reentrancy_lock = 1;
+    })
+    .await;
+
+    assert_eq!(
+        env.process_transaction(&[reserve.refresh(&lending_market)], &[])
+            .await,
+        Err(lending_error(LendingError::ReserveLocked))
+    );
+}
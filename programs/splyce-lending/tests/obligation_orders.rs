@@ -0,0 +1,475 @@
+mod common;
+
+use anchor_lang::{
+    prelude::*,
+    solana_program::{instruction::Instruction, system_program},
+    InstructionData,
+};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+use splyce_lending::{
+    error::LendingError,
+    math::Decimal,
+    state::{
+        Obligation, ObligationOrder, OrderTrigger, RateLimiter, RateLimiterConfig, Reserve,
+        OBLIGATION_ORDER_SEED,
+    },
+};
+
+const KEEPER_TIP: u64 = 1_000_000;
+
+/// Obligation with 10 tokens deposited in the collateral reserve and 4 tokens borrowed from the
+/// debt reserve, both priced at 1, refreshed in the current slot
+struct TestObligation {
+    lending_market: TestLendingMarket,
+    collateral_reserve: TestReserve,
+    debt_reserve: TestReserve,
+    owner: Keypair,
+    key: Pubkey,
+}
+
+impl TestObligation {
+    async fn new(env: &mut TestEnv, configure_collateral_reserve: impl FnOnce(&mut Reserve)) -> Self {
+        let lending_market = TestLendingMarket::new(env);
+        let collateral_reserve = TestReserve::new(
+            env,
+            &lending_market,
+            None,
+            6,
+            1,
+            10_000_000,
+            configure_collateral_reserve,
+        )
+        .await;
+        let debt_reserve = TestReserve::new(env, &lending_market, None, 6, 1, 10_000_000, |reserve| {
+            reserve.liquidity.borrowed_amount_wads = Decimal::from(4_000_000u64);
+        })
+        .await;
+        let owner = env.create_wallet(1_000_000_000);
+        let source_liquidity = env
+            .create_token_account(collateral_reserve.liquidity_mint, owner.pubkey(), 10_000_000)
+            .await;
+        let key = init_obligation(env, &lending_market, &owner).await;
+        env.process_transaction(
+            &[deposit(
+                &lending_market,
+                &collateral_reserve,
+                key,
+                &owner,
+                Some(source_liquidity),
+                false,
+                10_000_000,
+            )],
+            &[&owner],
+        )
+        .await
+        .unwrap();
+
+        let mut obligation: Obligation = env.zero_copy_account(&key).await;
+        obligation
+            .find_or_add_liquidity_to_borrows(debt_reserve.key, Decimal::one())
+            .unwrap()
+            .borrow(Decimal::from(4_000_000u64))
+            .unwrap();
+        env.set_zero_copy_account(key, &obligation);
+
+        let test_obligation = Self {
+            lending_market,
+            collateral_reserve,
+            debt_reserve,
+            owner,
+            key,
+        };
+        env.process_transaction(&test_obligation.refresh(), &[])
+            .await
+            .unwrap();
+        test_obligation
+    }
+
+    fn refresh(&self) -> Vec<Instruction> {
+        vec![
+            self.collateral_reserve.refresh(&self.lending_market),
+            self.debt_reserve.refresh(&self.lending_market),
+            refresh_obligation(
+                &self.lending_market,
+                self.key,
+                &[&self.collateral_reserve],
+                &[&self.debt_reserve],
+            ),
+        ]
+    }
+
+    fn order(&self, order_id: u8) -> Pubkey {
+        Pubkey::find_program_address(
+            &[OBLIGATION_ORDER_SEED, self.key.as_ref(), &[order_id]],
+            &splyce_lending::ID,
+        )
+        .0
+    }
+
+    /// Stop-loss at 30% loan to value withdrawing half the collateral and repaying the whole borrow
+    fn init_order(&self, order_id: u8, withdraw_reserve: Pubkey, repay_reserve: Pubkey) -> Instruction {
+        Instruction {
+            program_id: splyce_lending::ID,
+            accounts: splyce_lending::accounts::InitObligationOrder {
+                obligation_order: self.order(order_id),
+                obligation: self.key,
+                withdraw_reserve,
+                repay_reserve,
+                owner: self.owner.pubkey(),
+                system_program: system_program::ID,
+            }
+            .to_account_metas(None),
+            data: splyce_lending::instruction::InitObligationOrder {
+                order_id,
+                trigger: OrderTrigger::LtvAbove,
+                trigger_value: Decimal::from_percent(30).to_scaled_val().unwrap(),
+                withdraw_collateral_amount: 5_000_000,
+                repay_liquidity_amount: u64::MAX,
+                keeper_tip: KEEPER_TIP,
+            }
+            .data(),
+        }
+    }
+
+    fn execute_order(
+        &self,
+        order_id: u8,
+        keeper: Pubkey,
+        source_liquidity: Pubkey,
+        destination_liquidity: Pubkey,
+        owner_liquidity: Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: splyce_lending::ID,
+            accounts: splyce_lending::accounts::ExecuteObligationOrder {
+                obligation_order: self.order(order_id),
+                owner: self.owner.pubkey(),
+                obligation: self.key,
+                lending_market: self.lending_market.key,
+                source_liquidity,
+                destination_liquidity,
+                owner_liquidity,
+                repay_reserve: self.debt_reserve.key,
+                repay_reserve_liquidity_supply: self.debt_reserve.liquidity_supply,
+                withdraw_reserve: self.collateral_reserve.key,
+                withdraw_reserve_collateral_mint: self.collateral_reserve.collateral_mint,
+                withdraw_reserve_collateral_supply: self.collateral_reserve.collateral_supply,
+                withdraw_reserve_liquidity_supply: self.collateral_reserve.liquidity_supply,
+                keeper,
+                token_program: spl_token::ID,
+            }
+            .to_account_metas(None),
+            data: splyce_lending::instruction::ExecuteObligationOrder {}.data(),
+        }
+    }
+
+    /// Place the stop-loss order 0 on the collateral and debt reserves
+    async fn place_order(&self, env: &mut TestEnv) {
+        env.process_transaction(
+            &[self.init_order(0, self.collateral_reserve.key, self.debt_reserve.key)],
+            &[&self.owner],
+        )
+        .await
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_init_obligation_order() {
+    let mut env = TestEnv::new().await;
+    let obligation = TestObligation::new(&mut env, |_| {}).await;
+    let owner_lamports = env.lamports(&obligation.owner.pubkey()).await;
+
+    obligation.place_order(&mut env).await;
+
+    let order: ObligationOrder = env.anchor_account(&obligation.order(0)).await;
+    assert_eq!(order.obligation, obligation.key);
+    assert_eq!(order.owner, obligation.owner.pubkey());
+    assert_eq!(order.withdraw_reserve, obligation.collateral_reserve.key);
+    assert_eq!(order.repay_reserve, obligation.debt_reserve.key);
+    assert_eq!(order.keeper_tip, KEEPER_TIP);
+
+    // the owner pays the rent and escrows the tip in the order account
+    let order_lamports = env.lamports(&obligation.order(0)).await;
+    assert_eq!(
+        order_lamports,
+        Rent::default().minimum_balance(8 + ObligationOrder::INIT_SPACE) + KEEPER_TIP
+    );
+    assert_eq!(
+        env.lamports(&obligation.owner.pubkey()).await,
+        owner_lamports - order_lamports
+    );
+}
+
+#[tokio::test]
+async fn test_init_obligation_order_rejects_reserves_outside_the_obligation() {
+    let mut env = TestEnv::new().await;
+    let obligation = TestObligation::new(&mut env, |_| {}).await;
+    let collateral_reserve = obligation.collateral_reserve.key;
+    let debt_reserve = obligation.debt_reserve.key;
+
+    // the obligation deposits only the collateral reserve and borrows only the debt reserve
+    assert_eq!(
+        env.process_transaction(
+            &[obligation.init_order(0, debt_reserve, debt_reserve)],
+            &[&obligation.owner],
+        )
+        .await,
+        Err(lending_error(LendingError::InvalidObligationCollateral))
+    );
+    assert_eq!(
+        env.process_transaction(
+            &[obligation.init_order(0, collateral_reserve, collateral_reserve)],
+            &[&obligation.owner],
+        )
+        .await,
+        Err(lending_error(LendingError::InvalidObligationLiquidity))
+    );
+
+    // reserves of another lending market
+    let other_lending_market = TestLendingMarket::new(&mut env);
+    let other_reserve =
+        TestReserve::new(&mut env, &other_lending_market, None, 6, 1, 1_000_000, |_| {}).await;
+    assert_eq!(
+        env.process_transaction(
+            &[obligation.init_order(0, other_reserve.key, debt_reserve)],
+            &[&obligation.owner],
+        )
+        .await,
+        Err(lending_error(LendingError::InvalidAccountInput))
+    );
+    assert_eq!(
+        env.process_transaction(
+            &[obligation.init_order(0, collateral_reserve, other_reserve.key)],
+            &[&obligation.owner],
+        )
+        .await,
+        Err(lending_error(LendingError::InvalidAccountInput))
+    );
+}
+
+#[tokio::test]
+async fn test_execute_obligation_order_pays_the_keeper_the_repaid_value_and_tip() {
+    let mut env = TestEnv::new().await;
+    let obligation = TestObligation::new(&mut env, |_| {}).await;
+    obligation.place_order(&mut env).await;
+
+    let keeper = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(obligation.debt_reserve.liquidity_mint, keeper.pubkey(), 5_000_000)
+        .await;
+    let destination_liquidity = env
+        .create_token_account(obligation.collateral_reserve.liquidity_mint, keeper.pubkey(), 0)
+        .await;
+    let owner_liquidity = env
+        .create_token_account(
+            obligation.collateral_reserve.liquidity_mint,
+            obligation.owner.pubkey(),
+            0,
+        )
+        .await;
+    let owner_lamports = env.lamports(&obligation.owner.pubkey()).await;
+    let order_lamports = env.lamports(&obligation.order(0)).await;
+
+    let mut instructions = obligation.refresh();
+    instructions.push(obligation.execute_order(
+        0,
+        keeper.pubkey(),
+        source_liquidity,
+        destination_liquidity,
+        owner_liquidity,
+    ));
+    env.process_transaction(&instructions, &[&keeper])
+        .await
+        .unwrap();
+
+    // the keeper repays the 4 token borrow and takes back 4 tokens of the 5 withdrawn, the value
+    // it repaid and nothing more, the owner receives the rest
+    assert_eq!(env.token_balance(&source_liquidity).await, 1_000_000);
+    assert_eq!(env.token_balance(&destination_liquidity).await, 4_000_000);
+    assert_eq!(env.token_balance(&owner_liquidity).await, 1_000_000);
+    // on top of which the keeper only gets the tip, the owner gets the rent back
+    assert_eq!(
+        env.lamports(&keeper.pubkey()).await,
+        1_000_000_000 + KEEPER_TIP
+    );
+    assert_eq!(
+        env.lamports(&obligation.owner.pubkey()).await,
+        owner_lamports + order_lamports - KEEPER_TIP
+    );
+    assert!(env.account(&obligation.order(0)).await.is_none());
+
+    let obligation_state: Obligation = env.zero_copy_account(&obligation.key).await;
+    assert!(obligation_state.borrows().is_empty());
+    assert_eq!(obligation_state.deposits()[0].deposited_amount, 5_000_000);
+    // no borrow value is left to attribute to the collateral
+    assert_eq!(
+        obligation_state.deposits()[0].attributed_borrow_value,
+        Decimal::zero()
+    );
+    assert_eq!(
+        obligation.collateral_reserve.state(&mut env).await.attributed_borrow_value,
+        Decimal::zero()
+    );
+}
+
+#[tokio::test]
+async fn test_execute_obligation_order_rejects_untriggered_order() {
+    let mut env = TestEnv::new().await;
+    let obligation = TestObligation::new(&mut env, |_| {}).await;
+    // stop-loss at 50% loan to value, the obligation is at 40%
+    let mut init_order =
+        obligation.init_order(0, obligation.collateral_reserve.key, obligation.debt_reserve.key);
+    init_order.data = splyce_lending::instruction::InitObligationOrder {
+        order_id: 0,
+        trigger: OrderTrigger::LtvAbove,
+        trigger_value: Decimal::from_percent(50).to_scaled_val().unwrap(),
+        withdraw_collateral_amount: 5_000_000,
+        repay_liquidity_amount: u64::MAX,
+        keeper_tip: KEEPER_TIP,
+    }
+    .data();
+    env.process_transaction(&[init_order], &[&obligation.owner])
+        .await
+        .unwrap();
+
+    let keeper = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(obligation.debt_reserve.liquidity_mint, keeper.pubkey(), 5_000_000)
+        .await;
+    let destination_liquidity = env
+        .create_token_account(obligation.collateral_reserve.liquidity_mint, keeper.pubkey(), 0)
+        .await;
+    let owner_liquidity = env
+        .create_token_account(
+            obligation.collateral_reserve.liquidity_mint,
+            obligation.owner.pubkey(),
+            0,
+        )
+        .await;
+
+    let mut instructions = obligation.refresh();
+    instructions.push(obligation.execute_order(
+        0,
+        keeper.pubkey(),
+        source_liquidity,
+        destination_liquidity,
+        owner_liquidity,
+    ));
+    assert_eq!(
+        env.process_transaction(&instructions, &[&keeper]).await,
+        Err(lending_error(LendingError::ObligationOrderNotTriggered))
+    );
+}
+
+#[tokio::test]
+async fn test_execute_obligation_order_is_rate_limited() {
+    let mut env = TestEnv::new().await;
+    let slot = env.slot().await;
+    let obligation = TestObligation::new(&mut env, |reserve| {
+        reserve.rate_limiter = RateLimiter::new(
+            RateLimiterConfig {
+                window_duration: 10,
+                max_outflow: 1_000_000,
+            },
+            slot,
+        );
+    })
+    .await;
+    obligation.place_order(&mut env).await;
+
+    let keeper = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(obligation.debt_reserve.liquidity_mint, keeper.pubkey(), 5_000_000)
+        .await;
+    let destination_liquidity = env
+        .create_token_account(obligation.collateral_reserve.liquidity_mint, keeper.pubkey(), 0)
+        .await;
+    let owner_liquidity = env
+        .create_token_account(
+            obligation.collateral_reserve.liquidity_mint,
+            obligation.owner.pubkey(),
+            0,
+        )
+        .await;
+
+    let mut instructions = obligation.refresh();
+    instructions.push(obligation.execute_order(
+        0,
+        keeper.pubkey(),
+        source_liquidity,
+        destination_liquidity,
+        owner_liquidity,
+    ));
+    assert_eq!(
+        env.process_transaction(&instructions, &[&keeper]).await,
+        Err(lending_error(LendingError::OutflowRateLimitExceeded))
+    );
+}
+
+#[tokio::test]
+async fn test_execute_obligation_order_rejects_owner_liquidity_of_someone_else() {
+    let mut env = TestEnv::new().await;
+    let obligation = TestObligation::new(&mut env, |_| {}).await;
+    obligation.place_order(&mut env).await;
+
+    let keeper = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(obligation.debt_reserve.liquidity_mint, keeper.pubkey(), 5_000_000)
+        .await;
+    let destination_liquidity = env
+        .create_token_account(obligation.collateral_reserve.liquidity_mint, keeper.pubkey(), 0)
+        .await;
+
+    let mut instructions = obligation.refresh();
+    instructions.push(obligation.execute_order(
+        0,
+        keeper.pubkey(),
+        source_liquidity,
+        destination_liquidity,
+        destination_liquidity,
+    ));
+    assert_eq!(
+        env.process_transaction(&instructions, &[&keeper]).await,
+        Err(lending_error(LendingError::InvalidAccountInput))
+    );
+}
+
+#[tokio::test]
+async fn test_cancel_obligation_order() {
+    let mut env = TestEnv::new().await;
+    let obligation = TestObligation::new(&mut env, |_| {}).await;
+    obligation.place_order(&mut env).await;
+    let order_lamports = env.lamports(&obligation.order(0)).await;
+    let owner_lamports = env.lamports(&obligation.owner.pubkey()).await;
+
+    let cancel = |owner: Pubkey| Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::CancelObligationOrder {
+            obligation_order: obligation.order(0),
+            owner,
+        }
+        .to_account_metas(None),
+        data: splyce_lending::instruction::CancelObligationOrder {}.data(),
+    };
+
+    // only the owner can cancel the order
+    let stranger = env.create_wallet(1_000_000_000);
+    assert_eq!(
+        env.process_transaction(&[cancel(stranger.pubkey())], &[&stranger])
+            .await,
+        Err(lending_error(LendingError::InvalidObligationOwner))
+    );
+
+    // the rent and the escrowed tip go back to the owner
+    env.process_transaction(&[cancel(obligation.owner.pubkey())], &[&obligation.owner])
+        .await
+        .unwrap();
+    assert!(env.account(&obligation.order(0)).await.is_none());
+    assert_eq!(
+        env.lamports(&obligation.owner.pubkey()).await,
+        owner_lamports + order_lamports
+    );
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use anchor_lang::{prelude::*, solana_program::instruction::Instruction, InstructionData};
+use common::*;
+use solana_sdk::signature::Signer;
+use splyce_lending::{error::LendingError, math::Decimal, state::Obligation};
+
+fn socialize_bad_debt(
+    lending_market: &TestLendingMarket,
+    obligation: Pubkey,
+    reserve: &TestReserve,
+) -> Instruction {
+    Instruction {
+        program_id: splyce_lending::ID,
+        accounts: splyce_lending::accounts::SocializeBadDebt {
+            obligation,
+            lending_market: lending_market.key,
+            reserve: reserve.key,
+        }
+        .to_account_metas(None),
+        data: splyce_lending::instruction::SocializeBadDebt {}.data(),
+    }
+}
+
+/// Add a `borrowed_amount` borrow of `reserve` to the obligation
+async fn add_borrow(
+    env: &mut TestEnv,
+    obligation: Pubkey,
+    reserve: &TestReserve,
+    borrowed_amount: u64,
+) {
+    let mut obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    obligation_state
+        .find_or_add_liquidity_to_borrows(reserve.key, Decimal::one())
+        .unwrap()
+        .borrow(Decimal::from(borrowed_amount))
+        .unwrap();
+    env.set_zero_copy_account(obligation, &obligation_state);
+}
+
+#[tokio::test]
+async fn test_socialize_bad_debt() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |reserve| {
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(500_000u64);
+        reserve.liquidity.accumulated_protocol_fees_wads = Decimal::from(100_000u64);
+    })
+    .await;
+    let owner = env.create_wallet(1_000_000_000);
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    add_borrow(&mut env, obligation, &reserve, 300_000).await;
+
+    // permissionless, the instruction only needs the refreshed reserve
+    env.process_transaction(
+        &[
+            reserve.refresh(&lending_market),
+            socialize_bad_debt(&lending_market, obligation, &reserve),
+        ],
+        &[],
+    )
+    .await
+    .unwrap();
+
+    // the protocol fees cover 100_000, the other 200_000 is taken from the depositors
+    let reserve_state = reserve.state(&mut env).await;
+    assert_eq!(
+        reserve_state.liquidity.borrowed_amount_wads,
+        Decimal::from(200_000u64)
+    );
+    assert_eq!(
+        reserve_state.liquidity.accumulated_protocol_fees_wads,
+        Decimal::zero()
+    );
+    assert_eq!(
+        reserve_state.liquidity.total_supply().unwrap(),
+        Decimal::from(1_200_000u64)
+    );
+
+    let obligation_state: Obligation = env.zero_copy_account(&obligation).await;
+    assert!(obligation_state.borrows().is_empty());
+}
+
+#[tokio::test]
+async fn test_socialize_bad_debt_rejects_obligation_with_deposits() {
+    let mut env = TestEnv::new().await;
+    let lending_market = TestLendingMarket::new(&mut env);
+    let reserve = TestReserve::new(&mut env, &lending_market, None, 6, 1, 1_000_000, |reserve| {
+        reserve.liquidity.borrowed_amount_wads = Decimal::from(500_000u64);
+    })
+    .await;
+    let owner = env.create_wallet(1_000_000_000);
+    let source_liquidity = env
+        .create_token_account(reserve.liquidity_mint, owner.pubkey(), 100_000)
+        .await;
+    let obligation = init_obligation(&mut env, &lending_market, &owner).await;
+    env.process_transaction(
+        &[deposit(
+            &lending_market,
+            &reserve,
+            obligation,
+            &owner,
+            Some(source_liquidity),
+            false,
+            100_000,
+        )],
+        &[&owner],
+    )
+    .await
+    .unwrap();
+    add_borrow(&mut env, obligation, &reserve, 300_000).await;
+
+    // the collateral must be liquidated first
+    assert_eq!(
+        env.process_transaction(
+            &[
+                reserve.refresh(&lending_market),
+                socialize_bad_debt(&lending_market, obligation, &reserve),
+            ],
+            &[],
+        )
+        .await,
+        Err(lending_error(LendingError::ObligationDepositsNotEmpty))
+    );
+    assert_eq!(
+        reserve.state(&mut env).await.liquidity.borrowed_amount_wads,
+        Decimal::from(500_000u64)
+    );
+}
@@ -3,6 +3,28 @@ import { Program } from "@coral-xyz/anchor";
 import { SplyceLending } from "../target/types/splyce_lending";
 import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
 import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import { assert } from "chai";
+
+// Lending errors are returned as custom program errors, not as Anchor errors from the IDL, so
+// they are matched by their code in the failed transaction
+const INVALID_CONFIG = 0xb;
+const INVALID_OBLIGATION_OWNER = 0x20;
+// Anchor error of accounts that do not exist
+const ACCOUNT_NOT_INITIALIZED = 3012;
+
+async function expectLendingError(transaction: Promise<unknown>, code: number) {
+  try {
+    await transaction;
+  } catch (err) {
+    const logs: string[] = err.logs ?? [];
+    assert.include(
+      [String(err), ...logs].join("\n"),
+      `custom program error: 0x${code.toString(16)}`
+    );
+    return;
+  }
+  assert.fail(`Expected custom program error 0x${code.toString(16)}`);
+}
 
 describe("splyce-lending", () => {
   // Configure the client to use the local cluster.
@@ -11,6 +33,9 @@ describe("splyce-lending", () => {
 
   const program = anchor.workspace.SplyceLending as Program<SplyceLending>;
 
+  // Any unused key, the obligation address does not depend on its owner
+  const obligationSeed = Keypair.generate().publicKey;
+
   it("Init_lending_market", async () => {
 
     // Set quote currency to "USD" padded with null bytes (32 bytes total)
@@ -46,4 +71,403 @@ describe("splyce-lending", () => {
     );
     console.log("Lending Market Account:", lendingMarketAccount);
   });
-});
\ No newline at end of file
+
+  it("Set_liquidation_config", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // Liquidate at most 20% of a borrow per call, borrows worth 1 USD or less in full, with the
+    // bonus rising to its max over ~1 minute of slots
+    const tx = await program.methods
+      .setLiquidationConfig(20, new anchor.BN(1), new anchor.BN(150))
+      .accounts({
+        lendingMarket: lendingMarketPDA,
+        signer: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(tx);
+
+    const lendingMarketAccount = await program.account.lendingMarket.fetch(
+      lendingMarketPDA
+    );
+    assert.equal(lendingMarketAccount.liquidationCloseFactor, 20);
+    assert.equal(lendingMarketAccount.liquidationDustThreshold.toNumber(), 1);
+    assert.equal(lendingMarketAccount.liquidationAuctionSlots.toNumber(), 150);
+
+    // A zero close factor would make every liquidation too small
+    await expectLendingError(
+      program.methods
+        .setLiquidationConfig(0, new anchor.BN(1), new anchor.BN(150))
+        .accounts({
+          lendingMarket: lendingMarketPDA,
+          signer: provider.wallet.publicKey,
+        })
+        .rpc(),
+      INVALID_CONFIG
+    );
+  });
+
+  it("Set_oracle_config", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // Refresh reserves with prices at most 120 slots old and within 5% confidence
+    const tx = await program.methods
+      .setOracleConfig(new anchor.BN(120), 5)
+      .accounts({
+        lendingMarket: lendingMarketPDA,
+        signer: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(tx);
+
+    const lendingMarketAccount = await program.account.lendingMarket.fetch(
+      lendingMarketPDA
+    );
+    assert.equal(lendingMarketAccount.oracleMaxStalenessSlots.toNumber(), 120);
+    assert.equal(lendingMarketAccount.oracleMaxConfidencePct, 5);
+
+    // A zero staleness limit would reject every price
+    await expectLendingError(
+      program.methods
+        .setOracleConfig(new anchor.BN(0), 5)
+        .accounts({
+          lendingMarket: lendingMarketPDA,
+          signer: provider.wallet.publicKey,
+        })
+        .rpc(),
+      INVALID_CONFIG
+    );
+  });
+
+  it("Set_emode_category", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // Category 1: USD stablecoins with elevated LTV and liquidation threshold
+    const label = Buffer.from("USD stablecoins" + "\0".repeat(17), "utf-8");
+
+    const tx = await program.methods
+      .setEmodeCategory(1, {
+        loanToValueRatio: 90,
+        liquidationThreshold: 95,
+        label: [...label],
+      })
+      .accounts({
+        lendingMarket: lendingMarketPDA,
+        signer: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(tx);
+
+    const lendingMarketAccount = await program.account.lendingMarket.fetch(
+      lendingMarketPDA
+    );
+    const category = lendingMarketAccount.emodeCategories[0];
+    assert.equal(category.loanToValueRatio, 90);
+    assert.equal(category.liquidationThreshold, 95);
+    assert.deepEqual(category.label, [...label]);
+
+    // The liquidation threshold cannot be below the loan to value ratio
+    await expectLendingError(
+      program.methods
+        .setEmodeCategory(2, {
+          loanToValueRatio: 90,
+          liquidationThreshold: 85,
+          label: [...label],
+        })
+        .accounts({
+          lendingMarket: lendingMarketPDA,
+          signer: provider.wallet.publicKey,
+        })
+        .rpc(),
+      INVALID_CONFIG
+    );
+  });
+
+
+  it("Init_obligation", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // Derive the PDA for the obligation from the lending market and its seed
+    const [obligationPDA] = await PublicKey.findProgramAddress(
+      [lendingMarketPDA.toBuffer(), obligationSeed.toBuffer()],
+      program.programId
+    );
+
+    const tx = await program.methods
+      .initObligation(obligationSeed)
+      .accounts({
+        obligation: obligationPDA,
+        lendingMarket: lendingMarketPDA,
+        owner: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    assert.ok(tx);
+
+    const obligationAccount = await program.account.obligation.fetch(
+      obligationPDA
+    );
+    assert.ok(obligationAccount.owner.equals(provider.wallet.publicKey));
+    assert.ok(obligationAccount.lendingMarket.equals(lendingMarketPDA));
+    assert.ok(obligationAccount.seed.equals(obligationSeed));
+    assert.ok(obligationAccount.pendingOwner.equals(PublicKey.default));
+    assert.equal(obligationAccount.depositsLen, 0);
+    assert.equal(obligationAccount.borrowsLen, 0);
+    assert.equal(obligationAccount.emodeCategory, 0);
+  });
+
+  it("Set_obligation_emode_category", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const [obligationPDA] = await PublicKey.findProgramAddress(
+      [lendingMarketPDA.toBuffer(), obligationSeed.toBuffer()],
+      program.programId
+    );
+
+    // The obligation holds no reserves yet, so any configured category is accepted
+    const tx = await program.methods
+      .setObligationEmodeCategory(1)
+      .accounts({
+        obligation: obligationPDA,
+        lendingMarket: lendingMarketPDA,
+        owner: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(tx);
+
+    const obligationAccount = await program.account.obligation.fetch(
+      obligationPDA
+    );
+    assert.equal(obligationAccount.emodeCategory, 1);
+
+    // Category 2 was rejected above and is not configured
+    await expectLendingError(
+      program.methods
+        .setObligationEmodeCategory(2)
+        .accounts({
+          obligation: obligationPDA,
+          lendingMarket: lendingMarketPDA,
+          owner: provider.wallet.publicKey,
+        })
+        .rpc(),
+      INVALID_CONFIG
+    );
+  });
+
+  it("Set_obligation_delegate", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const [obligationPDA] = await PublicKey.findProgramAddress(
+      [lendingMarketPDA.toBuffer(), obligationSeed.toBuffer()],
+      program.programId
+    );
+
+    // Allow an automation key to deposit and repay, but never withdraw or borrow
+    const delegate = Keypair.generate().publicKey;
+    const DEPOSIT = 1 << 0;
+    const REPAY = 1 << 1;
+
+    const tx = await program.methods
+      .setObligationDelegate(delegate, DEPOSIT | REPAY, new anchor.BN(0))
+      .accounts({
+        obligation: obligationPDA,
+        owner: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(tx);
+
+    const obligationAccount = await program.account.obligation.fetch(
+      obligationPDA
+    );
+    assert.equal(obligationAccount.delegatesLen, 1);
+    assert.ok(obligationAccount.delegates[0].delegate.equals(delegate));
+    assert.equal(obligationAccount.delegates[0].permissions, DEPOSIT | REPAY);
+    assert.equal(obligationAccount.delegates[0].expirySlot.toNumber(), 0);
+
+    // Permission flags outside of deposit, repay, withdraw and borrow are rejected
+    await expectLendingError(
+      program.methods
+        .setObligationDelegate(delegate, 1 << 4, new anchor.BN(0))
+        .accounts({
+          obligation: obligationPDA,
+          owner: provider.wallet.publicKey,
+        })
+        .rpc(),
+      INVALID_CONFIG
+    );
+  });
+
+  it("Init_obligation_order", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const [obligationPDA] = await PublicKey.findProgramAddress(
+      [lendingMarketPDA.toBuffer(), obligationSeed.toBuffer()],
+      program.programId
+    );
+
+    const orderId = 0;
+    const [obligationOrderPDA] = await PublicKey.findProgramAddress(
+      [
+        Buffer.from("obligation_order"),
+        obligationPDA.toBuffer(),
+        Buffer.from([orderId]),
+      ],
+      program.programId
+    );
+
+    // Stop-loss: once the loan to value reaches 80%, withdraw all of the collateral and repay
+    // the whole borrow, tipping the keeper 0.001 SOL. The order reserves must be reserves the
+    // obligation deposits and borrows, the executed order flow is covered by the program tests.
+    const WAD = new anchor.BN("1000000000000000000");
+    const U64_MAX = new anchor.BN("18446744073709551615");
+
+    await expectLendingError(
+      program.methods
+        .initObligationOrder(
+          orderId,
+          { ltvAbove: {} },
+          WAD.muln(80).divn(100),
+          U64_MAX,
+          U64_MAX,
+          new anchor.BN(1_000_000)
+        )
+        .accounts({
+          obligationOrder: obligationOrderPDA,
+          obligation: obligationPDA,
+          withdrawReserve: Keypair.generate().publicKey,
+          repayReserve: Keypair.generate().publicKey,
+          owner: provider.wallet.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .rpc(),
+      ACCOUNT_NOT_INITIALIZED
+    );
+    assert.isNull(await provider.connection.getAccountInfo(obligationOrderPDA));
+  });
+
+  it("Transfer_obligation", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    const [obligationPDA] = await PublicKey.findProgramAddress(
+      [lendingMarketPDA.toBuffer(), obligationSeed.toBuffer()],
+      program.programId
+    );
+
+    const newOwner = Keypair.generate();
+
+    const proposeTx = await program.methods
+      .transferObligation(newOwner.publicKey)
+      .accounts({
+        obligation: obligationPDA,
+        owner: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(proposeTx);
+
+    let obligationAccount = await program.account.obligation.fetch(
+      obligationPDA
+    );
+    assert.ok(obligationAccount.owner.equals(provider.wallet.publicKey));
+    assert.ok(obligationAccount.pendingOwner.equals(newOwner.publicKey));
+
+    // Only the proposed owner can accept the transfer
+    const stranger = Keypair.generate();
+    await expectLendingError(
+      program.methods
+        .acceptObligationTransfer()
+        .accounts({
+          obligation: obligationPDA,
+          newOwner: stranger.publicKey,
+        })
+        .signers([stranger])
+        .rpc(),
+      INVALID_OBLIGATION_OWNER
+    );
+
+    const acceptTx = await program.methods
+      .acceptObligationTransfer()
+      .accounts({
+        obligation: obligationPDA,
+        newOwner: newOwner.publicKey,
+      })
+      .signers([newOwner])
+      .rpc();
+
+    assert.ok(acceptTx);
+
+    obligationAccount = await program.account.obligation.fetch(obligationPDA);
+    assert.ok(obligationAccount.owner.equals(newOwner.publicKey));
+    assert.ok(obligationAccount.pendingOwner.equals(PublicKey.default));
+    // Delegates granted by the previous owner do not carry over
+    assert.equal(obligationAccount.delegatesLen, 0);
+  });
+
+  it("Close_obligation", async () => {
+    const [lendingMarketPDA] = await PublicKey.findProgramAddress(
+      [provider.wallet.publicKey.toBuffer()],
+      program.programId
+    );
+
+    // The first obligation was transferred away, open an empty one to close
+    const closeSeed = Keypair.generate().publicKey;
+    const [obligationPDA] = await PublicKey.findProgramAddress(
+      [lendingMarketPDA.toBuffer(), closeSeed.toBuffer()],
+      program.programId
+    );
+
+    const initTx = await program.methods
+      .initObligation(closeSeed)
+      .accounts({
+        obligation: obligationPDA,
+        lendingMarket: lendingMarketPDA,
+        owner: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+    assert.ok(initTx);
+
+    const closeTx = await program.methods
+      .closeObligation()
+      .accounts({
+        obligation: obligationPDA,
+        owner: provider.wallet.publicKey,
+      })
+      .rpc();
+
+    assert.ok(closeTx);
+
+    const obligationInfo = await provider.connection.getAccountInfo(obligationPDA);
+    assert.isNull(obligationInfo);
+  });
+});